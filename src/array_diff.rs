@@ -0,0 +1,906 @@
+//! Element-wise comparison of arrays
+//!
+//! Arrays are compared with one of these algorithms:
+//!
+//! - Positional: the elements at each index are compared, and elements past the end of the
+//!   shorter array are reported as added or removed. Unequal nested arrays at the same index are
+//!   compared positionally as well.
+//! - Set: the arrays are compared as multisets, so the order of the elements is ignored, and
+//!   elements occurring more often in one of the arrays are reported as added or removed.
+//! - LCS: the elements are aligned on a longest common subsequence of their values, and the
+//!   elements outside of it are reported as added or removed.
+//! - Keyed: arrays of tables are aligned by the value of an identity field in each table, using a
+//!   longest common subsequence over the identities. Elements that are only on one side are
+//!   reported as added or removed, elements that are out of order are reported as moved, and the
+//!   aligned pairs are compared field by field.
+
+use anyhow::{anyhow, Error};
+use itertools::Itertools;
+
+use crate::key_handling::Key;
+use crate::parse;
+use crate::value_handling::ValueComparator;
+
+/// Key of an array of tables, and the field identifying the tables in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentitySpec {
+    pub key: Key,
+    pub field: String,
+}
+
+/// Algorithm used to compare the elements of two arrays
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Algorithm {
+    /// Compare the elements at equal indices
+    #[default]
+    Positional,
+    /// Compare the arrays as multisets, ignoring the order of the elements
+    Set,
+    /// Align the elements on a longest common subsequence of their values
+    Lcs,
+    /// Align arrays of tables on the value of the given identity field
+    Keyed(String),
+}
+
+impl Algorithm {
+    /// Parse an algorithm given on the command line, like `lcs` or `keyed:name`
+    pub fn from_arg(val: &str) -> Result<Self, Error> {
+        match val.split_once(':') {
+            Some(("keyed", field)) if !field.is_empty() => {
+                Ok(Algorithm::Keyed(String::from(field)))
+            }
+            None if val == "positional" => Ok(Algorithm::Positional),
+            None if val == "set" => Ok(Algorithm::Set),
+            None if val == "lcs" => Ok(Algorithm::Lcs),
+            _ => Err(anyhow!(
+                "Invalid array algorithm '{}', expected 'positional', 'set', 'lcs' or \
+                 'keyed:field'",
+                val
+            )),
+        }
+    }
+
+    /// The identity field of the keyed algorithm
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Algorithm::Keyed(field) => Some(field),
+            _ => None,
+        }
+    }
+}
+
+/// A single difference between two arrays
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayChange {
+    /// Element only in the second array, at the given index. With the keyed algorithm, the
+    /// identity is the value of the identity field, and is missing if the element does not have
+    /// it. With the other algorithms, the identity is the element itself.
+    Added {
+        index: usize,
+        id: Option<toml::Value>,
+    },
+    /// Element only in the first array, at the given index
+    Removed {
+        index: usize,
+        id: Option<toml::Value>,
+    },
+    /// Element in both arrays, but outside of the common ordering
+    Moved {
+        from: usize,
+        to: usize,
+        id: toml::Value,
+    },
+    /// Field of an element in both arrays with unequal values. A missing value means that the
+    /// field is not present in that element.
+    Changed {
+        id: toml::Value,
+        field: Key,
+        first: Option<toml::Value>,
+        second: Option<toml::Value>,
+    },
+    /// Unequal elements at the same index, from the positional algorithm
+    Replaced {
+        index: usize,
+        first: toml::Value,
+        second: toml::Value,
+    },
+    /// Unequal arrays at the same index, from the positional algorithm, with the changes of their
+    /// elements
+    Nested {
+        index: usize,
+        changes: Vec<ArrayChange>,
+    },
+}
+
+/// Parse identity specifications on the form "key1.key2=field", separated by commas
+pub fn parse_specs(specstr: &str) -> Result<Vec<IdentitySpec>, Error> {
+    let mut specs = Vec::<IdentitySpec>::new();
+    for spec in specstr.split(',') {
+        match spec.split_once('=') {
+            Some((key, field)) if !key.is_empty() && !field.is_empty() => {
+                specs.push(IdentitySpec {
+                    key: key.split('.').map(String::from).collect(),
+                    field: String::from(field),
+                });
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Invalid array identity '{}', expected 'key=field'",
+                    spec
+                ))
+            }
+        }
+    }
+    Ok(specs)
+}
+
+/// Find the identity field to use for a key, if any
+pub fn spec_for<'a>(specs: &'a [IdentitySpec], key: &[String]) -> Option<&'a IdentitySpec> {
+    specs.iter().find(|spec| spec.key == key)
+}
+
+/// Identities of the elements in an array of tables. Returns None if any element is not a table.
+fn identities(array: &[toml::Value], field: &str) -> Option<Vec<Option<toml::Value>>> {
+    array
+        .iter()
+        .map(|element| match element {
+            toml::Value::Table(table) => Some(table.get(field).cloned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Index pairs of the longest common subsequence of two sequences with the given lengths, where
+/// `matches(i, j)` tells if element i of the first sequence matches element j of the second
+fn lcs(
+    first_len: usize,
+    second_len: usize,
+    matches: impl Fn(usize, usize) -> bool,
+) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the LCS of first[i..] and second[j..]
+    let mut lengths = vec![vec![0usize; second_len + 1]; first_len + 1];
+    for i in (0..first_len).rev() {
+        for j in (0..second_len).rev() {
+            lengths[i][j] = if matches(i, j) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::<(usize, usize)>::new();
+    let (mut i, mut j) = (0, 0);
+    while i < first_len && j < second_len {
+        if matches(i, j) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// Compare the fields of two matched tables, with the key of the array
+fn compare_fields(
+    id: &toml::Value,
+    first: &toml::Value,
+    second: &toml::Value,
+    key: &[String],
+    comparison: &dyn ValueComparator,
+    changes: &mut Vec<ArrayChange>,
+) {
+    let first_fields = parse::flatten_value(first.clone());
+    let second_fields = parse::flatten_value(second.clone());
+
+    let fields = first_fields
+        .keys()
+        .chain(second_fields.keys())
+        .unique()
+        .sorted();
+    for field in fields {
+        let first_val = first_fields.get(field);
+        let second_val = second_fields.get(field);
+        let equal = match (first_val, second_val) {
+            (Some(first_val), Some(second_val)) => comparison.equal(key, first_val, second_val),
+            _ => false,
+        };
+        if !equal {
+            changes.push(ArrayChange::Changed {
+                id: id.clone(),
+                field: field.clone(),
+                first: first_val.cloned(),
+                second: second_val.cloned(),
+            });
+        }
+    }
+}
+
+/// Compare two arrays of tables, matching the tables on the given identity field, and comparing
+/// their fields with the key of the arrays
+///
+/// Returns None if any of the values is not an array of tables. Otherwise, removed elements are
+/// listed first, then added elements, then moved elements, and finally changed fields, each in
+/// array order.
+pub fn diff_arrays(
+    first: &toml::Value,
+    second: &toml::Value,
+    field: &str,
+    key: &[String],
+    comparison: &dyn ValueComparator,
+) -> Option<Vec<ArrayChange>> {
+    let (first, second) = match (first, second) {
+        (toml::Value::Array(first), toml::Value::Array(second)) => (first, second),
+        _ => return None,
+    };
+    let first_ids = identities(first, field)?;
+    let second_ids = identities(second, field)?;
+
+    // Elements without an identity never match anything
+    let mut pairs = lcs(first.len(), second.len(), |i, j| {
+        first_ids[i].is_some() && first_ids[i] == second_ids[j]
+    });
+    let mut first_matched: Vec<bool> = vec![false; first.len()];
+    let mut second_matched: Vec<bool> = vec![false; second.len()];
+    for &(i, j) in pairs.iter() {
+        first_matched[i] = true;
+        second_matched[j] = true;
+    }
+
+    // Elements with equal identities outside of the common subsequence have been reordered
+    let mut moved = Vec::<(usize, usize)>::new();
+    for i in 0..first.len() {
+        if first_matched[i] || first_ids[i].is_none() {
+            continue;
+        }
+        let candidate =
+            (0..second.len()).find(|&j| !second_matched[j] && second_ids[j] == first_ids[i]);
+        if let Some(j) = candidate {
+            first_matched[i] = true;
+            second_matched[j] = true;
+            moved.push((i, j));
+        }
+    }
+
+    // Only elements with an identity are matched
+    let id_of = |index: usize| match &first_ids[index] {
+        Some(id) => id.clone(),
+        None => unreachable!(),
+    };
+
+    let mut changes = Vec::<ArrayChange>::new();
+    for (i, _) in first_matched.iter().enumerate().filter(|(_, m)| !**m) {
+        changes.push(ArrayChange::Removed {
+            index: i,
+            id: first_ids[i].clone(),
+        });
+    }
+    for (j, _) in second_matched.iter().enumerate().filter(|(_, m)| !**m) {
+        changes.push(ArrayChange::Added {
+            index: j,
+            id: second_ids[j].clone(),
+        });
+    }
+    for &(i, j) in moved.iter() {
+        changes.push(ArrayChange::Moved {
+            from: i,
+            to: j,
+            id: id_of(i),
+        });
+    }
+
+    pairs.extend(moved);
+    pairs.sort_unstable();
+    for (i, j) in pairs {
+        compare_fields(
+            &id_of(i),
+            &first[i],
+            &second[j],
+            key,
+            comparison,
+            &mut changes,
+        );
+    }
+
+    Some(changes)
+}
+
+/// Compare two arrays element by element with the positional algorithm, where nested arrays are
+/// compared element by element too
+fn diff_positional(
+    first: &[toml::Value],
+    second: &[toml::Value],
+    key: &[String],
+    comparison: &dyn ValueComparator,
+) -> Vec<ArrayChange> {
+    let unequal = |first_val, second_val| !comparison.equal(key, first_val, second_val);
+    let mut changes = Vec::<ArrayChange>::new();
+    for index in 0..first.len().max(second.len()) {
+        match (first.get(index), second.get(index)) {
+            (
+                Some(first_val @ toml::Value::Array(first_arr)),
+                Some(second_val @ toml::Value::Array(second_arr)),
+            ) if unequal(first_val, second_val) => changes.push(ArrayChange::Nested {
+                index,
+                changes: diff_positional(first_arr, second_arr, key, comparison),
+            }),
+            (Some(first_val), Some(second_val)) if unequal(first_val, second_val) => {
+                changes.push(ArrayChange::Replaced {
+                    index,
+                    first: first_val.clone(),
+                    second: second_val.clone(),
+                })
+            }
+            (Some(first_val), None) => changes.push(ArrayChange::Removed {
+                index,
+                id: Some(first_val.clone()),
+            }),
+            (None, Some(second_val)) => changes.push(ArrayChange::Added {
+                index,
+                id: Some(second_val.clone()),
+            }),
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Compare two arrays as multisets, listing removed elements before added elements
+fn diff_set(
+    first: &[toml::Value],
+    second: &[toml::Value],
+    key: &[String],
+    comparison: &dyn ValueComparator,
+) -> Vec<ArrayChange> {
+    let mut second_matched: Vec<bool> = vec![false; second.len()];
+    let mut changes = Vec::<ArrayChange>::new();
+    for (i, element) in first.iter().enumerate() {
+        let candidate = (0..second.len())
+            .find(|&j| !second_matched[j] && comparison.equal(key, element, &second[j]));
+        match candidate {
+            Some(j) => second_matched[j] = true,
+            None => changes.push(ArrayChange::Removed {
+                index: i,
+                id: Some(element.clone()),
+            }),
+        }
+    }
+    for (j, _) in second_matched.iter().enumerate().filter(|(_, m)| !**m) {
+        changes.push(ArrayChange::Added {
+            index: j,
+            id: Some(second[j].clone()),
+        });
+    }
+    changes
+}
+
+/// Compare two arrays aligned on a longest common subsequence of their elements, listing removed
+/// elements before added elements
+fn diff_lcs(
+    first: &[toml::Value],
+    second: &[toml::Value],
+    key: &[String],
+    comparison: &dyn ValueComparator,
+) -> Vec<ArrayChange> {
+    let pairs = lcs(first.len(), second.len(), |i, j| {
+        comparison.equal(key, &first[i], &second[j])
+    });
+
+    let mut changes = Vec::<ArrayChange>::new();
+    for (i, element) in first.iter().enumerate() {
+        if !pairs.iter().any(|&(matched, _)| matched == i) {
+            changes.push(ArrayChange::Removed {
+                index: i,
+                id: Some(element.clone()),
+            });
+        }
+    }
+    for (j, element) in second.iter().enumerate() {
+        if !pairs.iter().any(|&(_, matched)| matched == j) {
+            changes.push(ArrayChange::Added {
+                index: j,
+                id: Some(element.clone()),
+            });
+        }
+    }
+    changes
+}
+
+/// Compare two arrays with the given algorithm, where the elements are compared with the key of
+/// the arrays
+///
+/// Returns None if any of the values is not an array, or, with the keyed algorithm, not an array
+/// of tables.
+pub fn diff(
+    algorithm: &Algorithm,
+    key: &[String],
+    first: &toml::Value,
+    second: &toml::Value,
+    comparison: &dyn ValueComparator,
+) -> Option<Vec<ArrayChange>> {
+    let (first_array, second_array) = match (first, second) {
+        (toml::Value::Array(first), toml::Value::Array(second)) => (first, second),
+        _ => return None,
+    };
+    match algorithm {
+        Algorithm::Positional => Some(diff_positional(first_array, second_array, key, comparison)),
+        Algorithm::Set => Some(diff_set(first_array, second_array, key, comparison)),
+        Algorithm::Lcs => Some(diff_lcs(first_array, second_array, key, comparison)),
+        Algorithm::Keyed(field) => diff_arrays(first, second, field, key, comparison),
+    }
+}
+
+/// Display a path to an element of an array with the displayed key, like key[field = id] for an
+/// element of an array of tables with an identity, and key[index] otherwise
+pub fn element_path(
+    key: &str,
+    field: Option<&str>,
+    id: Option<&toml::Value>,
+    index: usize,
+) -> String {
+    match (field, id) {
+        (Some(field), Some(id)) => format!("{}[{} = {}]", key, field, id),
+        _ => format!("{}[{}]", key, index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_handling::{Comparison, ExactComparator};
+
+    fn servers(toml_str: &str) -> toml::Value {
+        let content: toml::Value = toml_str.parse().expect("Could not parse toml");
+        content
+            .get("servers")
+            .cloned()
+            .expect("Missing servers array")
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        let specs = parse_specs("servers=name,a.b=id").expect("Could not parse specs");
+        assert_eq!(
+            vec![
+                IdentitySpec {
+                    key: vec![String::from("servers")],
+                    field: String::from("name"),
+                },
+                IdentitySpec {
+                    key: vec![String::from("a"), String::from("b")],
+                    field: String::from("id"),
+                },
+            ],
+            specs
+        );
+        assert!(parse_specs("servers").is_err());
+        assert!(parse_specs("servers=").is_err());
+    }
+
+    #[test]
+    fn test_diff_arrays_insert() {
+        let first = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "c"
+        "#,
+        );
+        let second = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "b"
+            [[servers]]
+            name = "c"
+        "#,
+        );
+        let changes = diff_arrays(&first, &second, "name", &[], &ExactComparator)
+            .expect("Not arrays of tables");
+        assert_eq!(
+            vec![ArrayChange::Added {
+                index: 1,
+                id: Some(toml::Value::String(String::from("b"))),
+            }],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_diff_arrays_delete() {
+        let first = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "b"
+            [[servers]]
+            name = "c"
+        "#,
+        );
+        let second = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "c"
+        "#,
+        );
+        let changes = diff_arrays(&first, &second, "name", &[], &ExactComparator)
+            .expect("Not arrays of tables");
+        assert_eq!(
+            vec![ArrayChange::Removed {
+                index: 1,
+                id: Some(toml::Value::String(String::from("b"))),
+            }],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_diff_arrays_reorder() {
+        let first = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "b"
+            [[servers]]
+            name = "c"
+        "#,
+        );
+        let second = servers(
+            r#"
+            [[servers]]
+            name = "c"
+            [[servers]]
+            name = "a"
+            [[servers]]
+            name = "b"
+        "#,
+        );
+        let changes = diff_arrays(&first, &second, "name", &[], &ExactComparator)
+            .expect("Not arrays of tables");
+        assert_eq!(
+            vec![ArrayChange::Moved {
+                from: 2,
+                to: 0,
+                id: toml::Value::String(String::from("c")),
+            }],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_diff_arrays_change() {
+        let first = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            port = 80
+            [[servers]]
+            name = "b"
+            port = 80
+            tls = { enabled = false }
+        "#,
+        );
+        let second = servers(
+            r#"
+            [[servers]]
+            name = "a"
+            port = 80
+            [[servers]]
+            name = "b"
+            port = 443
+            tls = { enabled = true }
+        "#,
+        );
+        let changes = diff_arrays(&first, &second, "name", &[], &ExactComparator)
+            .expect("Not arrays of tables");
+        let id = toml::Value::String(String::from("b"));
+        assert_eq!(
+            vec![
+                ArrayChange::Changed {
+                    id: id.clone(),
+                    field: vec![String::from("port")],
+                    first: Some(toml::Value::Integer(80)),
+                    second: Some(toml::Value::Integer(443)),
+                },
+                ArrayChange::Changed {
+                    id,
+                    field: vec![String::from("tls"), String::from("enabled")],
+                    first: Some(toml::Value::Boolean(false)),
+                    second: Some(toml::Value::Boolean(true)),
+                },
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_diff_arrays_not_tables() {
+        let first = toml::Value::Array(vec![toml::Value::Integer(1)]);
+        let second = toml::Value::Array(vec![toml::Value::Integer(2)]);
+        assert_eq!(
+            None,
+            diff_arrays(&first, &second, "name", &[], &ExactComparator)
+        );
+    }
+
+    #[test]
+    fn test_algorithm_from_arg() {
+        assert_eq!(
+            Algorithm::Positional,
+            Algorithm::from_arg("positional").expect("Invalid algorithm")
+        );
+        assert_eq!(
+            Algorithm::Set,
+            Algorithm::from_arg("set").expect("Invalid algorithm")
+        );
+        assert_eq!(
+            Algorithm::Lcs,
+            Algorithm::from_arg("lcs").expect("Invalid algorithm")
+        );
+        assert_eq!(
+            Algorithm::Keyed(String::from("name")),
+            Algorithm::from_arg("keyed:name").expect("Invalid algorithm")
+        );
+        assert!(Algorithm::from_arg("keyed:").is_err());
+        assert!(Algorithm::from_arg("keyed").is_err());
+        assert!(Algorithm::from_arg("lcs:name").is_err());
+    }
+
+    #[test]
+    fn test_diff_algorithms() {
+        let first = servers(
+            r#"
+            servers = [{ name = "a", port = 1 }, { name = "b", port = 2 }, { name = "c", port = 3 }]
+        "#,
+        );
+        let second = servers(
+            r#"
+            servers = [{ name = "c", port = 3 }, { name = "a", port = 1 }, { name = "b", port = 5 }]
+        "#,
+        );
+        let element = |index: usize, array: &toml::Value| match array {
+            toml::Value::Array(array) => array[index].clone(),
+            _ => unreachable!(),
+        };
+
+        let positional = diff(
+            &Algorithm::Positional,
+            &[],
+            &first,
+            &second,
+            &ExactComparator,
+        )
+        .expect("Not arrays");
+        assert_eq!(
+            (0..3)
+                .map(|index| ArrayChange::Replaced {
+                    index,
+                    first: element(index, &first),
+                    second: element(index, &second),
+                })
+                .collect::<Vec<ArrayChange>>(),
+            positional
+        );
+
+        let set =
+            diff(&Algorithm::Set, &[], &first, &second, &ExactComparator).expect("Not arrays");
+        assert_eq!(
+            vec![
+                ArrayChange::Removed {
+                    index: 1,
+                    id: Some(element(1, &first)),
+                },
+                ArrayChange::Added {
+                    index: 2,
+                    id: Some(element(2, &second)),
+                },
+            ],
+            set
+        );
+
+        let lcs =
+            diff(&Algorithm::Lcs, &[], &first, &second, &ExactComparator).expect("Not arrays");
+        assert_eq!(
+            vec![
+                ArrayChange::Removed {
+                    index: 0,
+                    id: Some(element(0, &first)),
+                },
+                ArrayChange::Removed {
+                    index: 1,
+                    id: Some(element(1, &first)),
+                },
+                ArrayChange::Added {
+                    index: 1,
+                    id: Some(element(1, &second)),
+                },
+                ArrayChange::Added {
+                    index: 2,
+                    id: Some(element(2, &second)),
+                },
+            ],
+            lcs
+        );
+
+        let keyed = diff(
+            &Algorithm::Keyed(String::from("name")),
+            &[],
+            &first,
+            &second,
+            &ExactComparator,
+        )
+        .expect("Not arrays");
+        assert_eq!(
+            vec![
+                ArrayChange::Moved {
+                    from: 2,
+                    to: 0,
+                    id: toml::Value::String(String::from("c")),
+                },
+                ArrayChange::Changed {
+                    id: toml::Value::String(String::from("b")),
+                    field: vec![String::from("port")],
+                    first: Some(toml::Value::Integer(2)),
+                    second: Some(toml::Value::Integer(5)),
+                },
+            ],
+            keyed
+        );
+    }
+
+    #[test]
+    fn test_diff_positional_lengths() {
+        let first = toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)]);
+        let second = toml::Value::Array(vec![toml::Value::Integer(1)]);
+        assert_eq!(
+            Some(vec![ArrayChange::Removed {
+                index: 1,
+                id: Some(toml::Value::Integer(2)),
+            }]),
+            diff(
+                &Algorithm::Positional,
+                &[],
+                &first,
+                &second,
+                &ExactComparator
+            )
+        );
+        assert_eq!(
+            Some(vec![ArrayChange::Added {
+                index: 1,
+                id: Some(toml::Value::Integer(2)),
+            }]),
+            diff(
+                &Algorithm::Positional,
+                &[],
+                &second,
+                &first,
+                &ExactComparator
+            )
+        );
+        assert_eq!(
+            None,
+            diff(
+                &Algorithm::Positional,
+                &[],
+                &first,
+                &toml::Value::Integer(1),
+                &ExactComparator
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_positional_nested() {
+        let value = |toml_str: &str| -> toml::Value {
+            let content: toml::Value = format!("value = {}", toml_str)
+                .parse()
+                .expect("Could not parse toml");
+            content["value"].clone()
+        };
+        let first = value("[[1, 2], [3, [4, 5]], [6]]");
+        let second = value("[[1, 2], [3, [4, 7], 8], 6]");
+        assert_eq!(
+            Some(vec![
+                ArrayChange::Nested {
+                    index: 1,
+                    changes: vec![
+                        ArrayChange::Nested {
+                            index: 1,
+                            changes: vec![ArrayChange::Replaced {
+                                index: 1,
+                                first: value("5"),
+                                second: value("7"),
+                            }],
+                        },
+                        ArrayChange::Added {
+                            index: 2,
+                            id: Some(value("8")),
+                        },
+                    ],
+                },
+                ArrayChange::Replaced {
+                    index: 2,
+                    first: value("[6]"),
+                    second: value("6"),
+                },
+            ]),
+            diff(
+                &Algorithm::Positional,
+                &[],
+                &first,
+                &second,
+                &ExactComparator
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_with_comparison() {
+        let first: toml::Value = toml::Value::Array(vec![
+            toml::Value::Float(1.0),
+            toml::Value::Integer(2),
+            toml::Value::Float(3.0),
+        ]);
+        let second: toml::Value = toml::Value::Array(vec![
+            toml::Value::Float(1.05),
+            toml::Value::Float(2.0),
+            toml::Value::Float(4.0),
+        ]);
+        let comparison = Comparison {
+            float_tolerance: Some(0.1),
+            numeric_loose: true,
+            ..Comparison::default()
+        };
+
+        // Only the elements that are unequal by the comparison are reported
+        let positional = diff(&Algorithm::Positional, &[], &first, &second, &comparison);
+        assert_eq!(
+            Some(vec![ArrayChange::Replaced {
+                index: 2,
+                first: toml::Value::Float(3.0),
+                second: toml::Value::Float(4.0),
+            }]),
+            positional
+        );
+        for algorithm in [Algorithm::Set, Algorithm::Lcs] {
+            assert_eq!(
+                Some(vec![
+                    ArrayChange::Removed {
+                        index: 2,
+                        id: Some(toml::Value::Float(3.0)),
+                    },
+                    ArrayChange::Added {
+                        index: 2,
+                        id: Some(toml::Value::Float(4.0)),
+                    },
+                ]),
+                diff(&algorithm, &[], &first, &second, &comparison)
+            );
+        }
+
+        let first = servers("servers = [{ name = \"a\", weight = 1.0 }]");
+        let second = servers("servers = [{ name = \"a\", weight = 1.05 }]");
+        let keyed = Algorithm::Keyed(String::from("name"));
+        assert_eq!(
+            Some(vec![]),
+            diff(&keyed, &[], &first, &second, &comparison)
+        );
+        assert_eq!(
+            1,
+            diff(&keyed, &[], &first, &second, &ExactComparator)
+                .expect("Not arrays of tables")
+                .len()
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Rewriting toml documents in a canonical form
+//!
+//! Documents with the same content, but written with other formatting, key order, or nesting
+//! syntax, have the same canonical form, so they can be compared with plain text diff tools. The
+//! keys are always sorted. The other normalizations are given by `Options`.
+
+use anyhow::{anyhow, Error};
+use itertools::Itertools;
+
+use crate::parse;
+
+/// Normalizations of the values of a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Sort the elements of arrays by their toml representation
+    pub sort_arrays: bool,
+    /// Round floats to this number of decimals
+    pub float_precision: Option<u32>,
+}
+
+/// Normalize a value, and all values nested in it
+fn normalize(value: toml::Value, options: &Options) -> toml::Value {
+    match value {
+        toml::Value::Float(val) => match options.float_precision {
+            Some(precision) => {
+                let scale = 10f64.powi(precision as i32);
+                toml::Value::Float((val * scale).round() / scale)
+            }
+            None => toml::Value::Float(val),
+        },
+        toml::Value::Array(array) => {
+            let mut array: Vec<toml::Value> = array
+                .into_iter()
+                .map(|element| normalize(element, options))
+                .collect();
+            if options.sort_arrays {
+                array.sort_by_cached_key(|element| element.to_string());
+            }
+            toml::Value::Array(array)
+        }
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .into_iter()
+                .map(|(key, val)| (key, normalize(val, options)))
+                .sorted_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Canonical toml document with the same content as the given value
+pub fn canonicalize(value: toml::Value, options: &Options) -> Result<String, Error> {
+    let (collection, arrays) = parse::flatten_value_arrays(value);
+    let nested = parse::unflatten(&collection, &arrays)?;
+    toml::to_string(&normalize(nested, options)).map_err(|msg| anyhow!(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(toml_str: &str) -> toml::Value {
+        toml_str.parse().expect("Could not parse toml")
+    }
+
+    #[test]
+    fn test_canonicalize_equal_content() {
+        let first = value(
+            r#"
+            name = "first"
+            ratio = 1.50
+            count = 0x10
+            server.port = 80
+            server.tls = { enabled = true, versions = ["1.3", "1.2"] }
+
+            [[items]]
+            id = 1
+        "#,
+        );
+        let second = value(
+            r#"
+            count = 16
+            name = 'first'
+            ratio = 1.5
+            items = [{ id = 1 }]
+
+            [server]
+            port = 80
+
+            [server.tls]
+            versions = [
+                "1.3",
+                "1.2",
+            ]
+            enabled = true
+        "#,
+        );
+        let options = Options::default();
+        let canonical = canonicalize(first.clone(), &options).expect("Could not canonicalize");
+        assert_eq!(
+            canonical,
+            canonicalize(second, &options).expect("Could not canonicalize")
+        );
+        assert_eq!(first, value(&canonical));
+        assert!(canonical.find("count").unwrap() < canonical.find("name").unwrap());
+    }
+
+    #[test]
+    fn test_canonicalize_options() {
+        let first = value("values = [3, 1, 2]\nratio = 0.1234");
+        let second = value("values = [1, 2, 3]\nratio = 0.12");
+        assert_ne!(
+            canonicalize(first.clone(), &Options::default()).unwrap(),
+            canonicalize(second.clone(), &Options::default()).unwrap()
+        );
+
+        let options = Options {
+            sort_arrays: true,
+            float_precision: Some(2),
+        };
+        assert_eq!(
+            canonicalize(first, &options).unwrap(),
+            canonicalize(second, &options).unwrap()
+        );
+    }
+}
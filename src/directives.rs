@@ -0,0 +1,241 @@
+//! Structured comments treated as diffable metadata
+//!
+//! A directive is a comment line on the form `# name: value`, where the name consists of ASCII
+//! letters, digits, `-` and `_`, and the value is the rest of the line with surrounding whitespace
+//! removed. It can not be empty. Directives on the lines directly above a key or a table header
+//! belong to that key, and are added to the collection under the key extended with `#name`, with
+//! the value as a string. A blank line, or a comment that is not a directive, discards the
+//! directives above it.
+//!
+//! The comments are found with a textual pass over the file, since the toml parser discards them.
+//! Directives above an array of tables header `[[key]]` belong to `key`, so the directives of the
+//! elements override each other.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Error;
+use indexmap::IndexMap;
+
+use crate::key_handling::Key;
+use crate::parse;
+
+/// Split a toml key like `a."b.c".d` into its parts
+fn split_key(key: &str) -> Key {
+    let mut parts = Key::new();
+    let mut part = String::new();
+    let mut quote: Option<char> = None;
+    for c in key.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => part.push(c),
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '.') => parts.push(std::mem::take(&mut part).trim().to_string()),
+            (None, c) => part.push(c),
+        }
+    }
+    parts.push(part.trim().to_string());
+    parts
+}
+
+/// Key of the key/value pair on a line, if it starts one
+pub fn line_key(line: &str) -> Option<Key> {
+    let mut quote: Option<char> = None;
+    for (pos, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '=') => return Some(split_key(&line[..pos])),
+            (None, '[') | (None, '#') | (None, ']') | (None, '{') | (None, ',') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Key of a table header like `[key]` or `[[key]]`, if the line is one
+pub fn header_key(line: &str) -> Option<Key> {
+    let line = match line.find('#') {
+        Some(pos) => line[..pos].trim_end(),
+        None => line,
+    };
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let inner = match (inner.strip_prefix('['), inner.strip_suffix(']')) {
+        (Some(_), Some(_)) => &inner[1..inner.len() - 1],
+        _ => inner,
+    };
+    if inner.contains('=') {
+        return None;
+    }
+    Some(split_key(inner))
+}
+
+/// Name and value of a directive comment, if the line is one
+fn directive(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.strip_prefix('#')?.split_once(':')?;
+    let name = name.trim();
+    let value = value.trim();
+    let valid_name = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || !valid_name || value.is_empty() {
+        return None;
+    }
+    Some((String::from(name), String::from(value)))
+}
+
+/// Collect the directives of a toml string
+pub fn parse_str(content: &str) -> IndexMap<Key, toml::Value> {
+    let mut directives = IndexMap::<Key, toml::Value>::new();
+    let mut pending = Vec::<(String, String)>::new();
+    let mut table = Key::new();
+    let mut in_multiline_string = false;
+
+    for line in content.lines() {
+        // Multi-line strings may contain anything, including lines that look like comments
+        let delimiters = line.matches("\"\"\"").count() + line.matches("'''").count();
+        if in_multiline_string {
+            in_multiline_string = delimiters % 2 == 0;
+            continue;
+        }
+        in_multiline_string = delimiters % 2 == 1;
+
+        let line = line.trim();
+        let key = if let Some(header) = header_key(line) {
+            table = header.clone();
+            Some(header)
+        } else {
+            line_key(line).map(|key| table.iter().cloned().chain(key).collect::<Key>())
+        };
+
+        match (key, directive(line)) {
+            (Some(key), _) => {
+                for (name, value) in pending.drain(..) {
+                    let mut directive_key = key.clone();
+                    directive_key.push(format!("#{}", name));
+                    directives.insert(directive_key, toml::Value::String(value));
+                }
+            }
+            (None, Some(found)) => pending.push(found),
+            (None, None) => pending.clear(),
+        }
+    }
+    directives
+}
+
+/// Collect the directives of the given files, where directives in later files override those in
+/// earlier files
+pub fn parse_files(paths: &[PathBuf]) -> Result<IndexMap<Key, toml::Value>, Error> {
+    let mut directives = IndexMap::<Key, toml::Value>::new();
+    for path in paths.iter() {
+        directives.extend(parse_str(&parse::read_file_to_string(path)?));
+    }
+    Ok(directives)
+}
+
+/// Collect the directives of a toml file
+pub fn parse_file(path: &Path) -> Result<IndexMap<Key, toml::Value>, Error> {
+    parse_files(&[path.to_path_buf()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_handling;
+
+    fn key(s: &str) -> Key {
+        s.split('/').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_directive() {
+        assert_eq!(
+            Some((String::from("owner"), String::from("team-a"))),
+            directive("# owner: team-a")
+        );
+        assert_eq!(
+            Some((String::from("review_by"), String::from("2020-01-01: q1"))),
+            directive("#review_by:2020-01-01: q1")
+        );
+        assert_eq!(None, directive("# owner:"));
+        assert_eq!(None, directive("# Note that this: is prose"));
+        assert_eq!(None, directive("owner: team-a"));
+    }
+
+    #[test]
+    fn test_parse_str() {
+        let content = r#"
+            # owner: team-a
+            name = "first"
+
+            # owner: team-b
+            # stability: beta
+            [server]
+            # owner: team-c
+            "host.name" = "localhost"
+
+            # owner: discarded
+
+            # A plain comment
+            # owner: also discarded
+            # Another plain comment
+            port = 80
+            text = """
+            # owner: in a string
+            """
+            # owner: team-d
+            [[items]]
+            id = 1
+        "#;
+        let directives = parse_str(content);
+
+        let expected: IndexMap<Key, toml::Value> = vec![
+            ("name/#owner", "team-a"),
+            ("server/#owner", "team-b"),
+            ("server/#stability", "beta"),
+            ("server/host.name/#owner", "team-c"),
+            ("items/#owner", "team-d"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (key(k), toml::Value::String(String::from(v))))
+        .collect();
+        assert_eq!(expected, directives);
+    }
+
+    #[test]
+    fn test_directive_changes() {
+        let first = parse_str(
+            r#"
+            # owner: team-a
+            name = "first"
+            # owner: team-a
+            version = "0.1.0"
+            # deprecated: yes
+            port = 80
+        "#,
+        );
+        let second = parse_str(
+            r#"
+            # owner: team-b
+            name = "first"
+            # owner: team-a
+            # stability: beta
+            version = "0.1.0"
+            port = 80
+        "#,
+        );
+        let first_keys: Vec<Key> = first.keys().cloned().collect();
+        let second_keys: Vec<Key> = second.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys)
+            .expect("Could not compare keys");
+
+        assert_eq!(vec![key("port/#deprecated")], key_origins.first_only());
+        assert_eq!(vec![key("version/#stability")], key_origins.second_only());
+        let changed: Vec<Key> = key_origins
+            .both()
+            .into_iter()
+            .filter(|k| first.get(k) != second.get(k))
+            .collect();
+        assert_eq!(vec![key("name/#owner")], changed);
+    }
+}
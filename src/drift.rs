@@ -0,0 +1,187 @@
+//! Drift of a target file from several baselines
+//!
+//! The target is compared against each baseline, and the keys that differ from every baseline,
+//! like systematic drift, are separated from the keys that only differ from some of them. A key
+//! differs if it is only on one side, or has unequal values.
+
+use std::collections::HashSet;
+
+use colored::Colorize;
+use indexmap::IndexMap;
+use itertools::Itertools;
+
+use crate::key_handling::{self, Key, KeyStatus};
+use crate::report::TextStyle;
+use crate::value_handling::Comparison;
+
+/// Changes of a target from several baselines
+#[derive(Debug, PartialEq)]
+pub struct Drift {
+    /// Keys that differ from every baseline, sorted
+    pub common: Vec<Key>,
+    /// Keys that differ from each baseline, but not from every baseline, sorted
+    pub specific: Vec<Vec<Key>>,
+}
+
+impl Drift {
+    /// Check if the target differs from any baseline
+    pub fn has_changes(&self) -> bool {
+        !self.common.is_empty() || self.specific.iter().any(|keys| !keys.is_empty())
+    }
+}
+
+/// Compare a target against each baseline, and split the changes into those from every baseline
+/// and those from only some of them, where only the keys where `is_compared` is true are kept
+pub fn baseline_drift(
+    baselines: &[IndexMap<Key, toml::Value>],
+    target: &IndexMap<Key, toml::Value>,
+    comparison: &Comparison,
+    is_compared: &dyn Fn(&[String]) -> bool,
+) -> Drift {
+    let changed: Vec<HashSet<Key>> = baselines
+        .iter()
+        .map(|baseline| {
+            key_handling::classify_keys(baseline, target, comparison)
+                .into_iter()
+                .filter(|(_, status)| !matches!(status, KeyStatus::Equal(_)))
+                .map(|(key, _)| key)
+                .filter(|key| is_compared(key))
+                .collect()
+        })
+        .collect();
+
+    let common: HashSet<Key> = match changed.split_first() {
+        Some((first, rest)) => first
+            .iter()
+            .filter(|key| rest.iter().all(|other| other.contains(*key)))
+            .cloned()
+            .collect(),
+        None => HashSet::new(),
+    };
+    Drift {
+        common: common.iter().cloned().sorted().collect(),
+        specific: changed
+            .iter()
+            .map(|keys| keys.difference(&common).cloned().sorted().collect())
+            .collect(),
+    }
+}
+
+/// Render the changes of the target from every baseline, and then from each baseline, where the
+/// baselines are displayed with the given names
+pub fn render(
+    drift: &Drift,
+    names: &[String],
+    baselines: &[IndexMap<Key, toml::Value>],
+    target: &IndexMap<Key, toml::Value>,
+    style: &TextStyle,
+) -> String {
+    let mut lines = Vec::<String>::new();
+    if !drift.common.is_empty() {
+        if style.color {
+            lines.push("\nAll baselines".red().to_string());
+        } else {
+            lines.push(String::from("\nEntries changed from all baselines"));
+        }
+        for key in drift.common.iter() {
+            lines.push(format!(
+                "{}: {}",
+                style.key_text(key),
+                style.value_text(target.get(key))
+            ));
+        }
+    }
+
+    for ((name, baseline), keys) in names
+        .iter()
+        .zip(baselines.iter())
+        .zip(drift.specific.iter())
+    {
+        if keys.is_empty() {
+            continue;
+        }
+        if style.color {
+            lines.push(format!("\n{}", name).blue().to_string());
+        } else {
+            lines.push(format!(
+                "\nEntries changed from {}, but not from all baselines",
+                name
+            ));
+        }
+        for key in keys.iter() {
+            lines.push(format!(
+                "{}: {} -> {}",
+                style.key_text(key),
+                style.value_text(baseline.get(key)),
+                style.value_text(target.get(key))
+            ));
+        }
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::parse;
+
+    fn key(s: &str) -> Key {
+        s.split('.').map(String::from).collect()
+    }
+
+    fn names() -> Vec<String> {
+        ["b1", "b2", "b3"]
+            .iter()
+            .map(|name| format!("assets/baselines/{}.toml", name))
+            .collect()
+    }
+
+    fn baselines() -> Vec<IndexMap<Key, toml::Value>> {
+        names()
+            .iter()
+            .map(|path| parse::parse_toml(Path::new(path)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_baseline_drift() {
+        let baselines = baselines();
+        let target = parse::parse_toml(Path::new("assets/baselines/target.toml")).unwrap();
+        let comparison = Comparison::default();
+
+        let drift = baseline_drift(&baselines, &target, &comparison, &|_| true);
+        assert_eq!(
+            Drift {
+                common: vec![key("debug"), key("port")],
+                specific: vec![
+                    vec![key("replicas")],
+                    vec![],
+                    vec![key("log"), key("replicas")]
+                ],
+            },
+            drift
+        );
+        assert!(drift.has_changes());
+
+        let drift = baseline_drift(&baselines, &target, &comparison, &|key| key != ["port"]);
+        assert_eq!(vec![key("debug")], drift.common);
+        let unchanged = baseline_drift(&baselines[..1], &baselines[0], &comparison, &|_| true);
+        assert!(!unchanged.has_changes());
+    }
+
+    #[test]
+    fn test_render() {
+        let baselines = baselines();
+        let target = parse::parse_toml(Path::new("assets/baselines/target.toml")).unwrap();
+        let drift = baseline_drift(&baselines, &target, &Comparison::default(), &|_| true);
+
+        let rendered = render(&drift, &names(), &baselines, &target, &TextStyle::default());
+        assert!(rendered.starts_with("\nEntries changed from all baselines\ndebug: true\n"));
+        assert!(rendered.contains(
+            "\nEntries changed from assets/baselines/b1.toml, but not from all baselines\n"
+        ));
+        assert!(!rendered.contains("assets/baselines/b2.toml"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Rendering of the diff as a standalone HTML page
+//!
+
+use itertools::Itertools;
+
+use crate::report::DiffReport;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+td { font-family: monospace; white-space: pre-wrap; }
+tr.removed td { background-color: #dde8ff; }
+tr.added td { background-color: #fff4cc; }
+tr.unequal td { background-color: #ffdddd; }
+tr.equal td { background-color: #ddffdd; }
+";
+
+/// Escape text for use in HTML content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn row(
+    report: &DiffReport,
+    class: &str,
+    key: &[String],
+    first: Option<&toml::Value>,
+    second: Option<&toml::Value>,
+) -> String {
+    let cell = |value: Option<&toml::Value>| {
+        value.map_or_else(String::new, |val| escape(&report.value_text(val)))
+    };
+    format!(
+        "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+        class,
+        escape(&key.iter().join(".")),
+        cell(first),
+        cell(second)
+    )
+}
+
+/// Render the report as a standalone HTML page
+pub fn render(report: &DiffReport) -> String {
+    let first_name = escape(&report.first_name);
+    let second_name = escape(&report.second_name);
+
+    let mut rows = String::new();
+    for (key, val) in report.first_only.iter() {
+        rows.push_str(&row(report, "removed", key, Some(val), None));
+    }
+    for (key, val) in report.second_only.iter() {
+        rows.push_str(&row(report, "added", key, None, Some(val)));
+    }
+    for (key, first_val, second_val) in report.unequal.iter() {
+        rows.push_str(&row(
+            report,
+            "unequal",
+            key,
+            Some(first_val),
+            Some(second_val),
+        ));
+    }
+    for (key, first_val, second_val) in report.equal.iter() {
+        rows.push_str(&row(
+            report,
+            "equal",
+            key,
+            Some(first_val),
+            Some(second_val),
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>difftoml: {first} vs {second}</title>
+<style>{style}</style>
+</head>
+<body>
+<h1>{first} vs {second}</h1>
+<p>{removed} only in {first}, {added} only in {second}, {unequal} unequal, {equal} equal</p>
+<table>
+<thead>
+<tr><th>Key</th><th>{first}</th><th>{second}</th></tr>
+</thead>
+<tbody>
+{rows}</tbody>
+</table>
+</body>
+</html>
+",
+        first = first_name,
+        second = second_name,
+        style = STYLE,
+        removed = report.first_only.len(),
+        added = report.second_only.len(),
+        unequal = report.unequal.len(),
+        equal = report.equal.len(),
+        rows = rows
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that every opened tag is closed in order, apart from void elements
+    fn is_well_formed(html: &str) -> bool {
+        let mut stack = Vec::<String>::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let end = match rest[start..].find('>') {
+                Some(end) => start + end,
+                None => return false,
+            };
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with('!') {
+                continue;
+            }
+            let name = tag
+                .trim_start_matches('/')
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_string();
+            if name == "meta" {
+                continue;
+            }
+            if tag.starts_with('/') {
+                if stack.pop() != Some(name) {
+                    return false;
+                }
+            } else {
+                stack.push(name);
+            }
+        }
+        stack.is_empty()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;",
+            escape("<a href=\"x\">&'")
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let removed_key = vec![String::from("removed")];
+        let added_key = vec![String::from("table"), String::from("added")];
+        let unequal_key = vec![String::from("unequal")];
+        let equal_key = vec![String::from("equal")];
+        let removed_val = toml::Value::Integer(1);
+        let added_val = toml::Value::String(String::from("<script>"));
+        let first_val = toml::Value::Boolean(true);
+        let second_val = toml::Value::Boolean(false);
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(removed_key, &removed_val)],
+            second_only: vec![(added_key, &added_val)],
+            unequal: vec![(unequal_key, &first_val, &second_val)],
+            equal: vec![(equal_key, &removed_val, &removed_val)],
+        };
+        let html = render(&report);
+
+        assert!(is_well_formed(&html));
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>first.toml vs second.toml</h1>"));
+        assert!(html.contains("1 only in first.toml, 1 only in second.toml, 1 unequal, 1 equal"));
+        assert!(html.contains("<tr class=\"removed\"><td>removed</td><td>1</td><td></td></tr>"));
+        assert!(html.contains(
+            "<tr class=\"added\"><td>table.added</td><td></td><td>&quot;&lt;script&gt;&quot;</td></tr>"
+        ));
+        assert!(
+            html.contains("<tr class=\"unequal\"><td>unequal</td><td>true</td><td>false</td></tr>")
+        );
+        assert!(html.contains("<tr class=\"equal\"><td>equal</td><td>1</td><td>1</td></tr>"));
+        assert!(!html.contains("<script>"));
+    }
+}
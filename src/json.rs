@@ -0,0 +1,174 @@
+//! Rendering of the diff as a JSON document
+//!
+//! The document is an object with the names of the files, their hashes if they are in the report,
+//! and a list of entries for each section.
+//! Each entry has the key as a list of strings, and the value on each side where the key is
+//! present. Datetimes are written as strings in toml format, and floats that are not finite as
+//! null.
+
+use serde_json::{json, Value};
+
+use crate::report::{DiffReport, DiffStat};
+
+/// Convert a toml value to the corresponding JSON value
+pub fn to_json(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(val) => Value::String(val.clone()),
+        toml::Value::Integer(val) => Value::from(*val),
+        toml::Value::Float(val) => {
+            serde_json::Number::from_f64(*val).map_or(Value::Null, Value::Number)
+        }
+        toml::Value::Boolean(val) => Value::Bool(*val),
+        toml::Value::Datetime(val) => Value::String(val.to_string()),
+        toml::Value::Array(array) => Value::Array(array.iter().map(to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, val)| (key.clone(), to_json(val)))
+                .collect(),
+        ),
+    }
+}
+
+/// Render the report as a pretty-printed JSON object
+pub fn render(report: &DiffReport) -> String {
+    let mut document = json!({
+        "first": report.first_name,
+        "second": report.second_name,
+        "first_only": report
+            .first_only
+            .iter()
+            .map(|(key, val)| json!({ "key": key, "first": to_json(val) }))
+            .collect::<Vec<Value>>(),
+        "second_only": report
+            .second_only
+            .iter()
+            .map(|(key, val)| json!({ "key": key, "second": to_json(val) }))
+            .collect::<Vec<Value>>(),
+        "unequal": report
+            .unequal
+            .iter()
+            .map(|(key, first_val, second_val)| {
+                json!({ "key": key, "first": to_json(first_val), "second": to_json(second_val) })
+            })
+            .collect::<Vec<Value>>(),
+        "equal": report
+            .equal
+            .iter()
+            .map(|(key, first_val, second_val)| {
+                json!({ "key": key, "first": to_json(first_val), "second": to_json(second_val) })
+            })
+            .collect::<Vec<Value>>(),
+    });
+    if let (Some(first_hash), Some(second_hash)) = (&report.first_hash, &report.second_hash) {
+        document["first_hash"] = json!(first_hash);
+        document["second_hash"] = json!(second_hash);
+    }
+    match serde_json::to_string_pretty(&document) {
+        Ok(rendered) => rendered,
+        // A JSON value always serializes
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Render the counts of the entries as a pretty-printed JSON object
+pub fn render_stat(stat: &DiffStat) -> String {
+    let document = json!({
+        "first_only": stat.first_only,
+        "second_only": stat.second_only,
+        "changed": stat.changed,
+        "equal": stat.equal,
+    });
+    match serde_json::to_string_pretty(&document) {
+        Ok(rendered) => rendered,
+        // A JSON value always serializes
+        Err(_) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let value: toml::Value = r#"
+            string = "a"
+            integer = 1
+            float = 1.5
+            nan = nan
+            boolean = true
+            datetime = 2020-01-01T07:32:00Z
+            array = [1, 2]
+            table = { key = "value" }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            json!({
+                "string": "a",
+                "integer": 1,
+                "float": 1.5,
+                "nan": null,
+                "boolean": true,
+                "datetime": "2020-01-01T07:32:00Z",
+                "array": [1, 2],
+                "table": { "key": "value" },
+            }),
+            to_json(&value)
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let one = toml::Value::Integer(1);
+        let two = toml::Value::Integer(2);
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(vec![String::from("removed")], &one)],
+            second_only: vec![(vec![String::from("a"), String::from("added")], &two)],
+            unequal: vec![(vec![String::from("unequal")], &one, &two)],
+            equal: vec![(vec![String::from("equal")], &one, &one)],
+        };
+        let rendered: Value = serde_json::from_str(&render(&report)).unwrap();
+        assert_eq!(
+            json!({
+                "first": "first.toml",
+                "second": "second.toml",
+                "first_only": [{ "key": ["removed"], "first": 1 }],
+                "second_only": [{ "key": ["a", "added"], "second": 2 }],
+                "unequal": [{ "key": ["unequal"], "first": 1, "second": 2 }],
+                "equal": [{ "key": ["equal"], "first": 1, "second": 1 }],
+            }),
+            rendered
+        );
+
+        let report = DiffReport {
+            first_hash: Some(String::from("abc")),
+            second_hash: Some(String::from("def")),
+            ..report
+        };
+        let rendered: Value = serde_json::from_str(&render(&report)).unwrap();
+        assert_eq!(json!("abc"), rendered["first_hash"]);
+        assert_eq!(json!("def"), rendered["second_hash"]);
+    }
+
+    #[test]
+    fn test_render_stat() {
+        let stat = DiffStat {
+            first_only: 1,
+            second_only: 2,
+            changed: 3,
+            equal: 4,
+        };
+        let rendered: Value = serde_json::from_str(&render_stat(&stat)).unwrap();
+        assert_eq!(
+            json!({ "first_only": 1, "second_only": 2, "changed": 3, "equal": 4 }),
+            rendered
+        );
+    }
+}
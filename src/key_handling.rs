@@ -1,12 +1,19 @@
 //! Misc utility functions regarding key handling
 //!
 
-use anyhow::{anyhow, Error};
+use anyhow::Error;
+use indexmap::IndexMap;
 use itertools::Itertools;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::value_handling::ValueComparator;
 
 pub type Key = Vec<String>;
 
-/// Contains the result of a key-comparison between two files
+/// Contains the result of a key-comparison between two files, with each group of keys in the order
+/// of the file it is taken from
 pub struct KeyOrigins<T: Eq + Clone> {
     /// Keys that are only in the first file
     first_only: Vec<T>,
@@ -17,11 +24,11 @@ pub struct KeyOrigins<T: Eq + Clone> {
 }
 
 impl<T: Eq + Clone> KeyOrigins<T> {
-    fn new(first_only: &[T], second_only: &[T], both: &[T]) -> Self {
+    fn new(first_only: Vec<T>, second_only: Vec<T>, both: Vec<T>) -> Self {
         KeyOrigins {
-            first_only: first_only.to_vec(),
-            second_only: second_only.to_vec(),
-            both: both.to_vec(),
+            first_only,
+            second_only,
+            both,
         }
     }
 
@@ -36,6 +43,91 @@ impl<T: Eq + Clone> KeyOrigins<T> {
     pub fn both(&self) -> Vec<T> {
         self.both.clone()
     }
+
+    /// Keep only the keys the predicate is true for, in every group
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        self.first_only.retain(&mut keep);
+        self.second_only.retain(&mut keep);
+        self.both.retain(&mut keep);
+    }
+}
+
+/// Contains the result of a three-way comparison of two files against a common base, with each
+/// group of keys in the order they are first found in the base, our, and their file
+pub struct ThreeWayOrigins<T: Eq + Clone> {
+    /// Keys changed from the base in our file only
+    ours: Vec<T>,
+    /// Keys changed from the base in their file only
+    theirs: Vec<T>,
+    /// Keys changed from the base to the same value in both files
+    both: Vec<T>,
+    /// Keys changed from the base to different values in the two files
+    conflicts: Vec<T>,
+}
+
+impl<T: Eq + Clone> ThreeWayOrigins<T> {
+    pub fn ours(&self) -> Vec<T> {
+        self.ours.clone()
+    }
+
+    pub fn theirs(&self) -> Vec<T> {
+        self.theirs.clone()
+    }
+
+    pub fn both(&self) -> Vec<T> {
+        self.both.clone()
+    }
+
+    pub fn conflicts(&self) -> Vec<T> {
+        self.conflicts.clone()
+    }
+
+    /// Keep only the keys the predicate is true for, in every group
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        self.ours.retain(&mut keep);
+        self.theirs.retain(&mut keep);
+        self.both.retain(&mut keep);
+        self.conflicts.retain(&mut keep);
+    }
+}
+
+/// Find the keys that are changed from the base collection in our collection, their collection,
+/// or both, where adding and removing a key count as changes
+pub fn compare_three_way(
+    base: &IndexMap<Key, toml::Value>,
+    ours: &IndexMap<Key, toml::Value>,
+    theirs: &IndexMap<Key, toml::Value>,
+    cmp: &dyn ValueComparator,
+) -> ThreeWayOrigins<Key> {
+    let equal = |key: &[String], first: Option<&toml::Value>, second: Option<&toml::Value>| match (
+        first, second,
+    ) {
+        (Some(first_val), Some(second_val)) => cmp.equal(key, first_val, second_val),
+        (None, None) => true,
+        _ => false,
+    };
+    let keys = base.keys().chain(ours.keys()).chain(theirs.keys()).unique();
+
+    let mut origins = ThreeWayOrigins {
+        ours: Vec::new(),
+        theirs: Vec::new(),
+        both: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    for key in keys {
+        let (base_val, our_val, their_val) = (base.get(key), ours.get(key), theirs.get(key));
+        let changed_by_us = !equal(key, base_val, our_val);
+        let changed_by_them = !equal(key, base_val, their_val);
+        let group = match (changed_by_us, changed_by_them) {
+            (false, false) => continue,
+            (true, false) => &mut origins.ours,
+            (false, true) => &mut origins.theirs,
+            (true, true) if equal(key, our_val, their_val) => &mut origins.both,
+            (true, true) => &mut origins.conflicts,
+        };
+        group.push(key.clone());
+    }
+    origins
 }
 
 /// Compares two vectors by partitioning the elements in the two into three new vectors
@@ -46,45 +138,125 @@ impl<T: Eq + Clone> KeyOrigins<T> {
 /// 2: Elements only in the second vector
 /// 3: Elements only in the third vector
 ///
-pub fn compare_vectors<T: Eq + Clone>(first: &[T], second: &[T]) -> Result<KeyOrigins<T>, Error> {
-    let mut in_first_only = Vec::<T>::new();
-    let mut in_second_only = Vec::<T>::new();
-    let mut in_both = Vec::<T>::new();
-
-    for element in first {
-        if second.contains(element) {
-            in_both.push(element.clone());
-        } else {
-            in_first_only.push(element.clone());
-        }
-    }
+/// The elements keep the order of the vector they are taken from, where the elements in both
+/// vectors are in the order of the first vector.
+pub fn compare_vectors<T: Eq + Hash + Clone>(
+    first: &[T],
+    second: &[T],
+) -> Result<KeyOrigins<T>, Error> {
+    let first_set: HashSet<&T> = first.iter().collect();
+    let second_set: HashSet<&T> = second.iter().collect();
+
+    let (in_both, in_first_only): (Vec<T>, Vec<T>) = first
+        .iter()
+        .cloned()
+        .partition(|element| second_set.contains(element));
+    let in_second_only: Vec<T> = second
+        .iter()
+        .filter(|element| !first_set.contains(element))
+        .cloned()
+        .collect();
+
+    Ok(KeyOrigins::new(in_first_only, in_second_only, in_both))
+}
 
-    let mut in_both_wrt_second = Vec::<T>::new();
-    for element in second {
-        if first.contains(element) {
-            in_both_wrt_second.push(element.clone());
-        } else {
-            in_second_only.push(element.clone());
+/// Key with every level in lowercase
+pub fn lowercase_key(key: &[String]) -> Key {
+    key.iter().map(|level| level.to_lowercase()).collect()
+}
+
+/// Rename the keys of the second collection to the casing of the keys in the first collection that
+/// they equal when ignoring case, so the keys match when compared
+///
+/// Keys that are in both collections with the same casing are kept as they are. When several keys
+/// in the first collection only differ in case, the smallest of them is used.
+pub fn align_key_case<V>(first_keys: &[Key], second: IndexMap<Key, V>) -> IndexMap<Key, V> {
+    let mut first_by_lowercase = HashMap::<Key, &Key>::new();
+    for key in first_keys.iter() {
+        let entry = first_by_lowercase.entry(lowercase_key(key)).or_insert(key);
+        if key < *entry {
+            *entry = key;
         }
     }
+    let exact: HashSet<&Key> = first_keys.iter().collect();
+    let second_keys: HashSet<Key> = second.keys().cloned().collect();
 
-    let mut not_found = false;
-    for element in in_both.iter() {
-        if !in_both_wrt_second.contains(element) {
-            not_found = true;
-        }
+    second
+        .into_iter()
+        .map(|(key, val)| {
+            if exact.contains(&key) {
+                return (key, val);
+            }
+            match first_by_lowercase.get(&lowercase_key(&key)) {
+                Some(&first_key) if !second_keys.contains(first_key) => (first_key.clone(), val),
+                _ => (key, val),
+            }
+        })
+        .collect()
+}
+
+/// Where a key is found, and how its values compare, together with the values
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyStatus<'a> {
+    /// The key is only in the first collection
+    OnlyFirst(&'a toml::Value),
+    /// The key is only in the second collection
+    OnlySecond(&'a toml::Value),
+    /// The key is in both collections, with values that differ under the comparator
+    Changed {
+        old: &'a toml::Value,
+        new: &'a toml::Value,
+    },
+    /// The key is in both collections, with values that are equal under the comparator. The value
+    /// is the one in the first collection.
+    Equal(&'a toml::Value),
+}
+
+/// Classify every key in any of the collections
+pub fn classify_keys<'a>(
+    first: &'a IndexMap<Key, toml::Value>,
+    second: &'a IndexMap<Key, toml::Value>,
+    cmp: &dyn ValueComparator,
+) -> HashMap<Key, KeyStatus<'a>> {
+    let mut statuses = HashMap::<Key, KeyStatus<'a>>::new();
+    for (key, first_val) in first.iter() {
+        let status = match second.get(key) {
+            Some(second_val) if cmp.equal(key, first_val, second_val) => {
+                KeyStatus::Equal(first_val)
+            }
+            Some(second_val) => KeyStatus::Changed {
+                old: first_val,
+                new: second_val,
+            },
+            None => KeyStatus::OnlyFirst(first_val),
+        };
+        statuses.insert(key.clone(), status);
     }
-    for element in in_both_wrt_second.iter() {
-        if !in_both.contains(element) {
-            not_found = true;
+    for (key, second_val) in second.iter() {
+        if !first.contains_key(key) {
+            statuses.insert(key.clone(), KeyStatus::OnlySecond(second_val));
         }
     }
+    statuses
+}
 
-    if not_found {
-        return Err(anyhow!("ERROR: Asymmetric comparison"));
-    }
-
-    Ok(KeyOrigins::new(&in_first_only, &in_second_only, &in_both))
+/// Find the keys that are in both collections, but with values that differ under the comparator
+///
+/// The keys are returned sorted.
+pub fn changed_keys(
+    first: &IndexMap<Key, toml::Value>,
+    second: &IndexMap<Key, toml::Value>,
+    cmp: &dyn ValueComparator,
+) -> Vec<Key> {
+    let mut changed: Vec<Key> = classify_keys(first, second, cmp)
+        .into_iter()
+        .filter_map(|(key, status)| match status {
+            KeyStatus::Changed { .. } => Some(key),
+            _ => None,
+        })
+        .collect();
+    changed.sort();
+    changed
 }
 
 /// Exclude keys from the input key list.
@@ -104,40 +276,196 @@ pub fn compare_vectors<T: Eq + Clone>(first: &[T], second: &[T]) -> Result<KeyOr
 /// which is interpreted to be equal to the above keys example.
 ///
 /// This function filters every entry that has one (or more) of the exclude keys as part of its
-/// key.
-pub fn filter_keys(keys: &[Key], blackstr: Option<String>) -> Vec<Vec<String>> {
+/// key. The keys are matched on whole key levels, so "key1" excludes ["key0", "key1"] but not
+/// ["mykey1"], and "key2.key3" excludes keys where "key3" directly follows "key2". A "*" level
+/// matches exactly one key level, and a "**" level matches any number of key levels, so
+/// "key2.*.key4" excludes ["key2", "key3", "key4"] but not ["key2", "key3", "key5", "key4"].
+///
+/// How the keys are matched can be changed with `options`, see `MatchOptions`.
+pub fn filter_keys(
+    keys: &[Key],
+    blackstr: Option<String>,
+    options: &MatchOptions,
+) -> Vec<Vec<String>> {
     let mut included_keys = Vec::<Key>::new();
 
-    match blackstr {
-        Some(val) => {
-            let blacklist: Vec<String> = val.split(',').map(String::from).collect();
-
-            for key in keys.iter() {
-                let mut include_key = true;
-                let key_str = key.iter().join(".");
-                for blacklisted_key in blacklist.iter() {
-                    if key_str.contains(blacklisted_key) {
-                        include_key = false;
-                    }
-                }
-                if include_key {
-                    included_keys.push(key.to_vec());
-                }
-            }
+    for key in keys.iter() {
+        if matching_pattern(key, blackstr.as_deref(), options).is_none() {
+            included_keys.push(key.to_vec());
         }
-        None => {
-            for key in keys.iter() {
-                included_keys.push(key.to_vec());
-            }
+    }
+    included_keys
+}
+
+/// Exclude the keys from the input key list whose dotted key, like "key2.key3", matches any of the
+/// regexes
+pub fn filter_keys_regex(keys: &[Key], regexes: &[Regex]) -> Vec<Vec<String>> {
+    keys.iter()
+        .filter(|key| matching_regex(key, regexes).is_none())
+        .cloned()
+        .collect()
+}
+
+/// The first of the regexes that matches the dotted key, if any
+pub fn matching_regex<'a>(key: &[String], regexes: &'a [Regex]) -> Option<&'a Regex> {
+    let key_str = key.join(".");
+    regexes.iter().find(|regex| regex.is_match(&key_str))
+}
+
+/// Exclude the keys from the input key list that are in any of the comma separated sections.
+///
+/// A section like "key1.key2", which may also be written as a table header "[key1.key2]", holds
+/// the keys that start with the levels of the section, so "key1" excludes ["key1", "key2"] and
+/// ["key1"], but not ["key0", "key1"] or ["key1_x"], and "key1.key2" excludes ["key1", "key2",
+/// "key3"] but not ["key1", "key3"]. Unlike the exclude keys of `filter_keys`, a section only
+/// matches from the top level. The sections may have "*" and "**" wildcard levels.
+pub fn filter_keys_section(keys: &[Key], sections: Option<&str>) -> Vec<Vec<String>> {
+    keys.iter()
+        .filter(|key| matching_section(key, sections).is_none())
+        .cloned()
+        .collect()
+}
+
+/// The first of the comma separated sections that holds the key, like a section in
+/// `filter_keys_section`, if any
+pub fn matching_section(key: &[String], sections: Option<&str>) -> Option<String> {
+    sections?
+        .split(',')
+        .find(|section| {
+            let section = section.trim_start_matches('[').trim_end_matches(']');
+            let levels: Vec<&str> = section.split('.').collect();
+            matches_levels(&levels, key, &MatchOptions::default())
+        })
+        .map(String::from)
+}
+
+/// Keep only the keys from the input key list that match the keys in whitestr.
+///
+/// whitestr has the same format as blackstr in `filter_keys`, and a key is kept if it would be
+/// excluded by `filter_keys` with whitestr as blackstr. If whitestr is None, all keys are kept.
+pub fn filter_keys_include(keys: &[Key], whitestr: Option<String>) -> Vec<Vec<String>> {
+    let mut included_keys = Vec::<Key>::new();
+
+    for key in keys.iter() {
+        if whitestr.is_none()
+            || matching_pattern(key, whitestr.as_deref(), &MatchOptions::default()).is_some()
+        {
+            included_keys.push(key.to_vec());
         }
     }
     included_keys
 }
 
+/// How the keys of a pattern like "key1.key2" are matched against the levels of a key
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MatchOptions {
+    /// Only match a pattern against the whole key, so "key1.key2" matches ["key1", "key2"], but
+    /// not ["key0", "key1", "key2"] or ["key1", "key2", "key3"]
+    pub anchored: bool,
+    /// Match the levels regardless of case, so "key1" matches ["KEY1"]
+    pub ignore_case: bool,
+}
+
+/// Check if the levels of a pattern match the first levels of the key, or all of them if the
+/// match is anchored, where "*" matches exactly one level, and "**" matches any number of levels
+fn matches_levels(levels: &[&str], key: &[String], options: &MatchOptions) -> bool {
+    match (levels.split_first(), key.split_first()) {
+        (None, _) => !options.anchored || key.is_empty(),
+        (Some((&"**", rest)), _) => {
+            (0..=key.len()).any(|skip| matches_levels(rest, &key[skip..], options))
+        }
+        (Some(_), None) => false,
+        (Some((&level, rest)), Some((first, key_rest))) => {
+            let equal = if options.ignore_case {
+                level.to_lowercase() == first.to_lowercase()
+            } else {
+                level == first
+            };
+            (level == "*" || equal) && matches_levels(rest, key_rest, options)
+        }
+    }
+}
+
+/// Check if the levels of a dotted pattern like "key1.key2" are consecutive levels of the key, or
+/// all levels of the key if the match is anchored, where the pattern may have "*" and "**"
+/// wildcard levels
+fn contains_levels(key: &[String], pattern: &str, options: &MatchOptions) -> bool {
+    let levels: Vec<&str> = pattern.split('.').collect();
+    if options.anchored {
+        return matches_levels(&levels, key, options);
+    }
+    (0..key.len()).any(|start| matches_levels(&levels, &key[start..], options))
+}
+
+/// The first of the comma separated keys in patterns that matches the key, like an exclude key in
+/// `filter_keys`, if any
+pub fn matching_pattern(
+    key: &[String],
+    patterns: Option<&str>,
+    options: &MatchOptions,
+) -> Option<String> {
+    patterns?
+        .split(',')
+        .find(|pattern| contains_levels(key, pattern, options))
+        .map(String::from)
+}
+
+/// Which keys are compared, by the include keys and the exclude keys, regexes and sections, in the
+/// formats of `filter_keys_include`, `filter_keys`, `filter_keys_regex` and `filter_keys_section`
+///
+/// A key is checked against all of them at once, so a collection can be filtered in a single pass.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyFilter<'a> {
+    pub include: Option<&'a str>,
+    pub exclude: Option<&'a str>,
+    /// How the exclude keys are matched
+    pub exclude_match: &'a MatchOptions,
+    pub exclude_regexes: &'a [Regex],
+    pub exclude_sections: Option<&'a str>,
+}
+
+impl KeyFilter<'_> {
+    /// Check if a key is compared, which it is if it is matched by an include key, when there are
+    /// any, and not excluded
+    pub fn is_compared(&self, key: &[String]) -> bool {
+        let included = self.include.is_none()
+            || matching_pattern(key, self.include, &MatchOptions::default()).is_some();
+        included && self.exclusion(key).is_none()
+    }
+
+    /// The exclude key, the exclude regex, or the exclude section that excludes a key, if any
+    pub fn exclusion(&self, key: &[String]) -> Option<String> {
+        matching_pattern(key, self.exclude, self.exclude_match)
+            .or_else(|| matching_regex(key, self.exclude_regexes).map(|regex| regex.to_string()))
+            .or_else(|| matching_section(key, self.exclude_sections))
+    }
+}
+
+/// The comma separated keys in patterns that match none of the keys, in the order of patterns,
+/// like misspelled exclude keys
+pub fn unused_patterns(
+    keys: &[Key],
+    patterns: Option<&str>,
+    options: &MatchOptions,
+) -> Vec<String> {
+    patterns
+        .into_iter()
+        .flat_map(|patterns| patterns.split(','))
+        .filter(|pattern| {
+            !keys
+                .iter()
+                .any(|key| contains_levels(key, pattern, options))
+        })
+        .map(String::from)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::value_handling::ExactComparator;
+
     #[test]
     fn test_compare_vectors() {
         let v1 = vec![1, 2, 3, 4, 5, 6];
@@ -150,11 +478,155 @@ mod tests {
                 assert_eq!(vec![4, 5, 6], result.both());
             }
             Err(_) => {
-                assert!(false);
+                panic!("Comparison failed");
             }
         }
     }
 
+    #[test]
+    fn test_align_key_case() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_keys = vec![key("Database.Host"), key("Database.Port"), key("name")];
+        let second: IndexMap<Key, toml::Value> = vec![
+            (key("database.host"), toml::Value::Integer(1)),
+            (key("database.port"), toml::Value::Integer(2)),
+            (key("Database.Port"), toml::Value::Integer(3)),
+            (key("other"), toml::Value::Integer(4)),
+        ]
+        .into_iter()
+        .collect();
+
+        let aligned = align_key_case(&first_keys, second);
+        let second_keys: Vec<Key> = aligned.keys().cloned().collect();
+        let mut key_origins = compare_vectors(&first_keys, &second_keys).unwrap().both();
+        key_origins.sort();
+        assert_eq!(
+            vec![key("Database.Host"), key("Database.Port")],
+            key_origins
+        );
+        assert_eq!(
+            Some(&toml::Value::Integer(1)),
+            aligned.get(&key("Database.Host"))
+        );
+        // An exact match is preferred to a match ignoring case
+        assert_eq!(
+            Some(&toml::Value::Integer(3)),
+            aligned.get(&key("Database.Port"))
+        );
+        assert_eq!(
+            Some(&toml::Value::Integer(2)),
+            aligned.get(&key("database.port"))
+        );
+        assert_eq!(Some(&toml::Value::Integer(4)), aligned.get(&key("other")));
+    }
+
+    #[test]
+    fn test_compare_vectors_order() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first = vec![
+            key("zeta"),
+            key("alpha"),
+            key("table.b"),
+            key("mid"),
+            key("table.a"),
+        ];
+        let second = vec![
+            key("table.a"),
+            key("omega"),
+            key("beta"),
+            key("mid"),
+            key("table.b"),
+        ];
+
+        let result = compare_vectors(&first, &second).unwrap();
+        assert_eq!(vec![key("zeta"), key("alpha")], result.first_only());
+        assert_eq!(vec![key("omega"), key("beta")], result.second_only());
+        assert_eq!(
+            vec![key("table.b"), key("mid"), key("table.a")],
+            result.both()
+        );
+    }
+
+    #[test]
+    fn test_compare_three_way() {
+        let key = |k: &str| vec![String::from(k)];
+        let collection = |entries: &[(&str, i64)]| -> IndexMap<Key, toml::Value> {
+            entries
+                .iter()
+                .map(|(k, v)| (key(k), toml::Value::Integer(*v)))
+                .collect()
+        };
+        let base = collection(&[
+            ("same", 1),
+            ("ours", 1),
+            ("theirs", 1),
+            ("both", 1),
+            ("x", 1),
+        ]);
+        let ours = collection(&[
+            ("same", 1),
+            ("ours", 2),
+            ("theirs", 1),
+            ("both", 2),
+            ("x", 2),
+        ]);
+        let theirs = collection(&[
+            ("same", 1),
+            ("ours", 1),
+            ("theirs", 2),
+            ("both", 2),
+            ("new", 1),
+        ]);
+
+        let origins = compare_three_way(&base, &ours, &theirs, &ExactComparator);
+        assert_eq!(vec![key("ours")], origins.ours());
+        assert_eq!(vec![key("theirs"), key("new")], origins.theirs());
+        assert_eq!(vec![key("both")], origins.both());
+        // Changed by us, and removed by them
+        assert_eq!(vec![key("x")], origins.conflicts());
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let mut first = IndexMap::<Key, toml::Value>::new();
+        let mut second = IndexMap::<Key, toml::Value>::new();
+        first.insert(vec![String::from("a")], toml::Value::Integer(1));
+        second.insert(vec![String::from("a")], toml::Value::Integer(1));
+        first.insert(vec![String::from("b")], toml::Value::Integer(1));
+        second.insert(vec![String::from("b")], toml::Value::Integer(2));
+        first.insert(
+            vec![String::from("c")],
+            toml::Value::String(String::from("Value")),
+        );
+        second.insert(
+            vec![String::from("c")],
+            toml::Value::String(String::from("value")),
+        );
+        first.insert(vec![String::from("first_only")], toml::Value::Integer(1));
+        second.insert(vec![String::from("second_only")], toml::Value::Integer(2));
+
+        assert_eq!(
+            vec![vec![String::from("b")], vec![String::from("c")]],
+            changed_keys(&first, &second, &ExactComparator)
+        );
+
+        struct IgnoreCase;
+        impl ValueComparator for IgnoreCase {
+            fn equal(&self, _key: &[String], first: &toml::Value, second: &toml::Value) -> bool {
+                match (first, second) {
+                    (toml::Value::String(a), toml::Value::String(b)) => {
+                        a.to_lowercase() == b.to_lowercase()
+                    }
+                    _ => first == second,
+                }
+            }
+        }
+        assert_eq!(
+            vec![vec![String::from("b")]],
+            changed_keys(&first, &second, &IgnoreCase)
+        );
+    }
+
     #[test]
     fn test_filter_keys_1() {
         let keys = vec![
@@ -167,7 +639,7 @@ mod tests {
             ],
         ];
         let blackstr = None;
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
         let correct = vec![
             vec![String::from("key1")],
             vec![String::from("key2"), String::from("key3")],
@@ -192,7 +664,7 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("key1"));
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
         let correct = vec![
             vec![String::from("key2"), String::from("key3")],
             vec![
@@ -216,7 +688,7 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("key3"));
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
         let correct = vec![
             vec![String::from("key1")],
             vec![
@@ -240,9 +712,8 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("key"));
-        let test = filter_keys(&keys, blackstr);
-        let correct = Vec::<Vec<String>>::new();
-        assert_eq!(correct, test);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
+        assert_eq!(keys, test);
     }
 
     #[test]
@@ -257,7 +728,7 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("ke.y1"));
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
         let correct = vec![
             vec![String::from("key1")],
             vec![String::from("key2"), String::from("key3")],
@@ -287,7 +758,7 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("key2.key3"));
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
         let correct = vec![
             vec![String::from("key1")],
             vec![
@@ -316,8 +787,238 @@ mod tests {
             ],
         ];
         let blackstr = Some(String::from("key2.key3.key4"));
-        let test = filter_keys(&keys, blackstr);
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
+        let correct = vec![
+            vec![String::from("key1")],
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key4"),
+                String::from("key5"),
+                String::from("key6"),
+            ],
+        ];
+        assert_eq!(correct, test);
+    }
+
+    #[test]
+    fn test_filter_keys_8() {
+        let keys = vec![
+            vec![String::from("key1")],
+            vec![String::from("mykey1")],
+            vec![String::from("key0"), String::from("key1")],
+            vec![String::from("key1"), String::from("api_key")],
+            vec![String::from("key2"), String::from("key10")],
+        ];
+        let blackstr = Some(String::from("key1"));
+        let test = filter_keys(&keys, blackstr, &MatchOptions::default());
+        let correct = vec![
+            vec![String::from("mykey1")],
+            vec![String::from("key2"), String::from("key10")],
+        ];
+        assert_eq!(correct, test);
+    }
+
+    #[test]
+    fn test_unused_patterns() {
+        let keys: Vec<Key> = vec![
+            vec![String::from("name")],
+            vec![String::from("server"), String::from("port")],
+        ];
+        assert!(unused_patterns(&keys, None, &MatchOptions::default()).is_empty());
+        assert!(unused_patterns(&keys, Some("name,server.*"), &MatchOptions::default()).is_empty());
+        assert_eq!(
+            vec![String::from("nmae"), String::from("port.server")],
+            unused_patterns(
+                &keys,
+                Some("nmae,port,port.server"),
+                &MatchOptions::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_filter_keys_match_options() {
+        let keys: Vec<Key> = vec![
+            "server.port",
+            "Server.Host",
+            "db.server.port",
+            "server.port.x",
+        ]
+        .into_iter()
+        .map(|key| key.split('.').map(String::from).collect())
+        .collect();
+        let remaining = |pattern: &str, options: MatchOptions| -> Vec<String> {
+            filter_keys(&keys, Some(String::from(pattern)), &options)
+                .iter()
+                .map(|key| key.join("."))
+                .collect()
+        };
+
+        let unanchored = MatchOptions::default();
+        assert_eq!(vec!["Server.Host"], remaining("server.port", unanchored));
+        let anchored = MatchOptions {
+            anchored: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec!["Server.Host", "db.server.port", "server.port.x"],
+            remaining("server.port", anchored)
+        );
+        assert_eq!(
+            vec!["server.port", "Server.Host", "db.server.port"],
+            remaining("server.**.x", anchored)
+        );
+        assert_eq!(
+            vec!["Server.Host", "server.port.x"],
+            remaining("**.port", anchored)
+        );
+
+        assert_eq!(keys.len(), remaining("SERVER.HOST", unanchored).len());
+        let ignore_case = MatchOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec!["server.port", "db.server.port", "server.port.x"],
+            remaining("SERVER.HOST", ignore_case)
+        );
+        assert_eq!(vec!["Server.Host"], remaining("SERVER.PORT", ignore_case));
+        let both = MatchOptions {
+            anchored: true,
+            ignore_case: true,
+        };
+        assert_eq!(
+            vec!["Server.Host", "db.server.port", "server.port.x"],
+            remaining("SERVER.PORT", both)
+        );
+    }
+
+    #[test]
+    fn test_filter_keys_section() {
+        let keys: Vec<Key> = vec![
+            "database.host",
+            "database.port",
+            "database_url",
+            "app.database.host",
+            "database",
+        ]
+        .into_iter()
+        .map(|key| key.split('.').map(String::from).collect())
+        .collect();
+        let remaining = |sections: &str| -> Vec<String> {
+            filter_keys_section(&keys, Some(sections))
+                .iter()
+                .map(|key| key.join("."))
+                .collect()
+        };
+        let remaining_exclude = |pattern: &str| -> Vec<String> {
+            filter_keys(&keys, Some(String::from(pattern)), &MatchOptions::default())
+                .iter()
+                .map(|key| key.join("."))
+                .collect()
+        };
+
+        // A section only matches from the top level, while an exclude key matches at any level
+        assert_eq!(
+            vec!["database_url", "app.database.host"],
+            remaining("database")
+        );
+        assert_eq!(vec!["database_url"], remaining_exclude("database"));
+        assert_eq!(remaining("database"), remaining("[database]"));
+
+        // A subpath only holds the keys under it
+        assert_eq!(
+            vec![
+                "database.port",
+                "database_url",
+                "app.database.host",
+                "database"
+            ],
+            remaining("database.host")
+        );
+        assert_eq!(
+            vec!["database.port", "database_url", "database"],
+            remaining_exclude("database.host")
+        );
+
+        assert_eq!(vec!["database_url"], remaining("database,app"));
+        assert_eq!(
+            Some(String::from("[database]")),
+            matching_section(&keys[0], Some("app,[database]"))
+        );
+        assert_eq!(None, matching_section(&keys[2], Some("app,[database]")));
+        assert_eq!(keys.len(), filter_keys_section(&keys, None).len());
+    }
+
+    #[test]
+    fn test_filter_keys_wildcards() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let keys = vec![
+            key("servers.web.password"),
+            key("servers.web.db.password"),
+            key("servers.web.user"),
+            key("servers"),
+            key("clients.web.password"),
+        ];
+
+        let test = filter_keys(
+            &keys,
+            Some(String::from("servers.*.password")),
+            &MatchOptions::default(),
+        );
+        let correct = vec![
+            key("servers.web.db.password"),
+            key("servers.web.user"),
+            key("servers"),
+            key("clients.web.password"),
+        ];
+        assert_eq!(correct, test);
+
+        let test = filter_keys(
+            &keys,
+            Some(String::from("servers.**")),
+            &MatchOptions::default(),
+        );
+        assert_eq!(vec![key("clients.web.password")], test);
+
+        let test = filter_keys(
+            &keys,
+            Some(String::from("servers.**.password")),
+            &MatchOptions::default(),
+        );
+        let correct = vec![
+            key("servers.web.user"),
+            key("servers"),
+            key("clients.web.password"),
+        ];
+        assert_eq!(correct, test);
+    }
+
+    #[test]
+    fn test_filter_keys_regex() {
+        let keys = vec![
+            vec![String::from("api_secret")],
+            vec![String::from("db"), String::from("user")],
+            vec![String::from("db"), String::from("pass")],
+            vec![String::from("db"), String::from("host")],
+            vec![String::from("dbxuser")],
+        ];
+        let regexes = vec![
+            Regex::new(".*secret.*").unwrap(),
+            Regex::new(r"db\.(user|pass)").unwrap(),
+        ];
+        let test = filter_keys_regex(&keys, &regexes);
         let correct = vec![
+            vec![String::from("db"), String::from("host")],
+            vec![String::from("dbxuser")],
+        ];
+        assert_eq!(correct, test);
+        assert_eq!(keys, filter_keys_regex(&keys, &[]));
+    }
+
+    #[test]
+    fn test_filter_keys_include_1() {
+        let keys = vec![
             vec![String::from("key1")],
             vec![String::from("key2"), String::from("key3")],
             vec![
@@ -326,6 +1027,163 @@ mod tests {
                 String::from("key6"),
             ],
         ];
+        let whitestr = None;
+        let test = filter_keys_include(&keys, whitestr);
+        assert_eq!(keys, test);
+    }
+
+    #[test]
+    fn test_filter_keys_include_2() {
+        let keys = vec![
+            vec![String::from("key1")],
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key4"),
+                String::from("key5"),
+                String::from("key6"),
+            ],
+        ];
+        let whitestr = Some(String::from("key1"));
+        let test = filter_keys_include(&keys, whitestr);
+        let correct = vec![vec![String::from("key1")]];
         assert_eq!(correct, test);
     }
+
+    #[test]
+    fn test_filter_keys_include_3() {
+        let keys = vec![
+            vec![String::from("key1")],
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key4"),
+                String::from("key5"),
+                String::from("key6"),
+            ],
+        ];
+        let whitestr = Some(String::from("key3,key5"));
+        let test = filter_keys_include(&keys, whitestr);
+        let correct = vec![
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key4"),
+                String::from("key5"),
+                String::from("key6"),
+            ],
+        ];
+        assert_eq!(correct, test);
+    }
+
+    #[test]
+    fn test_filter_keys_include_4() {
+        let keys = vec![
+            vec![String::from("key1")],
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key2"),
+                String::from("key3"),
+                String::from("key4"),
+            ],
+            vec![
+                String::from("key4"),
+                String::from("key5"),
+                String::from("key6"),
+            ],
+        ];
+        let whitestr = Some(String::from("key2.key3"));
+        let test = filter_keys_include(&keys, whitestr);
+        let correct = vec![
+            vec![String::from("key2"), String::from("key3")],
+            vec![
+                String::from("key2"),
+                String::from("key3"),
+                String::from("key4"),
+            ],
+        ];
+        assert_eq!(correct, test);
+    }
+
+    #[test]
+    fn test_matching_pattern() {
+        let key = vec![String::from("key2"), String::from("key3")];
+        assert_eq!(None, matching_pattern(&key, None, &MatchOptions::default()));
+        assert_eq!(
+            None,
+            matching_pattern(&key, Some("key1"), &MatchOptions::default())
+        );
+        assert_eq!(
+            Some(String::from("key3")),
+            matching_pattern(&key, Some("key1,key3,key2"), &MatchOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_classify_keys() {
+        let key = |k: &str| vec![String::from(k)];
+        let first: IndexMap<Key, toml::Value> = vec![
+            (key("equal"), toml::Value::Integer(1)),
+            (key("changed"), toml::Value::Integer(1)),
+            (key("first_only"), toml::Value::Integer(1)),
+        ]
+        .into_iter()
+        .collect();
+        let second: IndexMap<Key, toml::Value> = vec![
+            (key("equal"), toml::Value::Integer(1)),
+            (key("changed"), toml::Value::Integer(2)),
+            (key("second_only"), toml::Value::Integer(2)),
+        ]
+        .into_iter()
+        .collect();
+
+        let statuses = classify_keys(&first, &second, &ExactComparator);
+        assert_eq!(4, statuses.len());
+        assert_eq!(
+            Some(&KeyStatus::Equal(&toml::Value::Integer(1))),
+            statuses.get(&key("equal"))
+        );
+        assert_eq!(
+            Some(&KeyStatus::Changed {
+                old: &toml::Value::Integer(1),
+                new: &toml::Value::Integer(2),
+            }),
+            statuses.get(&key("changed"))
+        );
+        assert_eq!(
+            Some(&KeyStatus::OnlyFirst(&toml::Value::Integer(1))),
+            statuses.get(&key("first_only"))
+        );
+        assert_eq!(
+            Some(&KeyStatus::OnlySecond(&toml::Value::Integer(2))),
+            statuses.get(&key("second_only"))
+        );
+    }
+
+    #[test]
+    fn test_key_filter() {
+        let exclude_regexes = vec![Regex::new("^secret").unwrap()];
+        let filter = KeyFilter {
+            include: Some("db,server"),
+            exclude: Some("port"),
+            exclude_match: &MatchOptions::default(),
+            exclude_regexes: &exclude_regexes,
+            exclude_sections: Some("server.tls"),
+        };
+        let key = |dotted: &str| dotted.split('.').map(String::from).collect::<Key>();
+
+        assert!(filter.is_compared(&key("db.host")));
+        assert!(!filter.is_compared(&key("cache.host")));
+        assert_eq!(
+            Some(String::from("port")),
+            filter.exclusion(&key("db.port"))
+        );
+        assert_eq!(
+            Some(String::from("server.tls")),
+            filter.exclusion(&key("server.tls.cert"))
+        );
+        assert_eq!(
+            Some(String::from("^secret")),
+            filter.exclusion(&key("secret.db"))
+        );
+        assert!(!filter.is_compared(&key("secret.db")));
+        assert_eq!(None, filter.exclusion(&key("cache.host")));
+    }
 }
@@ -0,0 +1,277 @@
+//! Compare toml files key by key
+//!
+//! The files are flattened into collections of their innermost values, keyed by the path of keys
+//! leading to each value. The keys of the two collections are then split into the keys only in
+//! the first file, only in the second file, and in both, where the values of the keys in both can
+//! be compared with a `Comparison`.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let result = difftoml::diff_files(
+//!     Path::new("first.toml"),
+//!     Path::new("second.toml"),
+//!     &difftoml::DiffOptions::default(),
+//! )?;
+//! for key in result.key_origins.first_only() {
+//!     println!("Only in first.toml: {}", key.join("."));
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::path::Path;
+
+use anyhow::{anyhow, Error};
+use indexmap::IndexMap;
+use regex::Regex;
+
+pub mod array_diff;
+pub mod canonical;
+pub mod directives;
+pub mod drift;
+pub mod html;
+pub mod json;
+pub mod key_handling;
+pub mod lines;
+pub mod merge;
+pub mod parse;
+pub mod report;
+pub mod schema;
+pub mod side_by_side;
+pub mod tap;
+pub mod three_way;
+pub mod toml_patch;
+pub mod unified;
+pub mod value_handling;
+
+pub use key_handling::{compare_vectors, filter_keys, Key, KeyFilter, KeyOrigins, MatchOptions};
+pub use parse::{parse_toml, parse_toml_str};
+
+use parse::InputFormat;
+use value_handling::{Comparison, ValueComparator};
+
+/// Which keys to compare, and how to compare their values
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Comma separated list of keys to limit the diff to, in the format of `filter_keys`
+    pub include: Option<String>,
+    /// Comma separated list of keys to exclude from the diff, in the format of `filter_keys`
+    pub exclude: Option<String>,
+    /// How the exclude keys are matched
+    pub exclude_match: MatchOptions,
+    /// Regexes of dotted keys to exclude from the diff
+    pub exclude_regexes: Vec<Regex>,
+    /// Comma separated list of sections to exclude from the diff, in the format of
+    /// `filter_keys_section`
+    pub exclude_sections: Option<String>,
+    /// Exclude the keys with a value in either file that is written as this text, as given by
+    /// `value_handling::written_as`
+    pub exclude_value: Option<String>,
+    /// Match keys that only differ in case, with the casing of the first file
+    pub ignore_key_case: bool,
+    /// How to compare the values of keys in both files
+    pub comparison: Comparison,
+}
+
+impl DiffOptions {
+    /// Filter of the keys that are compared, by the include and exclude options
+    pub fn key_filter(&self) -> KeyFilter<'_> {
+        KeyFilter {
+            include: self.include.as_deref(),
+            exclude: self.exclude.as_deref(),
+            exclude_match: &self.exclude_match,
+            exclude_regexes: &self.exclude_regexes,
+            exclude_sections: self.exclude_sections.as_deref(),
+        }
+    }
+}
+
+/// The flattened files, and the keys that are compared, split by the files they are in
+pub struct DiffResult {
+    /// Innermost values of the first file, including the keys that are not compared
+    pub first: IndexMap<Key, toml::Value>,
+    /// Innermost values of the second file, including the keys that are not compared
+    pub second: IndexMap<Key, toml::Value>,
+    /// The compared keys, split into the keys only in the first file, only in the second file,
+    /// and in both
+    pub key_origins: KeyOrigins<Key>,
+    /// How the values of keys in both files are compared
+    pub comparison: Comparison,
+}
+
+impl DiffResult {
+    /// Keys in both files with unequal values, in the order of the first file
+    pub fn unequal(&self) -> Vec<Key> {
+        self.key_origins
+            .both()
+            .into_iter()
+            .filter(|key| {
+                !self
+                    .comparison
+                    .equal(key, &self.first[key], &self.second[key])
+            })
+            .collect()
+    }
+
+    /// Check if any compared key is only in one of the files, or has unequal values
+    pub fn has_differences(&self) -> bool {
+        !self.key_origins.first_only().is_empty()
+            || !self.key_origins.second_only().is_empty()
+            || !self.unequal().is_empty()
+    }
+}
+
+/// Compare two flattened files
+pub fn diff_collections(
+    first: IndexMap<Key, toml::Value>,
+    second: IndexMap<Key, toml::Value>,
+    options: &DiffOptions,
+) -> Result<DiffResult, Error> {
+    let second = if options.ignore_key_case {
+        let first_keys: Vec<Key> = first.keys().cloned().collect();
+        key_handling::align_key_case(&first_keys, second)
+    } else {
+        second
+    };
+
+    // The keys are filtered in a single pass, so only the compared keys are copied
+    let filter = options.key_filter();
+    let compared = |collection: &IndexMap<Key, toml::Value>| -> Vec<Key> {
+        collection
+            .keys()
+            .filter(|key| filter.is_compared(key))
+            .cloned()
+            .collect()
+    };
+    let mut key_origins = compare_vectors(&compared(&first), &compared(&second))?;
+    if let Some(text) = &options.exclude_value {
+        key_origins.retain(|key| {
+            !first
+                .get(key)
+                .into_iter()
+                .chain(second.get(key))
+                .any(|val| value_handling::written_as(val, text))
+        });
+    }
+
+    Ok(DiffResult {
+        first,
+        second,
+        key_origins,
+        comparison: options.comparison.clone(),
+    })
+}
+
+/// Compare two files, which may be toml, YAML or JSON files, as given by their extensions
+pub fn diff_files(first: &Path, second: &Path, options: &DiffOptions) -> Result<DiffResult, Error> {
+    let parse = |path: &Path| {
+        let format = InputFormat::from_path(path)
+            .ok_or_else(|| anyhow!("Path is not a toml, YAML or JSON file: {}", path.display()))?;
+        parse::parse_input(path, format)
+    };
+    diff_collections(parse(first)?, parse(second)?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_files() {
+        let options = DiffOptions {
+            exclude: Some(String::from("field3")),
+            ..Default::default()
+        };
+        let result = diff_files(
+            Path::new("assets/test_1.toml"),
+            Path::new("assets/test_2.toml"),
+            &options,
+        )
+        .expect("Could not diff files");
+
+        assert!(result.has_differences());
+        assert!(result
+            .key_origins
+            .first_only()
+            .iter()
+            .chain(result.key_origins.second_only().iter())
+            .chain(result.key_origins.both().iter())
+            .all(|key| !key.contains(&String::from("field3"))));
+        for key in result.unequal() {
+            assert_ne!(result.first[&key], result.second[&key]);
+        }
+
+        let same = diff_files(
+            Path::new("assets/test_1.toml"),
+            Path::new("assets/test_1.toml"),
+            &DiffOptions::default(),
+        )
+        .expect("Could not diff files");
+        assert!(!same.has_differences());
+    }
+
+    #[test]
+    fn test_diff_collections_exclude_value() {
+        let first = parse::parse_toml_str(
+            "name = \"TODO\"\nport = 80\nhost = \"a\"\nowner = \"TODO\"\nold = \"TODO\"",
+        )
+        .unwrap();
+        let second = parse::parse_toml_str(
+            "name = \"first\"\nport = 8080\nhost = \"TODO\"\nowner = \"TODO\"\nnew = \"TODO\"",
+        )
+        .unwrap();
+        let key = |s: &str| vec![String::from(s)];
+
+        let result =
+            diff_collections(first.clone(), second.clone(), &DiffOptions::default()).unwrap();
+        assert_eq!(
+            vec![key("name"), key("port"), key("host")],
+            result.unequal()
+        );
+
+        let options = DiffOptions {
+            exclude_value: Some(String::from("TODO")),
+            ..Default::default()
+        };
+        let result = diff_collections(first, second, &options).unwrap();
+        assert_eq!(vec![key("port")], result.unequal());
+        assert!(result.key_origins.first_only().is_empty());
+        assert!(result.key_origins.second_only().is_empty());
+        assert_eq!(vec![key("port")], result.key_origins.both());
+    }
+
+    #[test]
+    fn test_diff_files_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("difftoml_gzip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Could not create the directory");
+        let compressed = dir.join("test_1.toml.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&std::fs::read("assets/test_1.toml").expect("Could not read the file"))
+            .expect("Could not compress the file");
+        std::fs::write(
+            &compressed,
+            encoder.finish().expect("Could not compress the file"),
+        )
+        .expect("Could not write the file");
+
+        let options = DiffOptions::default();
+        let plain = Path::new("assets/test_1.toml");
+        let same = diff_files(plain, &compressed, &options);
+        let against_second = diff_files(&compressed, Path::new("assets/test_2.toml"), &options);
+        let plain_against_second = diff_files(plain, Path::new("assets/test_2.toml"), &options);
+        std::fs::remove_dir_all(&dir).expect("Could not remove the directory");
+
+        assert!(!same.expect("Could not diff files").has_differences());
+        let against_second = against_second.expect("Could not diff files");
+        let plain_against_second = plain_against_second.expect("Could not diff files");
+        assert_eq!(
+            plain_against_second.key_origins.first_only(),
+            against_second.key_origins.first_only()
+        );
+        assert_eq!(plain_against_second.unequal(), against_second.unequal());
+    }
+}
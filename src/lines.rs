@@ -0,0 +1,127 @@
+//! Line numbers of the keys of toml files
+//!
+//! The toml parser does not keep the positions of the keys, so they are found with a textual pass
+//! over the file, like the directives. A key is on the line of its key/value pair, or of its table
+//! header. Keys in inline tables and arrays are not on lines of their own, and have the line of
+//! the closest enclosing key that has one.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Error;
+
+use crate::directives;
+use crate::key_handling::Key;
+use crate::parse;
+
+/// Line numbers, counted from 1, of the keys of a file
+pub type LineNumbers = HashMap<Key, usize>;
+
+/// Line numbers of the keys of the two compared files
+#[derive(Default)]
+pub struct SourceLines {
+    pub first: LineNumbers,
+    pub second: LineNumbers,
+}
+
+impl SourceLines {
+    /// Describe where a key is in the two files, like ` (line 42)`, or an empty string if it is in
+    /// none of them
+    pub fn suffix(&self, key: &[String]) -> String {
+        match (line_of(&self.first, key), line_of(&self.second, key)) {
+            (Some(first), Some(second)) if first != second => {
+                format!(" (lines {} and {})", first, second)
+            }
+            (Some(line), _) | (None, Some(line)) => format!(" (line {})", line),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Find the line numbers of the keys of a toml string
+pub fn parse_str(content: &str) -> LineNumbers {
+    let mut lines = LineNumbers::new();
+    let mut table = Key::new();
+    let mut in_multiline_string = false;
+
+    for (index, line) in content.lines().enumerate() {
+        // Multi-line strings may contain anything, including lines that look like keys
+        let delimiters = line.matches("\"\"\"").count() + line.matches("'''").count();
+        if in_multiline_string {
+            in_multiline_string = delimiters % 2 == 0;
+            continue;
+        }
+        in_multiline_string = delimiters % 2 == 1;
+
+        let line = line.trim();
+        let key = if let Some(header) = directives::header_key(line) {
+            table = header.clone();
+            Some(header)
+        } else {
+            directives::line_key(line).map(|key| table.iter().cloned().chain(key).collect())
+        };
+        if let Some(key) = key {
+            lines.entry(key).or_insert(index + 1);
+        }
+    }
+    lines
+}
+
+/// Find the line numbers of the keys of a toml file
+pub fn parse_file(path: &Path) -> Result<LineNumbers, Error> {
+    Ok(parse_str(&parse::read_file_to_string(path)?))
+}
+
+/// The line number of a key, or of the closest enclosing key with a line number
+pub fn line_of(lines: &LineNumbers, key: &[String]) -> Option<usize> {
+    (1..=key.len())
+        .rev()
+        .find_map(|len| lines.get(&key[..len]).copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.split('/').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_str() {
+        let content = r#"name = "first"
+
+[server]
+"host.name" = "localhost"
+tls = { enabled = true }
+text = """
+port = 1
+"""
+port = 80
+
+[[items]]
+id = 1
+"#;
+        let lines = parse_str(content);
+
+        assert_eq!(Some(1), line_of(&lines, &key("name")));
+        assert_eq!(Some(3), line_of(&lines, &key("server")));
+        assert_eq!(Some(4), line_of(&lines, &key("server/host.name")));
+        assert_eq!(Some(5), line_of(&lines, &key("server/tls/enabled")));
+        assert_eq!(Some(9), line_of(&lines, &key("server/port")));
+        assert_eq!(Some(11), line_of(&lines, &key("items")));
+        assert_eq!(None, line_of(&lines, &key("missing")));
+    }
+
+    #[test]
+    fn test_suffix() {
+        let source_lines = SourceLines {
+            first: parse_str("a = 1\nb = 2\n"),
+            second: parse_str("b = 3\nc = 4\n"),
+        };
+        assert_eq!(" (line 1)", source_lines.suffix(&key("a")));
+        assert_eq!(" (lines 2 and 1)", source_lines.suffix(&key("b")));
+        assert_eq!(" (line 2)", source_lines.suffix(&key("c")));
+        assert_eq!("", source_lines.suffix(&key("d")));
+    }
+}
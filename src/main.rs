@@ -1,38 +1,678 @@
 //! Display the diff between two toml files
 //!
 use anyhow::{anyhow, Error};
-use clap::{App, Arg};
-use colored::Colorize;
+use clap::{App, AppSettings, Arg, SubCommand};
+use colored::{Color, Colorize};
+use indexmap::IndexMap;
 use itertools::Itertools;
+use regex::Regex;
 use std::{
-    collections::HashMap,
-    ffi::OsStr,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
     path::{Path, PathBuf},
+    process::{self, Command, Stdio},
 };
 
-mod key_handling;
-mod parse;
+use difftoml::array_diff::{self, ArrayChange, IdentitySpec};
+use difftoml::key_handling::{self, Key, KeyFilter, KeyOrigins, MatchOptions};
+use difftoml::lines::{self, LineNumbers, SourceLines};
+use difftoml::parse::{self, InputFormat, SectionError};
+use difftoml::report::{self, DiffReport, DiffStat, TextStyle};
+use difftoml::schema::{self, SchemaReport};
+use difftoml::value_handling::{self, ChangeKind, Comparison, ValueComparator, ValueTransform};
+use difftoml::{
+    canonical, directives, drift, html, json, merge, side_by_side, tap, three_way, toml_patch,
+    unified, DiffOptions, DiffResult,
+};
+
+/// Environment variable holding keys to exclude, in the same format as the `--exclude` option
+const EXCLUDE_ENV_VAR: &str = "DIFFTOML_EXCLUDE";
+
+/// Environment variable that disables colored output when set to a non-empty value, see
+/// https://no-color.org
+const NO_COLOR_ENV_VAR: &str = "NO_COLOR";
+
+/// Environment variable holding the pager of `--paginate`, like `less -R`
+const PAGER_ENV_VAR: &str = "PAGER";
+
+/// Output that is collected, and written through a pager when it is dropped, if it is longer than
+/// the height of the terminal
+///
+/// The pager is `$PAGER`, or `less` otherwise, which is told to pass colors through if `$LESS` is
+/// not set. If the pager can not be started, the output is written directly to stdout.
+struct Pager {
+    buffer: Vec<u8>,
+}
+
+impl Pager {
+    fn new() -> Self {
+        Pager { buffer: Vec::new() }
+    }
+
+    /// Write the collected output through the pager, or directly if it fits in the terminal or the
+    /// pager can not be started
+    fn finish(&mut self) -> io::Result<()> {
+        let content = std::mem::take(&mut self.buffer);
+        let height =
+            terminal_size::terminal_size().map(|(_, terminal_size::Height(height))| height);
+        let lines = content.iter().filter(|byte| **byte == b'\n').count();
+        if height.is_none_or(|height| lines < usize::from(height)) {
+            return io::stdout().write_all(&content);
+        }
+        let pager = env::var(PAGER_ENV_VAR)
+            .ok()
+            .filter(|pager| !pager.trim().is_empty())
+            .unwrap_or_else(|| String::from("less"));
+        let mut words = pager.split_whitespace();
+        let mut command = Command::new(words.next().unwrap_or("less"));
+        command.args(words).stdin(Stdio::piped());
+        if env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        match command.spawn() {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // The pager may be quit before all of the output is written
+                    let _ = stdin.write_all(&content);
+                }
+                child.wait()?;
+                Ok(())
+            }
+            Err(_) => io::stdout().write_all(&content),
+        }
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// The output is only written when the pager is dropped, so it is paged as a whole
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // Errors can not be returned from here, and are as fatal as a closed stdout
+        let _ = self.finish();
+    }
+}
+
+/// Check if the output is paged: with --paginate, when writing to a terminal rather than a file
+fn use_pager(paginate: bool, to_file: bool, is_terminal: bool) -> bool {
+    paginate && !to_file && is_terminal
+}
+
+/// Combine the exclude list given on the command line with the one from the environment
+///
+/// Both are comma separated key lists, and the result is the union of the two. Empty lists are
+/// treated as absent.
+fn merge_excludes(cli: Option<String>, env: Option<String>) -> Option<String> {
+    let lists: Vec<String> = vec![cli, env]
+        .into_iter()
+        .flatten()
+        .filter(|list| !list.is_empty())
+        .collect();
+    if lists.is_empty() {
+        None
+    } else {
+        Some(lists.join(","))
+    }
+}
+
+/// Comma separated key list of the key patterns in the content of an exclude file
+///
+/// The file has one key pattern per line, with the syntax of `-x`. Blank lines and lines starting
+/// with `#` are ignored.
+fn exclude_file_keys(content: &str) -> Option<String> {
+    let keys: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys.join(","))
+    }
+}
+
+/// Name of the project config file with default options, which is looked up in the current
+/// directory and its parents
+const CONFIG_FILE_NAME: &str = ".difftoml.toml";
+
+/// Default options from a project config file, where the options given on the command line take
+/// precedence
+#[derive(Debug, Default, PartialEq)]
+struct Config {
+    /// Comma separated list of keys to exclude, like with `-x`
+    exclude: Option<String>,
+    /// When to use colored output, from `true` for always, `false` for never, or a `--color` value
+    color: Option<ColorChoice>,
+    /// Display the equal values, like with `-e`
+    display_equal: Option<bool>,
+}
+
+impl Config {
+    /// Parse the content of a config file, like
+    ///
+    /// exclude = ["package.version", "dependencies.*.path"]
+    /// color = true
+    /// display_equal = false
+    fn parse(content: &str) -> Result<Self, Error> {
+        let table = content.parse::<toml::Value>().map_err(|msg| anyhow!(msg))?;
+        let mut config = Config::default();
+        for (name, val) in table.as_table().into_iter().flatten() {
+            let invalid = || anyhow!("Invalid value for '{}': {}", name, val);
+            match (name.as_str(), val) {
+                ("exclude", toml::Value::String(key)) => config.exclude = Some(key.clone()),
+                ("exclude", toml::Value::Array(keys)) => {
+                    let keys = keys
+                        .iter()
+                        .map(|key| key.as_str().ok_or_else(invalid))
+                        .collect::<Result<Vec<&str>, Error>>()?;
+                    config.exclude = Some(keys.join(","));
+                }
+                ("color", toml::Value::Boolean(true)) => config.color = Some(ColorChoice::Always),
+                ("color", toml::Value::Boolean(false)) => config.color = Some(ColorChoice::Never),
+                ("color", toml::Value::String(choice)) => match choice.as_str() {
+                    "auto" | "always" | "never" => {
+                        config.color = Some(ColorChoice::from_arg(choice))
+                    }
+                    _ => return Err(invalid()),
+                },
+                ("display_equal", toml::Value::Boolean(display)) => {
+                    config.display_equal = Some(*display)
+                }
+                ("exclude", _) | ("color", _) | ("display_equal", _) => return Err(invalid()),
+                _ => return Err(anyhow!("Unknown option '{}'", name)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Load the config file in the directory or the closest of its parents, if there is any
+    fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file());
+        match path {
+            Some(path) => {
+                let content = parse::read_file_to_string(&path)
+                    .map_err(|err| anyhow!("Could not read {}: {}", path.display(), err))?;
+                Config::parse(&content)
+                    .map_err(|err| anyhow!("Invalid config file {}: {}", path.display(), err))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+/// When to use colored output
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorChoice {
+    /// Color the output if stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Get the choice from a (validated) command line value
+    fn from_arg(val: &str) -> Self {
+        match val {
+            "always" => ColorChoice::Always,
+            "never" => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        }
+    }
+
+    /// Decide if the output should be colored, where `no_color` tells if the `NO_COLOR`
+    /// environment variable is set, which overrides the choice
+    fn use_color(self, is_terminal: bool, no_color: bool) -> bool {
+        if no_color {
+            return false;
+        }
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Order of the entries with unequal values
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum ChangeOrder {
+    /// By the position of the key in the first file
+    #[default]
+    File,
+    /// By key
+    Key,
+    /// Numeric changes by decreasing absolute change, then the other changes by key
+    Delta,
+    /// By the number of key segments, shallow keys first
+    Depth,
+    /// By the table containing the key, top-level keys first
+    Section,
+}
+
+impl ChangeOrder {
+    /// Get the order from a (validated) command line value
+    fn from_arg(val: &str) -> Self {
+        match val {
+            "delta" => ChangeOrder::Delta,
+            "depth" => ChangeOrder::Depth,
+            "section" => ChangeOrder::Section,
+            "key" => ChangeOrder::Key,
+            _ => ChangeOrder::File,
+        }
+    }
+}
+
+/// Format of the output
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// Standalone HTML page
+    Html,
+    /// Test Anything Protocol, with a failing test for each difference
+    Tap,
+    /// JSON document with the entries of each section
+    Json,
+    /// Lines like a unified diff, with a `-` and a `+` line for each change
+    Unified,
+    /// Toml document with the added, removed and changed entries, for applying the changes
+    TomlPatch,
+}
+
+impl OutputFormat {
+    /// Get the format from a (validated) command line value
+    fn from_arg(val: &str) -> Self {
+        match val {
+            "html" => OutputFormat::Html,
+            "tap" => OutputFormat::Tap,
+            "json" => OutputFormat::Json,
+            "unified" => OutputFormat::Unified,
+            "toml-patch" => OutputFormat::TomlPatch,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+/// Options collected from the command line
+#[derive(Clone, Default)]
+struct Args {
+    /// Path to the first toml file
+    first_path: PathBuf,
+    /// Path to the second toml file
+    second_path: PathBuf,
+    /// Name to display for the first file instead of its path
+    first_label: Option<String>,
+    /// Name to display for the second file instead of its path
+    second_label: Option<String>,
+    /// How to parse the first file
+    first_format: InputFormat,
+    /// How to parse the second file
+    second_format: InputFormat,
+    /// Display entries with equal values in both files
+    display_equal: bool,
+    /// Use colored output
+    color: bool,
+    /// Comma separated list of keys to exclude from the diff
+    exclude: Option<String>,
+    /// How the exclude keys are matched
+    exclude_match: MatchOptions,
+    /// Regexes of dotted keys to exclude from the diff
+    exclude_regexes: Vec<Regex>,
+    /// Comma separated list of sections to exclude from the diff, with all keys under them
+    exclude_sections: Option<String>,
+    /// Maximum number of characters of displayed values, where longer values are truncated
+    max_value_width: Option<usize>,
+    /// Exclude the keys with a value in either file that is written as this text
+    exclude_value: Option<String>,
+    /// Match keys that only differ in case
+    ignore_key_case: bool,
+    /// Comma separated list of keys to limit the diff to
+    include: Option<String>,
+    /// Report excluded keys that differ, and fail if there are any
+    audit_excluded: bool,
+    /// Exit with status 0 also when the files differ
+    no_exit_code: bool,
+    /// Report explicitly when no differences are found
+    always_report: bool,
+    /// Arrays of tables to compare element-wise, matched on an identity field
+    array_identities: Vec<IdentitySpec>,
+    /// Algorithm used to compare the elements of the other arrays
+    array_algorithm: array_diff::Algorithm,
+    /// Toml files to merge into the first side of the comparison, in increasing precedence
+    layers: Vec<PathBuf>,
+    /// Toml files to compare the second file against one by one, reporting common changes
+    baselines: Vec<PathBuf>,
+    /// Their toml file, for a three-way diff against the first file as the base
+    third_path: Option<PathBuf>,
+    /// Toml file to write the merge of the two files to, instead of displaying the diff
+    merge: Option<PathBuf>,
+    /// Which file's value to keep for keys with unequal values when merging
+    merge_prefer: merge::Preference,
+    /// Skip sections that can not be parsed instead of failing
+    lenient_parse: bool,
+    /// Report files that can not be parsed instead of failing
+    ignore_parse_errors: bool,
+    /// Group the unequal entries by the kind of their values
+    group_by_type: bool,
+    /// Annotate entries only in one file with keys that have the same value only in the other
+    cross_reference: bool,
+    /// Display the line numbers of the keys in the files
+    show_lines: bool,
+    /// Group the unequal entries by the first capture group of this regex on their keys
+    group_by_regex: Option<Regex>,
+    /// Toml file with the expected keys and value types
+    schema: Option<PathBuf>,
+    /// Check the types of the values against the schema
+    check_types: bool,
+    /// Approved values, for keys where a change to that value should not be reported
+    approved: Option<IndexMap<Key, toml::Value>>,
+    /// Default values, for keys where a missing key should be treated as having the default value
+    defaults: Option<IndexMap<Key, toml::Value>>,
+    /// Check that flattening the input files is lossless instead of comparing them
+    validate_roundtrip: bool,
+    /// Only check that the input files can be parsed instead of comparing them
+    check: bool,
+    /// Print the hashes of the input files
+    show_hashes: bool,
+    /// Order of the entries with unequal values
+    sort_changes_by: ChangeOrder,
+    /// Only show the entries that are only in the second file
+    added_only: bool,
+    /// Only show the entries that are only in the first file
+    removed_only: bool,
+    /// Only show the entries that are in both files
+    changed_only: bool,
+    /// Maximum number of entries to display in total
+    max_entries: Option<usize>,
+    /// Number of sibling keys to display around each unequal value
+    context: usize,
+    /// Percentage of the compared keys to display as siblings around each unequal value, instead
+    /// of a fixed number
+    context_percent: Option<f64>,
+    /// Display tables only found in one file as a single line
+    collapse: bool,
+    /// Summarize the entries with equal values in a single line
+    show_common_summary: bool,
+    /// Let local dates equal datetimes at midnight on the same date
+    lenient_datetime: bool,
+    /// Let offset datetimes equal other offset datetimes denoting the same instant
+    normalize_datetime: bool,
+    /// Maximum absolute difference between floats that are considered equal
+    float_tolerance: Option<f64>,
+    /// Let integers equal floats with the same numeric value
+    numeric_loose: bool,
+    /// Rewrite of the values of both files before the comparison
+    value_transform: Option<ValueTransform>,
+    /// Format of the output
+    format: OutputFormat,
+    /// File to write the diff to, instead of stdout
+    output: Option<PathBuf>,
+    /// Write the output through a pager, when it is longer than the terminal
+    paginate: bool,
+    /// Open the files in the editor at their first difference after the diff
+    edit: bool,
+    /// Number of files to diff at once when comparing directories, or one per CPU if not given
+    threads: Option<usize>,
+    /// Total width of the rows, if the text output is in columns
+    side_by_side: Option<usize>,
+    /// Separator between the levels of displayed keys, instead of '.'
+    key_separator: Option<String>,
+    /// Only print the number of entries in each section
+    stat: bool,
+    /// Print the number of entries in each section on stderr after the diff
+    summary_line: bool,
+    /// Print nothing, and only report whether the files differ with the exit status
+    quiet: bool,
+    /// Compare `# name: value` comments above keys as metadata of the keys
+    parse_comment_directives: bool,
+    /// Only display unequal values where the value in the second file is this value
+    became: Option<toml::Value>,
+    /// Lowercase names of the value types whose changes are not displayed
+    ignore_types: Vec<String>,
+    /// Key to trace through the filters instead of displaying the diff
+    explain_exclusions: Option<Key>,
+    /// Print the first file in canonical form instead of displaying the diff
+    canonicalize: Option<canonical::Options>,
+}
+
+/// Which sections of the diff to display
+#[derive(Debug, PartialEq)]
+struct Sections {
+    first_only: bool,
+    second_only: bool,
+    both: bool,
+}
+
+impl Args {
+    /// Separator between the levels of displayed keys
+    fn key_separator(&self) -> &str {
+        self.key_separator.as_deref().unwrap_or(".")
+    }
+
+    /// A value as it is displayed, truncated to the maximum width, if any
+    fn value_text(&self, value: &toml::Value) -> String {
+        report::truncate(value.to_string(), self.max_value_width)
+    }
+
+    /// How keys and values are written in the reports of the other modes than the diff
+    fn text_style(&self) -> TextStyle {
+        TextStyle {
+            color: self.color,
+            key_separator: String::from(self.key_separator()),
+            max_value_width: self.max_value_width,
+        }
+    }
+
+    /// A key as it is displayed, with its levels joined by the key separator
+    fn display_key(&self, key: &[String]) -> String {
+        key.iter().join(self.key_separator())
+    }
+
+    /// Which keys to compare, and how to compare their values
+    fn diff_options(&self) -> DiffOptions {
+        DiffOptions {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            exclude_match: self.exclude_match,
+            exclude_regexes: self.exclude_regexes.clone(),
+            exclude_sections: self.exclude_sections.clone(),
+            exclude_value: self.exclude_value.clone(),
+            ignore_key_case: self.ignore_key_case,
+            comparison: self.comparison(),
+        }
+    }
+
+    /// How to compare values
+    fn comparison(&self) -> Comparison {
+        Comparison {
+            lenient_datetime: self.lenient_datetime,
+            normalize_datetime: self.normalize_datetime,
+            unordered_arrays: self.array_algorithm == array_diff::Algorithm::Set,
+            float_tolerance: self.float_tolerance,
+            numeric_loose: self.numeric_loose,
+        }
+    }
+
+    /// Where to write the output: the file given by --output, the pager of --paginate, or stdout
+    fn output(&self) -> Result<Box<dyn Write>, Error> {
+        Ok(match &self.output {
+            Some(path) => {
+                Box::new(BufWriter::new(File::create(path).map_err(|msg| {
+                    anyhow!("Could not write {}: {}", path.display(), msg)
+                })?))
+            }
+            None if self.paginate => Box::new(Pager::new()),
+            None => Box::new(io::stdout()),
+        })
+    }
+
+    /// Where to write the reports besides the diff, like the audit of the excluded keys: along
+    /// with the text output, and to stderr with the other formats, which they would make
+    /// unparseable
+    fn report_output<'a>(&self, out: &'a mut dyn Write) -> Box<dyn Write + 'a> {
+        if self.format == OutputFormat::Text {
+            Box::new(out)
+        } else {
+            Box::new(io::stderr())
+        }
+    }
+
+    /// The sections of the diff to display
+    fn sections(&self) -> Sections {
+        Sections {
+            first_only: !self.added_only && !self.changed_only && self.became.is_none(),
+            second_only: !self.removed_only && !self.changed_only && self.became.is_none(),
+            both: !self.added_only && !self.removed_only,
+        }
+    }
+
+    /// Filter of the keys that are compared, by the include and exclude options
+    fn key_filter(&self) -> KeyFilter<'_> {
+        KeyFilter {
+            include: self.include.as_deref(),
+            exclude: self.exclude.as_deref(),
+            exclude_match: &self.exclude_match,
+            exclude_regexes: &self.exclude_regexes,
+            exclude_sections: self.exclude_sections.as_deref(),
+        }
+    }
+
+    /// Check if a key is compared, as given by `KeyFilter::is_compared`
+    fn is_compared(&self, key: &[String]) -> bool {
+        self.key_filter().is_compared(key)
+    }
+
+    /// The exclude key, the exclude regex, or the exclude section that excludes a key, if any
+    fn exclusion(&self, key: &[String]) -> Option<String> {
+        self.key_filter().exclusion(key)
+    }
+
+    /// The exclude value that excludes a key, if either of its values is written as it, as given
+    /// by `value_handling::written_as`
+    fn value_exclusion(
+        &self,
+        first_val: Option<&toml::Value>,
+        second_val: Option<&toml::Value>,
+    ) -> Option<&str> {
+        let text = self.exclude_value.as_deref()?;
+        first_val
+            .into_iter()
+            .chain(second_val)
+            .any(|val| value_handling::written_as(val, text))
+            .then_some(text)
+    }
+
+    /// Check if the second value of a key is approved
+    fn is_approved(&self, key: &[String], second_val: &toml::Value) -> bool {
+        self.approved
+            .as_ref()
+            .and_then(|approved| approved.get(key))
+            == Some(second_val)
+    }
+
+    /// Name of the first side of the comparison, for display
+    fn first_name(&self) -> String {
+        if let Some(label) = &self.first_label {
+            label.clone()
+        } else if self.layers.is_empty() {
+            self.first_path.display().to_string()
+        } else {
+            self.layers
+                .iter()
+                .map(|layer| layer.display().to_string())
+                .join(" + ")
+        }
+    }
 
-use key_handling::{Key, KeyOrigins};
+    /// Name of the second side of the comparison, for display
+    fn second_name(&self) -> String {
+        match &self.second_label {
+            Some(label) => label.clone(),
+            None => self.second_path.display().to_string(),
+        }
+    }
+}
 
-fn input_args() -> Result<(PathBuf, PathBuf, bool, bool, Option<String>), Error> {
+fn input_args() -> Result<Args, Error> {
     let matches = App::new("difftoml")
         .version("0.2.0")
         .author("Ole-Johan Skrede")
         .about("Diplay the difference between two toml files")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("canonicalize")
+                .about("Print a toml file in a canonical form, for comparison with text diff tools")
+                .long_about(
+                "Print a toml file in a canonical form, for comparison with text diff tools. \n\
+                Files with the same content have the same canonical form, regardless of \n\
+                formatting, key order, and whether tables are written as headers, inline \n\
+                tables, or dotted keys. The keys are always sorted.")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("TOML FILE")
+                        .help("Toml file to canonicalize")
+                        .takes_value(true)
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("sort_arrays")
+                        .long("sort-arrays")
+                        .help("Sort the elements of arrays, for arrays where the order does not \
+                               matter")
+                        .takes_value(false)
+                )
+                .arg(
+                    Arg::with_name("float_precision")
+                        .long("float-precision")
+                        .value_name("DECIMALS")
+                        .help("Round floats to this number of decimals")
+                        .takes_value(true)
+                )
+        )
         .arg(
             Arg::with_name("first")
                 .value_name("TOML FILE")
-                .help("First toml file")
+                .help("First toml file, or directory of toml files")
                 .takes_value(true)
                 .required(true)
         )
         .arg(
             Arg::with_name("second")
                 .value_name("TOML FILE")
-                .help("Second toml file")
+                .help("Second toml file, or directory of toml files when the first is a directory")
                 .takes_value(true)
-                .required(true)
+                .required_unless_one(&["layers", "baselines", "validate_roundtrip"])
+        )
+        .arg(
+            Arg::with_name("third")
+                .value_name("TOML FILE")
+                .help("Their toml file, for a three-way diff where the first file is the common \
+                       base and the second file is ours")
+                .long_help(
+                "Their toml file, for a three-way diff where the first file is the common base, \n\
+                and the second file is ours. The keys changed from the base only in our file, \n\
+                only in their file, and in both files, are listed, with the conflicts, where \n\
+                the files changed a key to different values, in red. Adding and removing a key \n\
+                count as changes. The exit status is 1 if there are conflicts.")
+                .takes_value(true)
+                .conflicts_with_all(&["layers", "baselines", "validate_roundtrip"])
         )
         .arg(
             Arg::with_name("exclude")
@@ -46,12 +686,105 @@ fn input_args() -> Result<(PathBuf, PathBuf, bool, bool, Option<String>), Error>
                 period mark '.' (without whitespace) to describe key-level hierarchy \n\
                 Usage: \n\
                 \t -x key1  // Excludes all entries which has 'key1' as a key somewhere in \n\
-                \t          // its key hierarchy. E.g. 'key1' or 'key0.key1.key2' or \n\
-                \t          // 'containskey1inside', but not key0.ke.y1key2'. \n\
+                \t          // its key hierarchy. E.g. 'key1' or 'key0.key1.key2', but not \n\
+                \t          // 'mykey1' or 'key0.key10'. \n\
                 \t -x key1.key2  // Excludes all entries which has 'key2' directly after 'key1' \n\
                 \t               // somewhere in its key hierarchy. E.g. 'key1.key2' or \n\
                 \t               // 'key0.key1.key2' but not 'key0.key1.key3.key2'. \n\
-                \t -x key1,key2.key3 // A union of the above two behaviours.")
+                \t -x key1,key2.key3 // A union of the above two behaviours. \n\
+                \t -x key1.*.key2  // '*' matches exactly one level, e.g. 'key1.key0.key2' \n\
+                \t                 // but not 'key1.key0.key3.key2'. \n\
+                \t -x key1.**  // '**' matches any number of levels, e.g. everything under \n\
+                \t             // 'key1'. \n\
+                Keys listed in the DIFFTOML_EXCLUDE environment variable (same syntax) are \n\
+                excluded as well, in union with the keys given here.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("exclude_value")
+                .long("exclude-value")
+                .value_name("VALUE")
+                .help("Exclude the keys with this value in either file, like placeholders. A \
+                       string value matches its content, like TODO for \"TODO\", and other \
+                       values match their toml representation, like false.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("exclude_anchored")
+                .long("exclude-anchored")
+                .help("Only exclude keys that are matched as a whole by an exclude key, so \
+                       'key1.key2' excludes 'key1.key2', but not 'key0.key1.key2' or \
+                       'key1.key2.key3'. Use '**' levels to match more.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("exclude_ignore_case")
+                .long("exclude-ignore-case")
+                .help("Match the exclude keys regardless of case, so 'key1' excludes 'Key1'")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("exclude_file")
+                .long("exclude-file")
+                .value_name("PATH")
+                .help("File with keys to ignore in the diff, one per line, with the syntax of \
+                       --exclude. Blank lines and lines starting with '#' are ignored. The keys \
+                       are excluded in union with the keys given with --exclude.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("no_exit_code")
+                .long("no-exit-code")
+                .help("Exit with status 0 also when the files differ. Failed checks, like \
+                       --audit-excluded and --schema, still give status 1.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("audit_excluded")
+                .long("audit-excluded")
+                .help("After the diff, report the excluded keys that differ between the files, \
+                       and exit with a non-zero status if there are any.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("exclude_regex")
+                .long("exclude-regex")
+                .value_name("REGEX")
+                .help("Exclude the keys where the dotted key, like 'key1.key2', matches the regex. \
+                       Can be given several times.")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("exclude_section")
+                .long("exclude-section")
+                .value_name("SECTIONS")
+                .help("Exclude whole sections, with every key under them, like 'database' or \
+                       '[database]' for 'database.host' and 'database.port'. Unlike -x, a \
+                       section only matches from the top level. Separate sections with commas.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("ignore_key_case")
+                .long("ignore-key-case")
+                .help("Match keys that only differ in case, like 'Database.Host' and \
+                       'database.host'. The keys are displayed as in the first file.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("include")
+                .short("i")
+                .long("include")
+                .value_name("KEY LIST")
+                .help("Only show the diff for these key(s)")
+                .long_help(
+                "Specify a single key or a list of keys that you want to limit the diff to, \n\
+                with the same syntax and matching as for --exclude. The keys are included \n\
+                before the exclude keys are excluded. \n\
+                Usage: \n\
+                \t -i key1 -x key1.key2  // Compare the entries with 'key1' in their key \n\
+                \t                       // hierarchy, except those with 'key1.key2'")
                 .takes_value(true)
         )
         .arg(
@@ -64,163 +797,3967 @@ fn input_args() -> Result<(PathBuf, PathBuf, bool, bool, Option<String>), Error>
         )
         .arg(
             Arg::with_name("color")
-                .short("c")
                 .long("color")
-                .help("Toggle this if you want colored output")
+                .value_name("WHEN")
+                .help("When to use colored output")
+                .long_help(
+                "When to use colored output. With 'auto', the output is colored only if it is \n\
+                written to a terminal. Use 'always' to keep the colors when piping the output, \n\
+                e.g. to 'less -R'. The output is never colored when the NO_COLOR environment \n\
+                variable is set to a non-empty value.")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("color_always")
+                .short("c")
+                .help("Same as --color always")
                 .takes_value(false)
         )
-        .get_matches();
-
-    // Gets a value for config if supplied by user, or defaults to "default.conf"
-    let first_path = Path::new(matches.value_of("first").unwrap_or(""));
-    let second_path = Path::new(matches.value_of("second").unwrap_or(""));
-    let display_equal = matches.is_present("display_equal");
-    let color = matches.is_present("color");
-    let exclude = matches.value_of("exclude").map(String::from);
-
-    if !first_path.exists() {
-        return Err(anyhow!("Path does not exist: {}", first_path.display()));
-    }
-    if !second_path.exists() {
-        return Err(anyhow!("Path does not exist: {}", second_path.display()));
-    }
-
-    if first_path.extension().unwrap_or_else(|| OsStr::new("")) != "toml" {
-        return Err(anyhow!("Path is not a toml file: {}", first_path.display()));
-    }
-    if second_path.extension().unwrap_or_else(|| OsStr::new("")) != "toml" {
-        return Err(anyhow!(
-            "Path is not a toml file: {}",
-            second_path.display()
-        ));
-    }
-
-    Ok((
-        first_path.to_path_buf(),
-        second_path.to_path_buf(),
-        display_equal,
-        color,
-        exclude,
-    ))
-}
-
-fn display(
-    first_path: &Path,
-    second_path: &Path,
-    first_collection: &HashMap<Vec<String>, toml::Value>,
-    second_collection: &HashMap<Vec<String>, toml::Value>,
-    key_origins: &KeyOrigins<Key>,
-    display_equal: bool,
-    color: bool,
-) {
-    if !key_origins.first_only().is_empty() {
-        if color {
-            let output = format!("\n{}", first_path.display());
-            println!("{}", output.blue());
-        } else {
-            println!("\nEntries only found in {}", first_path.display());
-        }
-        for key in key_origins.first_only().iter() {
-            match first_collection.get(key) {
-                Some(val) => {
-                    println!("{}: {}", key.iter().join("."), val);
-                }
-                None => unreachable!(),
-            }
-        }
-    }
-
-    if !key_origins.second_only().is_empty() {
-        if color {
-            let output = format!("\n{}", second_path.display());
-            println!("{}", output.yellow());
-        } else {
-            println!("\nEntries only found in {}", second_path.display());
-        }
-        for key in key_origins.second_only().iter() {
-            match second_collection.get(key) {
-                Some(val) => {
-                    println!("{}: {}", key.iter().join("."), val);
-                }
-                None => unreachable!(),
-            }
-        }
-    }
-
-    if !key_origins.both().is_empty() {
-        for key in key_origins.both().iter() {
-            let first_val = match first_collection.get(key) {
-                Some(val) => val,
-                None => unreachable!(),
-            };
-            let second_val = match second_collection.get(key) {
-                Some(val) => val,
-                None => unreachable!(),
-            };
-            if first_val != second_val {
-                if color {
-                    let output = key.iter().join(".");
-                    println!("\n{}", output.red());
-                    println!("{} {}", "<".blue(), first_val);
-                    println!("{} {}", ">".yellow(), second_val);
-                } else {
-                    println!("\nUnequal value for key '{}'", key.iter().join("."));
-                    println!("< {}", first_val);
-                    println!("> {}", second_val);
-                }
-            }
-        }
-
-        if display_equal {
-            for key in key_origins.both().iter() {
-                let first_val = match first_collection.get(key) {
-                    Some(val) => val,
-                    None => unreachable!(),
-                };
-                let second_val = match second_collection.get(key) {
-                    Some(val) => val,
-                    None => unreachable!(),
-                };
-                if first_val == second_val {
-                    if color {
-                        let output = key.iter().join(".");
-                        println!("\n{}", output.green());
-                        println!("{} {}", "<".blue(), first_val);
-                        println!("{} {}", ">".yellow(), second_val);
-                    } else {
-                        println!("\nEqual value for key '{}'", key.iter().join("."));
-                        println!("< {}", first_val);
-                        println!("> {}", second_val);
-                    }
-                }
-            }
-        }
-    }
-}
-
-fn main() -> Result<(), Error> {
-    let (first_path, second_path, display_equal, color, exclude) = input_args()?;
-
-    let first_collection = parse::parse_toml(&first_path)?;
-    let second_collection = parse::parse_toml(&second_path)?;
-
-    let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
-    let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
-
-    let first_keys = key_handling::filter_keys(&first_keys, exclude.clone());
-    let second_keys = key_handling::filter_keys(&second_keys, exclude);
-
-    let key_origins = key_handling::compare_vectors(&first_keys, &second_keys)?;
-
-    display(
-        &first_path,
-        &second_path,
-        &first_collection,
-        &second_collection,
-        &key_origins,
-        display_equal,
-        color,
-    );
-
-    Ok(())
+        .arg(
+            Arg::with_name("always_report")
+                .long("always-report")
+                .help("Print a message saying that the files are identical when no differences \
+                       are found.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("array_smart")
+                .long("array-smart")
+                .value_name("KEY=FIELD LIST")
+                .help("Compare arrays of tables element-wise")
+                .long_help(
+                "Compare the arrays of tables under the given keys element by element, instead \n\
+                of as a whole. The tables are matched on the value of the given identity field, \n\
+                and reported as added, removed, moved, or with unequal fields. Use a comma mark \n\
+                ',' (without whitespace) to give several arrays. This overrides --array-algorithm \n\
+                for the given keys. \n\
+                Usage: \n\
+                \t --array-smart servers=name  // Match the tables in 'servers' on 'name'")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("array_algorithm")
+                .long("array-algorithm")
+                .value_name("ALGORITHM")
+                .help("Algorithm used to compare the elements of unequal arrays")
+                .long_help(
+                "Algorithm used to compare the elements of unequal arrays. \n\
+                \t positional   // Compare the elements at each index, and report elements past \n\
+                \t              // the end of the shorter array as added or removed \n\
+                \t set          // Ignore the order of the elements, and report elements \n\
+                \t              // occurring more often in one array as added or removed. \n\
+                \t              // Arrays with the same elements in any order are equal, \n\
+                \t              // also when nested in other arrays or tables. \n\
+                \t lcs          // Align the elements on a longest common subsequence, and \n\
+                \t              // report the other elements as added or removed \n\
+                \t keyed:FIELD  // Match the tables of arrays of tables on the value of FIELD, \n\
+                \t              // and report them as added, removed, moved, or with unequal \n\
+                \t              // fields. Arrays of other values are compared as a whole.")
+                .default_value("positional")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("ignore_array_order")
+                .long("ignore-array-order")
+                .help("Treat arrays with the same elements in any order as equal. Same as \
+                       --array-algorithm set.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("layers")
+                .long("layers")
+                .value_name("TOML FILES")
+                .help("Compare the merge of several layered toml files against a single file")
+                .long_help(
+                "Merge the given toml files from left to right into an effective config, and \n\
+                compare it against a single target file. Later files override earlier ones: \n\
+                scalar values are overwritten, tables are merged key by key, and arrays are \n\
+                replaced as a whole. Separate the layers from the target with '--'. \n\
+                Usage: \n\
+                \t --layers base.toml env.toml local.toml -- target.toml")
+                .takes_value(true)
+                .multiple(true)
+                .conflicts_with("second")
+        )
+        .arg(
+            Arg::with_name("lenient_parse")
+                .long("lenient-parse")
+                .help("Compare the parts of the files that can be parsed, and report the \
+                       sections that can not.")
+                .takes_value(false)
+                .conflicts_with("layers")
+        )
+        .arg(
+            Arg::with_name("ignore_parse_errors")
+                .long("ignore-parse-errors")
+                .help("Report the input files that fail to parse, instead of failing with an \
+                       error, and exit with status 3. When comparing directories, the remaining \
+                       files are still compared.")
+                .takes_value(false)
+                .conflicts_with("lenient_parse")
+        )
+        .arg(
+            Arg::with_name("baselines")
+                .long("baselines")
+                .value_name("TOML FILE LIST")
+                .help("Compare a single file against several baselines, and separate the changes \
+                       from all baselines from the changes from only some of them")
+                .long_help(
+                "Compare a single target file against each of the given baseline files. Changes \n\
+                from every baseline, like systematic drift, are displayed first, followed by \n\
+                the other changes from each baseline. Use a comma mark ',' (without \n\
+                whitespace) to separate the baselines. \n\
+                Usage: \n\
+                \t --baselines b1.toml,b2.toml,b3.toml target.toml")
+                .takes_value(true)
+                .conflicts_with_all(&["second", "layers"])
+        )
+        .arg(
+            Arg::with_name("group_by_type")
+                .long("group-by-type")
+                .help("Group the entries with unequal values by the type of the values, e.g. \
+                       all string changes together.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("cross_reference")
+                .long("cross-reference")
+                .help("Annotate each entry only found in one file with the keys only found in \
+                       the other file that have the same value, which may be the same entry \
+                       under a new key.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("show_lines")
+                .long("show-lines")
+                .help("Display the line numbers where the keys are defined, like 'db.host (line \
+                       42)'. Keys in inline tables get the line of the enclosing key, and the \
+                       lines are only found for toml files.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("group_by_regex")
+                .long("group-by-regex")
+                .value_name("REGEX")
+                .help("Group the entries with unequal values by the first capture group of a \
+                       regex on their keys")
+                .long_help(
+                "Group the entries with unequal values by the first capture group of a regex on \n\
+                their keys, written with periods between the key levels. The groups are sorted \n\
+                by the captured text, and entries with keys that do not match are grouped last, \n\
+                under 'other'. \n\
+                Usage: \n\
+                \t --group-by-regex '^services\\.([^.]+)'  // Group by service name")
+                .takes_value(true)
+                .conflicts_with("group_by_type")
+        )
+        .arg(
+            Arg::with_name("schema")
+                .long("schema")
+                .value_name("TOML FILE")
+                .help("Report keys in the input files that are not in this schema file")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("check_types")
+                .long("check-types")
+                .help("Also report values with another type than the value with the same key in \
+                       the schema. Exits with a non-zero status on type mismatches.")
+                .takes_value(false)
+                .requires("schema")
+        )
+        .arg(
+            Arg::with_name("approved")
+                .long("approved")
+                .value_name("TOML FILE")
+                .help("Toml file with approved values. Unequal values where the value in the \
+                       second file is exactly the approved value for that key are not shown.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("defaults_file")
+                .long("defaults-file")
+                .value_name("TOML FILE")
+                .help("Toml file with default values. A key only found in one of the files, with \
+                       a value equal to its default, is treated as having the default value in \
+                       the other file, so it is equal rather than added or removed.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("became")
+                .long("became")
+                .value_name("TOML VALUE")
+                .help("Only display unequal values where the value in the second file is the \
+                       given toml value, with the same type")
+                .long_help(
+                "Only display unequal values where the value in the second file is the given \n\
+                toml value. The type must match too, so 1 does not match 1.0. Keys only in \n\
+                one of the files are not displayed. \n\
+                Usage: \n\
+                \t --became false      // Find everything that was disabled \n\
+                \t --became '\"debug\"'  // Find everything that was set to the string debug")
+                .conflicts_with_all(&["added_only", "removed_only", "display_equal"])
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("ignore_types")
+                .long("ignore-types")
+                .value_name("TYPE LIST")
+                .help("Comma separated list of value types, like 'string,array', whose changes \
+                       are not displayed. A change is only left out if both values are of \
+                       ignored types.")
+                .possible_values(value_handling::TYPE_NAMES)
+                .use_delimiter(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("explain_exclusions")
+                .long("explain-exclusions")
+                .value_name("KEY")
+                .help("Explain whether and why a key is left out of the diff, instead of \
+                       displaying the diff")
+                .long_help(
+                "Trace a key, like 'key2.key3', through each stage of the comparison, and \n\
+                report whether it is left out of the diff, and by which option. The diff itself \n\
+                is not displayed.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("parse_first")
+                .long("parse-first")
+                .value_name("FORMAT")
+                .help("Parse the first file in this format, regardless of its extension")
+                .possible_values(InputFormat::NAMES)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("parse_second")
+                .long("parse-second")
+                .value_name("FORMAT")
+                .help("Parse the second file in this format, regardless of its extension")
+                .possible_values(InputFormat::NAMES)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("validate_roundtrip")
+                .long("validate-roundtrip")
+                .help("Instead of comparing, check that flattening each given file and \
+                       rebuilding it gives back the same content. Exits with a non-zero status \
+                       if it does not.")
+                .takes_value(false)
+                .conflicts_with_all(&["layers", "lenient_parse"])
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Instead of comparing, check that both input files can be parsed. Prints \
+                       nothing and exits with status 0 if they can, and fails with the parse \
+                       error if they can not.")
+                .takes_value(false)
+                .conflicts_with_all(&["validate_roundtrip", "lenient_parse", "third"])
+        )
+        .arg(
+            Arg::with_name("show_hashes")
+                .long("show-hashes")
+                .help("Print the sha256 hash of each input file before the diff")
+                .takes_value(false)
+                .conflicts_with("layers")
+        )
+        .arg(
+            Arg::with_name("sort_changes_by")
+                .long("sort-changes-by")
+                .value_name("ORDER")
+                .help("Order of the entries with unequal values")
+                .long_help(
+                "Order of the entries with unequal values. Entries that compare equal in the \n\
+                chosen order are sorted by key. \n\
+                \t file     // As in the first file \n\
+                \t key      // By key \n\
+                \t delta    // Numeric values by decreasing absolute change, then the rest by key \n\
+                \t depth    // By key depth, shallow keys first \n\
+                \t section  // By the table containing the key, top-level keys first")
+                .possible_values(&["file", "key", "delta", "depth", "section"])
+                .default_value("file")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("added_only")
+                .long("added-only")
+                .help("Only show the entries that are only found in the second file")
+                .takes_value(false)
+                .conflicts_with("removed_only")
+        )
+        .arg(
+            Arg::with_name("removed_only")
+                .long("removed-only")
+                .help("Only show the entries that are only found in the first file")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("changed_only")
+                .long("changed-only")
+                .help("Only show the entries with unequal values, and not the entries only found \
+                       in one of the files. Entries with equal values are still shown with -e.")
+                .takes_value(false)
+                .conflicts_with_all(&["added_only", "removed_only"])
+        )
+        .arg(
+            Arg::with_name("max_entries")
+                .long("max-entries")
+                .value_name("N")
+                .help("Display at most N entries in total, and the number of omitted entries per \
+                       section. The entries are taken in display order, so the entries only in \
+                       the first file come first, then those only in the second file, those \
+                       with unequal values, after they are sorted, and those with equal values.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .value_name("N")
+                .help("Display up to N sibling keys, in the same table, with their values in the \
+                       first file, below each unequal value. The closest siblings in the first \
+                       file are shown.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("context_percent")
+                .long("context-percent")
+                .value_name("P")
+                .help("Display sibling keys like --context, where the number of siblings is P \
+                       percent of the compared keys in the first file, and at least 1 and at most \
+                       20, so small files get more context in proportion than large files.")
+                .takes_value(true)
+                .conflicts_with("context")
+        )
+        .arg(
+            Arg::with_name("collapse")
+                .long("collapse")
+                .help("Display each table that is only found in one of the files as a single \
+                       line with its number of keys, like '[db.*] (12 keys)', instead of listing \
+                       its keys.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("show_common_summary")
+                .long("show-common-summary")
+                .help("Print the number of keys with equal values, and the number of top-level \
+                       sections they are in, instead of listing them.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("lenient_datetime")
+                .long("lenient-datetime")
+                .help("Treat a local date as equal to a local or offset datetime at midnight on \
+                       the same date.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("normalize_datetime")
+                .long("normalize-datetime")
+                .help("Treat offset datetimes as equal when they denote the same instant, like \
+                       1979-05-27T07:32:00Z and 1979-05-27T00:32:00-07:00. Datetimes without an \
+                       offset are compared as they are written.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("float_tolerance")
+                .long("float-tolerance")
+                .value_name("EPS")
+                .help("Treat floats as equal when their absolute difference is at most EPS, \
+                       also when nested in arrays and tables.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("numeric_loose")
+                .long("numeric-loose")
+                .help("Treat an integer and a float as equal when they have the same numeric \
+                       value, like 30 and 30.0.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("parse_comment_directives")
+                .long("parse-comment-directives")
+                .help("Compare structured comments above keys as metadata of the keys")
+                .long_help(
+                "Compare structured comments above keys as metadata of the keys. A comment line \
+                 on the form '# name: value', directly above a key or a table header, is \
+                 compared as the string value of the key extended with '#name'. The name \
+                 consists of letters, digits, '-' and '_'. A blank line or another comment \
+                 discards the directives above it.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("transform")
+                .long("transform")
+                .value_name("NAME=KEY LIST")
+                .help("Rewrite the string values of the given keys in both files before comparing")
+                .long_help(
+                "Rewrite the string values of the given keys in both files before comparing \n\
+                them. Use a comma mark ',' (without whitespace) to give several transforms, \n\
+                which are applied in the given order. The transforms are \n\
+                \t lowercase  // Convert to lowercase \n\
+                \t trim       // Remove leading and trailing whitespace \n\
+                \t sort-csv   // Sort the comma separated items, and remove the whitespace \n\
+                \t            // around them \n\
+                Usage: \n\
+                \t --transform lowercase=server.host,sort-csv=tags")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("merge")
+                .long("merge")
+                .value_name("OUTFILE")
+                .help("Write the merge of the two files to OUTFILE instead of displaying the diff")
+                .long_help(
+                "Write the merge of the two files to OUTFILE instead of displaying the diff. \n\
+                The merge has the keys of both files, and for keys with unequal values, the \n\
+                value of the file given by --merge-prefer.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("merge_prefer")
+                .long("merge-prefer")
+                .value_name("FILE")
+                .help("Which file's value to keep for keys with unequal values when merging \
+                       [default: first]")
+                .possible_values(&["first", "second"])
+                .requires("merge")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Print nothing, and only exit with 1 if the files differ, and 0 otherwise")
+                .conflicts_with_all(&[
+                    "no_exit_code",
+                    "merge",
+                    "explain_exclusions",
+                    "stat",
+                    "show_hashes",
+                    "validate_roundtrip",
+                    "baselines",
+                    "third",
+                    "audit_excluded",
+                    "schema",
+                    "summary_line",
+                ])
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("summary_line")
+                .long("summary-line")
+                .help("After the diff, print the number of keys added in and removed from the \
+                       second file, with changed values, and with equal values to stderr, like \
+                       'difftoml: added=3 removed=1 changed=5 equal=120'.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("side_by_side")
+                .long("side-by-side")
+                .help("Display the entries only in one of the files, and with unequal values, in \
+                       aligned columns with the key, the value in the first file, and the value \
+                       in the second file. Long keys and values are truncated.")
+                .takes_value(false)
+                .conflicts_with("stat")
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("N")
+                .help("Total width of the rows of --side-by-side, of at least 20 characters")
+                .default_value("80")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("key_separator")
+                .long("key-separator")
+                .value_name("SEP")
+                .help("Separator between the levels of the displayed keys, like '/' for keys \
+                       like 'db/host'. Keys given as options are still separated by '.'.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("stat")
+                .long("stat")
+                .help("Only print the number of keys only in the first file, only in the second \
+                       file, with changed values, and with equal values. Combine with \
+                       --format json for a JSON object with the counts.")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Format of the output")
+                .long_help(
+                "Format of the output. \n\
+                \t text  // Human-readable text \n\
+                \t html  // Standalone HTML page with a color-coded table of the entries \n\
+                \t tap   // Test Anything Protocol, with a failing test for each difference, and \n\
+                \t       // a passing test for each equal value with -e \n\
+                \t json  // JSON object with the entries only in the first file, only in the \n\
+                \t       // second file, with unequal values, and with equal values with -e \n\
+                \t unified  // Lines '-key = value' for values only in or changed from the \n\
+                \t          // first file, and '+key = value' for values only in or changed \n\
+                \t          // in the second file, sorted by key \n\
+                \t toml-patch  // Toml document with the tables added, removed, and changed \n\
+                \t             // with the old and new values, keyed by the dotted keys \n\
+                With the formats other than text, the reports besides the diff, like those of \n\
+                --audit-excluded, --schema, --show-hashes and --lenient-parse, are written to \n\
+                stderr, so the output stays parseable.")
+                .possible_values(&["text", "html", "tap", "json", "unified", "toml-patch"])
+                .default_value("text")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_value_width")
+                .long("max-value-width")
+                .value_name("N")
+                .help("Truncate displayed values longer than N characters, like long base64 \
+                       strings, with a trailing '…(truncated)'. The values are not truncated \
+                       in --format json and toml-patch, which are meant for other tools.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("first_label")
+                .long("first-label")
+                .value_name("LABEL")
+                .help("Name to display for the first file instead of its path, like 'old' for a \
+                       temporary file")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("second_label")
+                .long("second-label")
+                .value_name("LABEL")
+                .help("Name to display for the second file instead of its path")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write the diff to FILE instead of stdout, without colors unless they are \
+                       forced with --color always")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("edit")
+                .long("edit")
+                .help("After the diff, open both files in $VISUAL or $EDITOR, at the line of the \
+                       first difference in each file for editors that take a +LINE argument, \
+                       like vim, nano and emacs, or --goto, like VS Code. The editor is not \
+                       opened when the files have no differences.")
+                .conflicts_with("paginate")
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .value_name("N")
+                .help("Diff N files at once when comparing directories, instead of one file per \
+                       CPU. The diffs are written in the order of the files either way. Without \
+                       the 'parallel' feature, the files are diffed one at a time.")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("paginate")
+                .long("paginate")
+                .help("Write the output through $PAGER, or less, when it is longer than the \
+                       terminal. The output is written directly when stdout is not a terminal, or \
+                       the pager can not be started.")
+                .overrides_with("no_paginate")
+        )
+        .arg(
+            Arg::with_name("no_paginate")
+                .long("no-paginate")
+                .help("Write the output directly, even with --paginate")
+                .overrides_with("paginate")
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|err| {
+            // Usage errors are errors like any other, while help and version requests are not
+            if err.use_stderr() {
+                eprintln!("{}", err.message);
+                process::exit(2);
+            }
+            err.exit()
+        });
+
+    if let Some(sub_matches) = matches.subcommand_matches("canonicalize") {
+        let first_path = PathBuf::from(sub_matches.value_of("file").unwrap_or(""));
+        let float_precision = match sub_matches.value_of("float_precision") {
+            Some(val) => Some(
+                val.parse::<u32>()
+                    .map_err(|_| anyhow!("Invalid number of decimals: {}", val))?,
+            ),
+            None => None,
+        };
+        return Ok(Args {
+            first_format: input_format(&first_path, None)?,
+            first_path,
+            canonicalize: Some(canonical::Options {
+                sort_arrays: sub_matches.is_present("sort_arrays"),
+                float_precision,
+            }),
+            ..Default::default()
+        });
+    }
+
+    // Gets a value for config if supplied by user, or defaults to "default.conf"
+    let layers: Vec<PathBuf> = matches
+        .values_of("layers")
+        .map(|vals| vals.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let baselines: Vec<PathBuf> = matches
+        .value_of("baselines")
+        .map(|val| val.split(',').map(PathBuf::from).collect())
+        .unwrap_or_default();
+    // With layers or baselines, the only positional argument is the target, which is compared as
+    // the second file. The first path is then the most specific layer, or the first baseline.
+    let (first_path, second_path) = match layers.last().or_else(|| baselines.first()) {
+        Some(last_layer) => (
+            last_layer.as_path(),
+            Path::new(matches.value_of("first").unwrap_or("")),
+        ),
+        None => (
+            Path::new(matches.value_of("first").unwrap_or("")),
+            Path::new(matches.value_of("second").unwrap_or("")),
+        ),
+    };
+    let third_path = matches.value_of("third").map(PathBuf::from);
+    let merge = matches.value_of("merge").map(PathBuf::from);
+    let merge_prefer = match matches.value_of("merge_prefer") {
+        Some("second") => merge::Preference::Second,
+        _ => merge::Preference::First,
+    };
+    let config = Config::load(&env::current_dir()?)?;
+    let display_equal =
+        matches.is_present("display_equal") || config.display_equal.unwrap_or(false);
+    let include = matches.value_of("include").map(String::from);
+    let ignore_key_case = matches.is_present("ignore_key_case");
+    let exclude_regexes = match matches.values_of("exclude_regex") {
+        Some(values) => values
+            .map(|val| {
+                Regex::new(val).map_err(|msg| anyhow!("Invalid exclude regex '{}': {}", val, msg))
+            })
+            .collect::<Result<Vec<Regex>, Error>>()?,
+        None => Vec::new(),
+    };
+    let exclude_sections = matches.value_of("exclude_section").map(String::from);
+    let max_value_width = match matches.value_of("max_value_width") {
+        Some(val) => Some(
+            val.parse::<usize>()
+                .map_err(|_| anyhow!("Invalid maximum value width: {}", val))?,
+        ),
+        None => None,
+    };
+    let audit_excluded = matches.is_present("audit_excluded");
+    let no_exit_code = matches.is_present("no_exit_code");
+    let color_choice = if matches.is_present("color_always") {
+        ColorChoice::Always
+    } else if matches.occurrences_of("color") > 0 {
+        ColorChoice::from_arg(matches.value_of("color").unwrap_or("auto"))
+    } else {
+        config.color.unwrap_or(ColorChoice::Auto)
+    };
+    let no_color = env::var_os(NO_COLOR_ENV_VAR).is_some_and(|val| !val.is_empty());
+    let output = matches.value_of("output").map(PathBuf::from);
+    let paginate = use_pager(
+        matches.is_present("paginate") && !matches.is_present("no_paginate"),
+        output.is_some(),
+        io::stdout().is_terminal(),
+    );
+    let color = color_choice.use_color(output.is_none() && io::stdout().is_terminal(), no_color);
+    let always_report = matches.is_present("always_report");
+    let lenient_parse = matches.is_present("lenient_parse");
+    let ignore_parse_errors = matches.is_present("ignore_parse_errors");
+    let group_by_type = matches.is_present("group_by_type");
+    let cross_reference = matches.is_present("cross_reference");
+    let show_lines = matches.is_present("show_lines");
+    let group_by_regex = match matches.value_of("group_by_regex") {
+        Some(val) => {
+            let regex = Regex::new(val).map_err(|msg| anyhow!("Invalid regex: {}", msg))?;
+            if regex.captures_len() < 2 {
+                return Err(anyhow!("The regex '{}' has no capture group", val));
+            }
+            Some(regex)
+        }
+        None => None,
+    };
+    let schema = matches.value_of("schema").map(PathBuf::from);
+    let check_types = matches.is_present("check_types");
+    let approved_path = matches.value_of("approved").map(PathBuf::from);
+    let defaults_path = matches.value_of("defaults_file").map(PathBuf::from);
+    let explain_exclusions = matches
+        .value_of("explain_exclusions")
+        .map(|val| val.split('.').map(String::from).collect());
+    let became = match matches.value_of("became") {
+        Some(val) => Some(parse::parse_value_str(val)?),
+        None => None,
+    };
+    let ignore_types = match matches.values_of("ignore_types") {
+        Some(vals) => vals.map(String::from).collect(),
+        None => Vec::new(),
+    };
+    let validate_roundtrip = matches.is_present("validate_roundtrip");
+    let check = matches.is_present("check");
+    let show_hashes = matches.is_present("show_hashes");
+    let max_entries = match matches.value_of("max_entries") {
+        Some(val) => Some(
+            val.parse::<usize>()
+                .map_err(|_| anyhow!("Invalid number of entries: {}", val))?,
+        ),
+        None => None,
+    };
+    let context = match matches.value_of("context") {
+        Some(val) => val
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Invalid number of context keys: {}", val))?,
+        None => 0,
+    };
+    let context_percent = match matches.value_of("context_percent") {
+        Some(val) => match val.parse::<f64>() {
+            Ok(percent) if percent > 0.0 && percent <= 100.0 => Some(percent),
+            _ => return Err(anyhow!("Invalid context percentage: {}", val)),
+        },
+        None => None,
+    };
+    let edit = matches.is_present("edit");
+    let threads = match matches.value_of("threads") {
+        Some(val) => match val.parse::<usize>() {
+            Ok(threads) if threads > 0 => Some(threads),
+            _ => return Err(anyhow!("Invalid number of threads: {}", val)),
+        },
+        None => None,
+    };
+    let collapse = matches.is_present("collapse");
+    let show_common_summary = matches.is_present("show_common_summary");
+    let lenient_datetime = matches.is_present("lenient_datetime");
+    let normalize_datetime = matches.is_present("normalize_datetime");
+    let float_tolerance = match matches.value_of("float_tolerance") {
+        Some(val) => match val.parse::<f64>() {
+            Ok(eps) if eps >= 0.0 => Some(eps),
+            _ => return Err(anyhow!("Invalid float tolerance: {}", val)),
+        },
+        None => None,
+    };
+    let numeric_loose = matches.is_present("numeric_loose");
+    let value_transform = match matches.value_of("transform") {
+        Some(val) => Some(value_handling::parse_transforms(val)?),
+        None => None,
+    };
+    let format = OutputFormat::from_arg(matches.value_of("format").unwrap_or("text"));
+    let stat = matches.is_present("stat");
+    let summary_line = matches.is_present("summary_line");
+    let quiet = matches.is_present("quiet");
+    if stat && format != OutputFormat::Text && format != OutputFormat::Json {
+        return Err(anyhow!(
+            "--stat can only be combined with --format text or json"
+        ));
+    }
+    let side_by_side = if matches.is_present("side_by_side") {
+        if format != OutputFormat::Text {
+            return Err(anyhow!(
+                "--side-by-side can only be used with --format text"
+            ));
+        }
+        let val = matches.value_of("width").unwrap_or("");
+        match val.parse::<usize>() {
+            Ok(width) if width >= 20 => Some(width),
+            _ => return Err(anyhow!("Invalid width: {}", val)),
+        }
+    } else {
+        None
+    };
+    let key_separator = matches.value_of("key_separator").map(String::from);
+    let parse_comment_directives = matches.is_present("parse_comment_directives");
+    let added_only = matches.is_present("added_only");
+    let removed_only = matches.is_present("removed_only");
+    let changed_only = matches.is_present("changed_only");
+    let sort_changes_by = ChangeOrder::from_arg(matches.value_of("sort_changes_by").unwrap_or(""));
+    let array_algorithm =
+        array_diff::Algorithm::from_arg(matches.value_of("array_algorithm").unwrap_or(""))?;
+    let array_algorithm = if matches.is_present("ignore_array_order") {
+        if matches.occurrences_of("array_algorithm") > 0
+            && array_algorithm != array_diff::Algorithm::Set
+        {
+            return Err(anyhow!(
+                "--ignore-array-order can not be combined with --array-algorithm {}",
+                matches.value_of("array_algorithm").unwrap_or("")
+            ));
+        }
+        array_diff::Algorithm::Set
+    } else {
+        array_algorithm
+    };
+    let array_identities = match matches.value_of("array_smart") {
+        Some(val) => array_diff::parse_specs(val)?,
+        None => Vec::new(),
+    };
+    let exclude_file = match matches.value_of("exclude_file") {
+        Some(val) => {
+            let content = parse::read_file_to_string(Path::new(val))
+                .map_err(|err| anyhow!("Could not read {}: {}", val, err))?;
+            exclude_file_keys(&content)
+        }
+        None => None,
+    };
+    let exclude = merge_excludes(
+        merge_excludes(
+            matches
+                .value_of("exclude")
+                .map(String::from)
+                .or_else(|| config.exclude.clone()),
+            exclude_file,
+        ),
+        env::var(EXCLUDE_ENV_VAR).ok(),
+    );
+    let exclude_value = matches.value_of("exclude_value").map(String::from);
+    let exclude_match = MatchOptions {
+        anchored: matches.is_present("exclude_anchored"),
+        ignore_case: matches.is_present("exclude_ignore_case"),
+    };
+
+    for path in layers
+        .iter()
+        .chain(baselines.iter())
+        .chain(schema.iter())
+        .chain(approved_path.iter())
+        .chain(defaults_path.iter())
+    {
+        check_path(path)?;
+    }
+    if let Some(path) = &third_path {
+        input_format(path, None)?;
+    }
+    // The files in directories are toml files, found by their extension
+    let directories = first_path.is_dir() && second_path.is_dir();
+    let first_label = matches.value_of("first_label").map(String::from);
+    let second_label = matches.value_of("second_label").map(String::from);
+    if directories && (first_label.is_some() || second_label.is_some()) {
+        return Err(anyhow!(
+            "--first-label and --second-label can not be used with directories"
+        ));
+    }
+    let first_format = if directories {
+        InputFormat::default()
+    } else {
+        input_format(first_path, matches.value_of("parse_first"))?
+    };
+    let second_format = if directories || validate_roundtrip && matches.value_of("second").is_none()
+    {
+        InputFormat::default()
+    } else {
+        input_format(second_path, matches.value_of("parse_second"))?
+    };
+    let approved = match approved_path {
+        Some(path) => Some(parse::parse_toml(&path)?),
+        None => None,
+    };
+    let defaults = match defaults_path {
+        Some(path) => Some(parse::parse_toml(&path)?),
+        None => None,
+    };
+
+    Ok(Args {
+        first_path: first_path.to_path_buf(),
+        first_label,
+        second_label,
+        second_path: second_path.to_path_buf(),
+        first_format,
+        second_format,
+        display_equal,
+        color,
+        exclude,
+        exclude_match,
+        exclude_regexes,
+        exclude_sections,
+        exclude_value,
+        max_value_width,
+        ignore_key_case,
+        include,
+        audit_excluded,
+        no_exit_code,
+        always_report,
+        array_identities,
+        array_algorithm,
+        baselines,
+        third_path,
+        merge,
+        merge_prefer,
+        layers,
+        lenient_parse,
+        ignore_parse_errors,
+        group_by_type,
+        group_by_regex,
+        cross_reference,
+        show_lines,
+        schema,
+        check_types,
+        approved,
+        defaults,
+        validate_roundtrip,
+        check,
+        show_hashes,
+        sort_changes_by,
+        added_only,
+        removed_only,
+        changed_only,
+        max_entries,
+        context,
+        context_percent,
+        collapse,
+        show_common_summary,
+        lenient_datetime,
+        normalize_datetime,
+        float_tolerance,
+        numeric_loose,
+        value_transform,
+        format,
+        output,
+        paginate,
+        edit,
+        threads,
+        side_by_side,
+        key_separator,
+        stat,
+        summary_line,
+        quiet,
+        parse_comment_directives,
+        became,
+        ignore_types,
+        explain_exclusions,
+        canonicalize: None,
+    })
+}
+
+/// Check that an input path exists, and find out how to parse it
+///
+/// The format is given by its name on the command line, or else detected from the extension.
+fn input_format(path: &Path, name: Option<&str>) -> Result<InputFormat, Error> {
+    if !path.exists() {
+        return Err(anyhow!("Path does not exist: {}", path.display()));
+    }
+    match name {
+        Some(name) => {
+            InputFormat::from_name(name).ok_or_else(|| anyhow!("Unknown input format: {}", name))
+        }
+        None => InputFormat::from_path(path)
+            .ok_or_else(|| anyhow!("Path is not a toml, YAML or JSON file: {}", path.display())),
+    }
+}
+
+/// Check that a path exists and is a toml file
+fn check_path(path: &Path) -> Result<(), Error> {
+    if !path.exists() {
+        return Err(anyhow!("Path does not exist: {}", path.display()));
+    }
+    if parse::format_extension(path) != Some("toml") {
+        return Err(anyhow!("Path is not a toml file: {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Check if there are any differences between the two collections among the compared keys, in
+/// the sections that are displayed
+fn has_differences(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> bool {
+    let sections = args.sections();
+    let comparison = args.comparison();
+    // Stop at the first unequal value, instead of finding all of them
+    let any_unequal = || {
+        key_origins.both().iter().any(|key| {
+            match (first_collection.get(key), second_collection.get(key)) {
+                (Some(first_val), Some(second_val)) => {
+                    !comparison.equal(key, first_val, second_val)
+                        && is_reported_change(args, key, first_val, second_val)
+                }
+                _ => unreachable!(),
+            }
+        })
+    };
+    sections.first_only && !key_origins.first_only().is_empty()
+        || sections.second_only && !key_origins.second_only().is_empty()
+        || sections.both && any_unequal()
+}
+
+/// Display the element-wise differences between two arrays, where the field is the identity field
+/// of arrays of tables compared with the keyed algorithm, and `lines` is the `--show-lines`
+/// suffix of the key of the arrays
+fn display_array_changes(
+    out: &mut dyn Write,
+    key: &str,
+    lines: &str,
+    field: Option<&str>,
+    changes: &[ArrayChange],
+    style: &TextStyle,
+) -> io::Result<()> {
+    let color = style.color;
+    let text = |val: &toml::Value| style.value_text(Some(val));
+    // Unequal elements or fields are labeled with their change of type, like unequal values
+    let display_unequal_element = |out: &mut dyn Write,
+                                   path: &str,
+                                   first: Option<&toml::Value>,
+                                   second: Option<&toml::Value>| {
+        let change = match (first, second) {
+            (Some(first), Some(second)) => value_handling::type_change(first, second),
+            _ => None,
+        };
+        if color {
+            writeln!(out, "\n{}{}", path.red(), lines)?;
+            if let Some(change) = change {
+                writeln!(out, "{}", format!("type change: {}", change).bold())?;
+            }
+            writeln!(out, "{} {}", "<".blue(), style.value_text(first))?;
+            writeln!(out, "{} {}", ">".yellow(), style.value_text(second))
+        } else {
+            writeln!(out, "\nUnequal value for key '{}'{}", path, lines)?;
+            if let Some(change) = change {
+                writeln!(out, "type change: {}", change)?;
+            }
+            writeln!(out, "< {}", style.value_text(first))?;
+            writeln!(out, "> {}", style.value_text(second))
+        }
+    };
+    for change in changes.iter() {
+        match change {
+            ArrayChange::Removed {
+                index,
+                id: Some(value),
+            } if field.is_none() => {
+                let path = array_diff::element_path(key, field, None, *index);
+                if color {
+                    writeln!(out, "\n{}{}", path.blue(), lines)?;
+                    writeln!(out, "{} {}", "<".blue(), text(value))?;
+                } else {
+                    writeln!(out, "\nRemoved array entry '{}'{}", path, lines)?;
+                    writeln!(out, "< {}", text(value))?;
+                }
+            }
+            ArrayChange::Added {
+                index,
+                id: Some(value),
+            } if field.is_none() => {
+                let path = array_diff::element_path(key, field, None, *index);
+                if color {
+                    writeln!(out, "\n{}{}", path.yellow(), lines)?;
+                    writeln!(out, "{} {}", ">".yellow(), text(value))?;
+                } else {
+                    writeln!(out, "\nAdded array entry '{}'{}", path, lines)?;
+                    writeln!(out, "> {}", text(value))?;
+                }
+            }
+            ArrayChange::Replaced {
+                index,
+                first,
+                second,
+            } => {
+                let path = array_diff::element_path(key, field, None, *index);
+                display_unequal_element(out, &path, Some(first), Some(second))?;
+            }
+            ArrayChange::Nested { index, changes } => {
+                let element_key = format!("{}[{}]", key, index);
+                display_array_changes(out, &element_key, lines, field, changes, style)?;
+            }
+            ArrayChange::Removed { index, id } => {
+                let path = array_diff::element_path(key, field, id.as_ref(), *index);
+                if color {
+                    writeln!(out, "\n{}{}", path.blue(), lines)?;
+                    writeln!(out, "{} removed at index {}", "<".blue(), index)?;
+                } else {
+                    writeln!(
+                        out,
+                        "\nRemoved array entry '{}' at index {}{}",
+                        path, index, lines
+                    )?;
+                }
+            }
+            ArrayChange::Added { index, id } => {
+                let path = array_diff::element_path(key, field, id.as_ref(), *index);
+                if color {
+                    writeln!(out, "\n{}{}", path.yellow(), lines)?;
+                    writeln!(out, "{} added at index {}", ">".yellow(), index)?;
+                } else {
+                    writeln!(
+                        out,
+                        "\nAdded array entry '{}' at index {}{}",
+                        path, index, lines
+                    )?;
+                }
+            }
+            ArrayChange::Moved { from, to, id } => {
+                let path = array_diff::element_path(key, field, Some(id), *from);
+                if color {
+                    writeln!(out, "\n{}{}", path.red(), lines)?;
+                    writeln!(out, "{} index {}", "<".blue(), from)?;
+                    writeln!(out, "{} index {}", ">".yellow(), to)?;
+                } else {
+                    writeln!(
+                        out,
+                        "\nMoved array entry '{}' from index {} to {}{}",
+                        path, from, to, lines
+                    )?;
+                }
+            }
+            ArrayChange::Changed {
+                id,
+                field: sub_key,
+                first,
+                second,
+            } => {
+                let path = format!(
+                    "{}{}{}",
+                    array_diff::element_path(key, field, Some(id), 0),
+                    style.key_separator,
+                    style.key_text(sub_key)
+                );
+                display_unequal_element(out, &path, first.as_ref(), second.as_ref())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keys in both collections with equal values, in the order of the first collection
+fn equal_keys(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> Vec<Key> {
+    let comparison = args.comparison();
+    key_origins
+        .both()
+        .into_iter()
+        .filter(
+            |key| match (first_collection.get(key), second_collection.get(key)) {
+                (Some(first_val), Some(second_val)) => comparison.equal(key, first_val, second_val),
+                _ => unreachable!(),
+            },
+        )
+        .collect()
+}
+
+/// Count the keys, and the top-level sections they are in
+///
+/// Keys directly at the top level count as being in one section of their own.
+fn common_summary(keys: &[Key]) -> (usize, usize) {
+    let sections: HashSet<Option<&String>> = keys
+        .iter()
+        .map(|key| if key.len() > 1 { key.first() } else { None })
+        .collect();
+    (keys.len(), sections.len())
+}
+
+/// Fill in the default value of each key only found in one of the collections, where the value in
+/// that collection equals the default, so the key is in both collections with equal values
+///
+/// The keys are split by the collections they are in again, where the filled in keys are in both.
+fn fill_defaults(
+    args: &Args,
+    defaults: &IndexMap<Key, toml::Value>,
+    first_collection: &mut IndexMap<Vec<String>, toml::Value>,
+    second_collection: &mut IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> Result<KeyOrigins<Key>, Error> {
+    let comparison = args.comparison();
+    let is_default = |key: &Key, val: &toml::Value| match defaults.get(key) {
+        Some(default) => comparison.equal(key, val, default),
+        None => false,
+    };
+    let first_filled: Vec<Key> = key_origins
+        .second_only()
+        .into_iter()
+        .filter(|key| is_default(key, &second_collection[key]))
+        .collect();
+    let second_filled: Vec<Key> = key_origins
+        .first_only()
+        .into_iter()
+        .filter(|key| is_default(key, &first_collection[key]))
+        .collect();
+    for key in first_filled.iter() {
+        first_collection.insert(key.clone(), defaults[key].clone());
+    }
+    for key in second_filled.iter() {
+        second_collection.insert(key.clone(), defaults[key].clone());
+    }
+
+    let compared: HashSet<Key> = key_origins
+        .first_only()
+        .into_iter()
+        .chain(key_origins.second_only())
+        .chain(key_origins.both())
+        .collect();
+    let compared_keys = |collection: &IndexMap<Vec<String>, toml::Value>| -> Vec<Key> {
+        collection
+            .keys()
+            .filter(|key| compared.contains(*key))
+            .cloned()
+            .collect()
+    };
+    key_handling::compare_vectors(
+        &compared_keys(first_collection),
+        &compared_keys(second_collection),
+    )
+}
+
+/// Keys in both collections with unequal values, leaving out approved changes
+fn unequal_keys(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> Vec<Key> {
+    let both: HashSet<Key> = key_origins.both().into_iter().collect();
+    key_handling::changed_keys(first_collection, second_collection, &args.comparison())
+        .into_iter()
+        .filter(|key| both.contains(key))
+        .filter(
+            |key| match (first_collection.get(key), second_collection.get(key)) {
+                (Some(first_val), Some(second_val)) => {
+                    is_reported_change(args, key, first_val, second_val)
+                }
+                _ => unreachable!(),
+            },
+        )
+        .collect()
+}
+
+/// Check if a change of a key between the values is reported, which it is unless it is approved,
+/// the new value is not the one given by `--became`, or both values are of types given by
+/// `--ignore-types`
+fn is_reported_change(
+    args: &Args,
+    key: &[String],
+    first_val: &toml::Value,
+    second_val: &toml::Value,
+) -> bool {
+    !args.is_approved(key, second_val)
+        && match &args.became {
+            Some(target) => second_val == target,
+            None => true,
+        }
+        && !(is_ignored_type(args, first_val) && is_ignored_type(args, second_val))
+}
+
+/// Check if the type of a value is given by `--ignore-types`
+fn is_ignored_type(args: &Args, value: &toml::Value) -> bool {
+    let name = value_handling::type_name(value).to_lowercase();
+    args.ignore_types.contains(&name)
+}
+
+/// Trace a key through each stage of the comparison, as pairs of the stage and the outcome for the
+/// key. The trace stops at the stage that leaves out the key, if any.
+fn explain_exclusions(
+    args: &Args,
+    key: &[String],
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+) -> Vec<(&'static str, String)> {
+    let mut trace = Vec::<(&'static str, String)>::new();
+    let first_val = first_collection.get(key);
+    let second_val = second_collection.get(key);
+
+    let presence = match (first_val, second_val) {
+        (Some(_), Some(_)) => String::from("in both files"),
+        (Some(_), None) => format!("only in {}", args.first_name()),
+        (None, Some(_)) => format!("only in {}", args.second_name()),
+        (None, None) => {
+            let prefixed = first_collection
+                .keys()
+                .chain(second_collection.keys())
+                .filter(|other| other.starts_with(key))
+                .unique()
+                .count();
+            let outcome = if prefixed > 0 {
+                format!(
+                    "left out: not a key with a value, but the table of {} keys",
+                    prefixed
+                )
+            } else {
+                String::from("left out: in none of the files")
+            };
+            trace.push(("input", outcome));
+            return trace;
+        }
+    };
+    trace.push(("input", presence));
+
+    if args.include.is_some() {
+        match key_handling::matching_pattern(key, args.include.as_deref(), &MatchOptions::default())
+        {
+            Some(pattern) => trace.push(("include", format!("included by '{}'", pattern))),
+            None => {
+                trace.push((
+                    "include",
+                    String::from("left out: not matched by any include key"),
+                ));
+                return trace;
+            }
+        }
+    }
+
+    match args.exclusion(key) {
+        Some(pattern) => {
+            trace.push((
+                "exclude",
+                format!("left out by the exclude key '{}'", pattern),
+            ));
+            return trace;
+        }
+        None => trace.push(("exclude", String::from("not matched by any exclude key"))),
+    }
+
+    if let Some(text) = &args.exclude_value {
+        if args.value_exclusion(first_val, second_val).is_some() {
+            trace.push((
+                "exclude-value",
+                format!(
+                    "left out: a value is written as the exclude value '{}'",
+                    text
+                ),
+            ));
+            return trace;
+        }
+        trace.push((
+            "exclude-value",
+            format!("no value is written as '{}'", text),
+        ));
+    }
+
+    let sections = args.sections();
+    let (shown, option) = match (first_val, second_val) {
+        (Some(_), None) => (
+            sections.first_only,
+            if args.became.is_some() {
+                "--became"
+            } else if args.changed_only {
+                "--changed-only"
+            } else {
+                "--added-only"
+            },
+        ),
+        (None, Some(_)) => (
+            sections.second_only,
+            if args.became.is_some() {
+                "--became"
+            } else if args.changed_only {
+                "--changed-only"
+            } else {
+                "--removed-only"
+            },
+        ),
+        _ => (
+            sections.both,
+            if args.added_only {
+                "--added-only"
+            } else {
+                "--removed-only"
+            },
+        ),
+    };
+    if !shown {
+        trace.push(("section", format!("left out by {}", option)));
+        return trace;
+    }
+    trace.push(("section", String::from("displayed")));
+
+    if let (Some(first_val), Some(second_val)) = (first_val, second_val) {
+        if args.comparison().equal(key, first_val, second_val) {
+            let outcome = if args.display_equal && !args.show_common_summary {
+                String::from("equal, and displayed by -e")
+            } else {
+                String::from("left out: the values are equal, and only displayed with -e")
+            };
+            trace.push(("values", outcome));
+            return trace;
+        }
+        trace.push(("values", String::from("unequal")));
+
+        if args.is_approved(key, second_val) {
+            trace.push((
+                "approved",
+                String::from("left out: the change is approved by --approved"),
+            ));
+            return trace;
+        }
+        if let Some(target) = &args.became {
+            if second_val != target {
+                trace.push((
+                    "became",
+                    format!("left out: the new value is not {}", target),
+                ));
+                return trace;
+            }
+        }
+        if is_ignored_type(args, first_val) && is_ignored_type(args, second_val) {
+            trace.push((
+                "ignore-types",
+                String::from("left out: the types of both values are ignored by --ignore-types"),
+            ));
+            return trace;
+        }
+    }
+
+    if let Some(max_entries) = args.max_entries {
+        trace.push((
+            "max-entries",
+            format!(
+                "displayed if it is among the first {} entries of the diff",
+                max_entries
+            ),
+        ));
+    }
+    trace
+}
+
+/// Find the keys among `other_keys` with the same value in `other_collection` as each of the
+/// `keys` has in `collection`. Keys without any such keys are left out, and the found keys are
+/// sorted.
+fn cross_references(
+    keys: &[Key],
+    collection: &IndexMap<Key, toml::Value>,
+    other_keys: &[Key],
+    other_collection: &IndexMap<Key, toml::Value>,
+) -> HashMap<Key, Vec<Key>> {
+    let mut references = HashMap::<Key, Vec<Key>>::new();
+    for key in keys.iter() {
+        let val = collection.get(key);
+        let matching: Vec<Key> = other_keys
+            .iter()
+            .filter(|other| other_collection.get(*other) == val)
+            .cloned()
+            .sorted()
+            .collect();
+        if !matching.is_empty() {
+            references.insert(key.clone(), matching);
+        }
+    }
+    references
+}
+
+/// Annotation of an entry with the keys it also appears as, if any
+fn also_appears_as(references: &HashMap<Key, Vec<Key>>, key: &[String], separator: &str) -> String {
+    match references.get(key) {
+        Some(others) => format!(
+            " (also appears as {})",
+            others
+                .iter()
+                .map(|other| other.iter().join(separator))
+                .join(", ")
+        ),
+        None => String::new(),
+    }
+}
+
+/// Group keys by the kind of change of their values
+///
+/// The groups are ordered by `ChangeKind`, and the keys in each group keep their given order.
+fn group_by_type(unequal: &[(Key, &toml::Value, &toml::Value)]) -> Vec<(ChangeKind, Vec<Key>)> {
+    let mut groups = BTreeMap::<ChangeKind, Vec<Key>>::new();
+    for (key, first_val, second_val) in unequal.iter() {
+        let kind = ChangeKind::of(first_val, second_val);
+        groups.entry(kind).or_default().push(key.clone());
+    }
+    groups.into_iter().collect()
+}
+
+/// Group keys by the first capture group of a regex on the keys, written with periods between the
+/// key levels
+///
+/// The groups are sorted by the captured text, and the keys in each group keep their given order.
+/// Keys that do not match, or where the group does not participate in the match, are put in a
+/// last group, named "other".
+fn group_by_regex(keys: &[Key], regex: &Regex) -> Vec<(String, Vec<Key>)> {
+    let mut groups = BTreeMap::<String, Vec<Key>>::new();
+    let mut other = Vec::<Key>::new();
+    for key in keys.iter() {
+        let key_str = key.iter().join(".");
+        match regex
+            .captures(&key_str)
+            .and_then(|captures| captures.get(1))
+        {
+            Some(group) => groups
+                .entry(String::from(group.as_str()))
+                .or_default()
+                .push(key.clone()),
+            None => other.push(key.clone()),
+        }
+    }
+    let mut groups: Vec<(String, Vec<Key>)> = groups.into_iter().collect();
+    if !other.is_empty() {
+        groups.push((String::from("other"), other));
+    }
+    groups
+}
+
+/// Sort unequal keys in the given order
+///
+/// Ties are broken by key order, which compares the keys segment by segment.
+fn sort_changes(
+    keys: &mut [Key],
+    order: ChangeOrder,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+) {
+    keys.sort();
+    match order {
+        ChangeOrder::File => {
+            keys.sort_by_key(|key| first_collection.get_index_of(key).unwrap_or(usize::MAX))
+        }
+        ChangeOrder::Key => {}
+        ChangeOrder::Delta => {
+            let delta = |key: &Key| match (first_collection.get(key), second_collection.get(key)) {
+                (Some(first_val), Some(second_val)) => {
+                    value_handling::numeric_delta(first_val, second_val)
+                }
+                _ => None,
+            };
+            // Numeric changes by decreasing absolute change, then all other changes. The sort is
+            // stable, so ties keep their key order.
+            keys.sort_by(|a, b| match (delta(a), delta(b)) {
+                (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            });
+        }
+        ChangeOrder::Depth => keys.sort_by_key(|key| key.len()),
+        ChangeOrder::Section => keys.sort_by(|a, b| a[..a.len() - 1].cmp(&b[..b.len() - 1])),
+    }
+}
+
+/// Display a key with unequal values in the two files
+fn display_unequal(
+    out: &mut dyn Write,
+    args: &Args,
+    key: &[String],
+    first_val: &toml::Value,
+    second_val: &toml::Value,
+    source_lines: Option<&SourceLines>,
+) -> io::Result<()> {
+    let color = args.color;
+    let lines = source_lines
+        .map(|source_lines| source_lines.suffix(key))
+        .unwrap_or_default();
+
+    let algorithm = match array_diff::spec_for(&args.array_identities, key) {
+        Some(spec) => array_diff::Algorithm::Keyed(spec.field.clone()),
+        None => args.array_algorithm.clone(),
+    };
+    if let Some(changes) =
+        array_diff::diff(&algorithm, key, first_val, second_val, &args.comparison())
+    {
+        display_array_changes(
+            out,
+            &args.display_key(key),
+            &lines,
+            algorithm.field(),
+            &changes,
+            &args.text_style(),
+        )?;
+    } else if color {
+        let output = args.display_key(key);
+        writeln!(out, "\n{}{}", output.red(), lines)?;
+        if let Some(change) = value_handling::type_change(first_val, second_val) {
+            writeln!(out, "{}", format!("type change: {}", change).bold())?;
+        }
+        writeln!(out, "{} {}", "<".blue(), args.value_text(first_val))?;
+        writeln!(out, "{} {}", ">".yellow(), args.value_text(second_val))?;
+    } else {
+        writeln!(
+            out,
+            "\nUnequal value for key '{}'{}",
+            args.display_key(key),
+            lines
+        )?;
+        if let Some(change) = value_handling::type_change(first_val, second_val) {
+            writeln!(out, "type change: {}", change)?;
+        }
+        writeln!(out, "< {}", args.value_text(first_val))?;
+        writeln!(out, "> {}", args.value_text(second_val))?;
+    }
+    Ok(())
+}
+
+/// Limit on the total number of displayed entries, shared by all sections
+///
+/// The sections take from the budget in display order, so the earlier sections are shown in full
+/// before the later sections get any entries.
+struct EntryBudget {
+    remaining: Option<usize>,
+}
+
+impl EntryBudget {
+    fn new(max_entries: Option<usize>) -> Self {
+        EntryBudget {
+            remaining: max_entries,
+        }
+    }
+
+    /// Use up the budget for as many of `count` entries as possible, and return how many that is
+    fn take(&mut self, count: usize) -> usize {
+        match self.remaining.as_mut() {
+            Some(remaining) => {
+                let taken = count.min(*remaining);
+                *remaining -= taken;
+                taken
+            }
+            None => count,
+        }
+    }
+}
+
+/// Annotations of the displayed entries, found from the whole files
+#[derive(Default)]
+struct Annotations {
+    /// Line numbers of the keys in the files
+    source_lines: Option<SourceLines>,
+    /// Keys only in the second file with the same value, for each key only in the first file
+    first_references: HashMap<Key, Vec<Key>>,
+    /// Keys only in the first file with the same value, for each key only in the second file
+    second_references: HashMap<Key, Vec<Key>>,
+    /// Sibling keys, and their values in the first file, for each key in both files
+    context: HashMap<Key, Vec<(Key, toml::Value)>>,
+    /// Tables of the keys only in the first file, that are not in the second file
+    first_subtrees: Vec<Key>,
+    /// Tables of the keys only in the second file, that are not in the first file
+    second_subtrees: Vec<Key>,
+}
+
+/// The largest tables of the given keys that have no keys in the other collection, and that hold
+/// at least two of the keys, in the order of the keys
+fn one_sided_subtrees(keys: &[Key], other: &IndexMap<Key, toml::Value>) -> Vec<Key> {
+    let other_prefixes: HashSet<&[String]> = other
+        .keys()
+        .flat_map(|key| (1..=key.len()).map(move |len| &key[..len]))
+        .collect();
+    let mut counts = IndexMap::<Key, usize>::new();
+    for key in keys.iter() {
+        let subtree = (1..key.len())
+            .map(|len| &key[..len])
+            .find(|prefix| !other_prefixes.contains(prefix));
+        if let Some(subtree) = subtree {
+            *counts.entry(subtree.to_vec()).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count >= 2)
+        .map(|(subtree, _)| subtree)
+        .collect()
+}
+
+/// Keys grouped by the key of the table they are in, in the order of the given keys
+fn group_by_parent(keys: &[Key]) -> IndexMap<Key, Vec<Key>> {
+    let mut groups = IndexMap::<Key, Vec<Key>>::new();
+    for key in keys.iter() {
+        let parent = key[..key.len().saturating_sub(1)].to_vec();
+        groups.entry(parent).or_default().push(key.clone());
+    }
+    groups
+}
+
+/// Largest number of sibling keys displayed with --context-percent
+const MAX_CONTEXT: usize = 20;
+
+/// Number of sibling keys to display around each unequal value, as a percentage of the number of
+/// compared keys, clamped to at least 1 and at most `MAX_CONTEXT`
+fn context_size(percent: f64, key_count: usize) -> usize {
+    ((key_count as f64 * percent / 100.0).round() as usize).clamp(1, MAX_CONTEXT)
+}
+
+/// Up to `count` of the siblings of a key, closest first in the order of the siblings, returned in
+/// that order
+fn closest_siblings(key: &[String], siblings: &[Key], count: usize) -> Vec<Key> {
+    let position = match siblings.iter().position(|sibling| sibling == key) {
+        Some(position) => position,
+        None => return Vec::new(),
+    };
+    siblings
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != position)
+        .sorted_by_key(|(index, _)| (index.abs_diff(position), *index))
+        .take(count)
+        .sorted_by_key(|(index, _)| *index)
+        .map(|(_, sibling)| sibling.clone())
+        .collect()
+}
+
+/// Find the annotations of the entries that are asked for on the command line
+fn annotations(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> Result<Annotations, Error> {
+    let mut annotations = Annotations::default();
+    if args.show_lines {
+        annotations.source_lines = Some(source_lines(args)?);
+    }
+    if args.cross_reference {
+        let first_only = key_origins.first_only();
+        let second_only = key_origins.second_only();
+        annotations.first_references = cross_references(
+            &first_only,
+            first_collection,
+            &second_only,
+            second_collection,
+        );
+        annotations.second_references = cross_references(
+            &second_only,
+            second_collection,
+            &first_only,
+            first_collection,
+        );
+    }
+    if args.collapse {
+        annotations.first_subtrees =
+            one_sided_subtrees(&key_origins.first_only(), second_collection);
+        annotations.second_subtrees =
+            one_sided_subtrees(&key_origins.second_only(), first_collection);
+    }
+    if args.context > 0 || args.context_percent.is_some() {
+        // Siblings among the compared keys of the first file
+        let both = key_origins.both();
+        let compared: HashSet<Key> = key_origins
+            .first_only()
+            .into_iter()
+            .chain(both.iter().cloned())
+            .collect();
+        let compared: Vec<Key> = first_collection
+            .keys()
+            .filter(|key| compared.contains(*key))
+            .cloned()
+            .collect();
+        let count = match args.context_percent {
+            Some(percent) => context_size(percent, compared.len()),
+            None => args.context,
+        };
+        let groups = group_by_parent(&compared);
+        for key in both {
+            let siblings = &groups[&key[..key.len() - 1]];
+            let context = closest_siblings(&key, siblings, count)
+                .into_iter()
+                .map(|sibling| {
+                    let val = first_collection[&sibling].clone();
+                    (sibling, val)
+                })
+                .collect();
+            annotations.context.insert(key, context);
+        }
+    }
+    Ok(annotations)
+}
+
+/// Lines of the entries only found in one file, where the keys in each of the given tables are
+/// collapsed into a single line with the number of keys, and the keys are in the given color, if
+/// any
+fn one_sided_lines(
+    entries: &[(Key, &toml::Value)],
+    subtrees: &[Key],
+    references: &HashMap<Key, Vec<Key>>,
+    separator: &str,
+    lines: &dyn Fn(&[String]) -> String,
+    key_color: Option<Color>,
+    max_value_width: Option<usize>,
+) -> Vec<String> {
+    let paint = |text: String| match key_color {
+        Some(key_color) => text.color(key_color).to_string(),
+        None => text,
+    };
+    let mut collapsed = HashSet::<&Key>::new();
+    let mut output = Vec::<String>::new();
+    for (key, val) in entries.iter() {
+        match subtrees.iter().find(|subtree| key.starts_with(subtree)) {
+            Some(subtree) => {
+                if collapsed.insert(subtree) {
+                    let count = entries
+                        .iter()
+                        .filter(|(other, _)| other.starts_with(subtree))
+                        .count();
+                    let table = format!("[{}{}*]", subtree.iter().join(separator), separator);
+                    output.push(format!("{} ({} keys)", paint(table), count));
+                }
+            }
+            None => output.push(format!(
+                "{}{}: {}{}",
+                paint(key.iter().join(separator)),
+                lines(key),
+                report::truncate(val.to_string(), max_value_width),
+                also_appears_as(references, key, separator)
+            )),
+        }
+    }
+    output
+}
+
+/// Render the entries of a diff as text
+fn display(
+    out: &mut dyn Write,
+    args: &Args,
+    report: &DiffReport,
+    annotations: &Annotations,
+) -> io::Result<()> {
+    let color = args.color;
+    if let (Some(first_hash), Some(second_hash)) = (&report.first_hash, &report.second_hash) {
+        writeln!(out, "first: {} second: {}", first_hash, second_hash)?;
+    }
+    let source_lines = annotations.source_lines.as_ref();
+    let lines = |key: &[String]| {
+        source_lines
+            .map(|source_lines| source_lines.suffix(key))
+            .unwrap_or_default()
+    };
+
+    if args.always_report
+        && report.first_only.is_empty()
+        && report.second_only.is_empty()
+        && report.unequal.is_empty()
+    {
+        if color {
+            writeln!(out, "{}", "Files are identical".green())?;
+        } else {
+            writeln!(out, "Files are identical")?;
+        }
+        return Ok(());
+    }
+
+    let mut budget = EntryBudget::new(args.max_entries);
+    let mut omitted = Vec::<(String, usize)>::new();
+
+    if !report.first_only.is_empty() {
+        let entries = one_sided_lines(
+            &report.first_only,
+            &annotations.first_subtrees,
+            &annotations.first_references,
+            args.key_separator(),
+            &lines,
+            if color { Some(Color::Blue) } else { None },
+            args.max_value_width,
+        );
+        let shown = budget.take(entries.len());
+        if color {
+            let output = format!("\n{}", report.first_name);
+            writeln!(out, "{}", output.blue())?;
+        } else {
+            writeln!(out, "\nEntries only found in {}", report.first_name)?;
+        }
+        for entry in entries[..shown].iter() {
+            writeln!(out, "{}", entry)?;
+        }
+        omitted.push((
+            format!("only found in {}", report.first_name),
+            entries.len() - shown,
+        ));
+    }
+
+    if !report.second_only.is_empty() {
+        let entries = one_sided_lines(
+            &report.second_only,
+            &annotations.second_subtrees,
+            &annotations.second_references,
+            args.key_separator(),
+            &lines,
+            if color { Some(Color::Yellow) } else { None },
+            args.max_value_width,
+        );
+        let shown = budget.take(entries.len());
+        if color {
+            let output = format!("\n{}", report.second_name);
+            writeln!(out, "{}", output.yellow())?;
+        } else {
+            writeln!(out, "\nEntries only found in {}", report.second_name)?;
+        }
+        for entry in entries[..shown].iter() {
+            writeln!(out, "{}", entry)?;
+        }
+        omitted.push((
+            format!("only found in {}", report.second_name),
+            entries.len() - shown,
+        ));
+    }
+
+    let shown = budget.take(report.unequal.len());
+    if !report.unequal.is_empty() {
+        omitted.push((
+            String::from("with unequal values"),
+            report.unequal.len() - shown,
+        ));
+    }
+    let unequal = &report.unequal[..shown];
+    let unequal_values: HashMap<&Key, (&toml::Value, &toml::Value)> = unequal
+        .iter()
+        .map(|(key, first_val, second_val)| (key, (*first_val, *second_val)))
+        .collect();
+    // The unequal entries under the headings of their groups, if they are grouped
+    let groups: Vec<(Option<String>, Vec<Key>)> = if let Some(regex) = &args.group_by_regex {
+        let keys: Vec<Key> = unequal.iter().map(|(key, _, _)| key.clone()).collect();
+        group_by_regex(&keys, regex)
+            .into_iter()
+            .map(|(group, keys)| (Some(group), keys))
+            .collect()
+    } else if args.group_by_type {
+        group_by_type(unequal)
+            .into_iter()
+            .map(|(kind, keys)| (Some(String::from(kind.heading())), keys))
+            .collect()
+    } else {
+        vec![(
+            None,
+            unequal.iter().map(|(key, _, _)| key.clone()).collect(),
+        )]
+    };
+    for (heading, keys) in groups.iter() {
+        if let Some(heading) = heading {
+            if color {
+                writeln!(out, "\n{}", heading.bold())?;
+            } else {
+                writeln!(out, "\n{}", heading)?;
+            }
+        }
+        for key in keys.iter() {
+            let (first_val, second_val) = unequal_values[key];
+            display_unequal(out, args, key, first_val, second_val, source_lines)?;
+            for (sibling, val) in annotations.context.get(key).into_iter().flatten() {
+                let output = format!(
+                    "  {}: {} (context)",
+                    args.display_key(sibling),
+                    args.value_text(val)
+                );
+                if color {
+                    writeln!(out, "{}", output.dimmed())?;
+                } else {
+                    writeln!(out, "{}", output)?;
+                }
+            }
+        }
+    }
+
+    if args.display_equal && !args.show_common_summary && !report.equal.is_empty() {
+        let shown = budget.take(report.equal.len());
+        omitted.push((
+            String::from("with equal values"),
+            report.equal.len() - shown,
+        ));
+        for (key, first_val, second_val) in report.equal[..shown].iter() {
+            if color {
+                let output = args.display_key(key);
+                writeln!(out, "\n{}{}", output.green(), lines(key))?;
+                writeln!(out, "{} {}", "<".blue(), report.value_text(first_val))?;
+                writeln!(out, "{} {}", ">".yellow(), report.value_text(second_val))?;
+            } else {
+                writeln!(
+                    out,
+                    "\nEqual value for key '{}'{}",
+                    args.display_key(key),
+                    lines(key)
+                )?;
+                writeln!(out, "< {}", report.value_text(first_val))?;
+                writeln!(out, "> {}", report.value_text(second_val))?;
+            }
+        }
+    }
+
+    let total_omitted: usize = omitted.iter().map(|(_, count)| count).sum();
+    if total_omitted > 0 {
+        writeln!(out, "\n{} entries omitted by --max-entries", total_omitted)?;
+        for (section, count) in omitted.iter().filter(|(_, count)| *count > 0) {
+            writeln!(out, "{} {}", count, section)?;
+        }
+    }
+
+    if args.show_common_summary && args.sections().both {
+        let equal: Vec<Key> = report.equal.iter().map(|(key, _, _)| key.clone()).collect();
+        let (key_count, section_count) = common_summary(&equal);
+        let output = format!(
+            "{} keys identical across {} sections",
+            key_count, section_count
+        );
+        if color {
+            writeln!(out, "\n{}", output.green())?;
+        } else {
+            writeln!(out, "\n{}", output)?;
+        }
+    }
+    Ok(())
+}
+
+/// Collect the displayed entries of the diff, for rendering in any of the output formats
+///
+/// The entries with equal values are collected when they are displayed, or summarized.
+fn diff_report<'a>(
+    args: &Args,
+    first_collection: &'a IndexMap<Vec<String>, toml::Value>,
+    second_collection: &'a IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> DiffReport<'a> {
+    let sections = args.sections();
+    fn value<'a>(collection: &'a IndexMap<Vec<String>, toml::Value>, key: &Key) -> &'a toml::Value {
+        match collection.get(key) {
+            Some(val) => val,
+            None => unreachable!(),
+        }
+    }
+
+    let mut first_only = Vec::<Key>::new();
+    if sections.first_only {
+        first_only = key_origins.first_only();
+    }
+    let mut second_only = Vec::<Key>::new();
+    if sections.second_only {
+        second_only = key_origins.second_only();
+    }
+    let mut unequal = Vec::<Key>::new();
+    let mut equal = Vec::<Key>::new();
+    if sections.both {
+        unequal = unequal_keys(args, first_collection, second_collection, key_origins);
+        sort_changes(
+            &mut unequal,
+            args.sort_changes_by,
+            first_collection,
+            second_collection,
+        );
+        if args.display_equal || args.show_common_summary {
+            equal = equal_keys(args, first_collection, second_collection, key_origins);
+        }
+    }
+
+    DiffReport {
+        first_name: args.first_name(),
+        second_name: args.second_name(),
+        first_hash: None,
+        second_hash: None,
+        max_value_width: args.max_value_width,
+        first_only: first_only
+            .into_iter()
+            .map(|key| {
+                let val = value(first_collection, &key);
+                (key, val)
+            })
+            .collect(),
+        second_only: second_only
+            .into_iter()
+            .map(|key| {
+                let val = value(second_collection, &key);
+                (key, val)
+            })
+            .collect(),
+        unequal: unequal
+            .into_iter()
+            .map(|key| {
+                let first_val = value(first_collection, &key);
+                let second_val = value(second_collection, &key);
+                (key, first_val, second_val)
+            })
+            .collect(),
+        equal: equal
+            .into_iter()
+            .map(|key| {
+                let first_val = value(first_collection, &key);
+                let second_val = value(second_collection, &key);
+                (key, first_val, second_val)
+            })
+            .collect(),
+    }
+}
+
+/// Keys left out by the exclude keys or the exclude value that are only in one of the collections,
+/// or have unequal values, sorted. Keys left out by the include keys are not considered.
+fn excluded_differences(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+) -> Vec<Key> {
+    key_handling::classify_keys(first_collection, second_collection, &args.comparison())
+        .into_iter()
+        .filter(|(_, status)| !matches!(status, key_handling::KeyStatus::Equal(_)))
+        .map(|(key, _)| key)
+        .filter(|key| {
+            args.include.is_none()
+                || key_handling::matching_pattern(
+                    key,
+                    args.include.as_deref(),
+                    &MatchOptions::default(),
+                )
+                .is_some()
+        })
+        .filter(|key| {
+            args.exclusion(key).is_some()
+                || args
+                    .value_exclusion(first_collection.get(key), second_collection.get(key))
+                    .is_some()
+        })
+        .sorted()
+        .collect()
+}
+
+/// Count the entries of each section of the diff, where the hidden sections count as empty
+fn diff_stat(
+    args: &Args,
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    key_origins: &KeyOrigins<Key>,
+) -> DiffStat {
+    let sections = args.sections();
+    let mut stat = DiffStat::default();
+    if sections.first_only {
+        stat.first_only = key_origins.first_only().len();
+    }
+    if sections.second_only {
+        stat.second_only = key_origins.second_only().len();
+    }
+    if sections.both {
+        stat.changed = unequal_keys(args, first_collection, second_collection, key_origins).len();
+        stat.equal = equal_keys(args, first_collection, second_collection, key_origins).len();
+    }
+    stat
+}
+
+/// The counts of the entries as a single line of `name=count` pairs, for log parsers
+fn summary_line(stat: &DiffStat) -> String {
+    format!(
+        "difftoml: added={} removed={} changed={} equal={}",
+        stat.second_only, stat.first_only, stat.changed, stat.equal
+    )
+}
+
+/// Display the counts of the entries in a single line
+fn display_stat(out: &mut dyn Write, args: &Args, stat: &DiffStat) -> io::Result<()> {
+    let first_only = format!("{} only in {}", stat.first_only, args.first_name());
+    let second_only = format!("{} only in {}", stat.second_only, args.second_name());
+    let changed = format!("{} changed", stat.changed);
+    let equal = format!("{} equal", stat.equal);
+    if args.color {
+        writeln!(
+            out,
+            "{}, {}, {}, {}",
+            first_only.blue(),
+            second_only.yellow(),
+            changed.red(),
+            equal.green()
+        )?;
+    } else {
+        writeln!(
+            out,
+            "{}, {}, {}, {}",
+            first_only, second_only, changed, equal
+        )?;
+    }
+    Ok(())
+}
+
+/// Display the excluded keys that differ between the collections
+fn display_excluded_audit(
+    out: &mut dyn Write,
+    differed: &[Key],
+    first_collection: &IndexMap<Vec<String>, toml::Value>,
+    second_collection: &IndexMap<Vec<String>, toml::Value>,
+    args: &Args,
+) -> io::Result<()> {
+    let color = args.color;
+    if differed.is_empty() {
+        if color {
+            writeln!(out, "\n{}", "No excluded keys differ".green())?;
+        } else {
+            writeln!(out, "\nNo excluded keys differ")?;
+        }
+        return Ok(());
+    }
+    let output = format!("{} excluded keys differ", differed.len());
+    if color {
+        writeln!(out, "\n{}", output.red())?;
+    } else {
+        writeln!(out, "\n{}", output)?;
+    }
+    let show = |val: Option<&toml::Value>| {
+        val.map_or_else(|| String::from("(missing)"), |val| args.value_text(val))
+    };
+    for key in differed.iter() {
+        writeln!(
+            out,
+            "{}: {} -> {}",
+            args.display_key(key),
+            show(first_collection.get(key)),
+            show(second_collection.get(key))
+        )?;
+    }
+    Ok(())
+}
+
+/// Display the sections of a file that were skipped because they could not be parsed
+fn display_section_errors(
+    out: &mut dyn Write,
+    path: &Path,
+    failures: &[SectionError],
+    color: bool,
+) -> io::Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    if color {
+        let output = format!("\nUnparseable sections in {}", path.display());
+        writeln!(out, "{}", output.red())?;
+    } else {
+        writeln!(out, "\nUnparseable sections in {}", path.display())?;
+    }
+    for failure in failures.iter() {
+        let header = if failure.header.is_empty() {
+            "(top level)"
+        } else {
+            &failure.header
+        };
+        writeln!(
+            out,
+            "{} (line {}): {}",
+            header,
+            failure.line,
+            failure.message.trim()
+        )?;
+    }
+    Ok(())
+}
+
+/// Display the result of checking a file against the schema
+fn display_schema_report(
+    out: &mut dyn Write,
+    args: &Args,
+    name: &str,
+    report: &SchemaReport,
+) -> io::Result<()> {
+    let color = args.color;
+    if !report.unknown.is_empty() {
+        if color {
+            let output = format!("\nKeys not in schema in {}", name);
+            writeln!(out, "{}", output.red())?;
+        } else {
+            writeln!(out, "\nKeys not in schema in {}", name)?;
+        }
+        for key in report.unknown.iter() {
+            writeln!(out, "{}", args.display_key(key))?;
+        }
+    }
+    if !report.mismatches.is_empty() {
+        if color {
+            let output = format!("\nType mismatches against schema in {}", name);
+            writeln!(out, "{}", output.red())?;
+        } else {
+            writeln!(out, "\nType mismatches against schema in {}", name)?;
+        }
+        for mismatch in report.mismatches.iter() {
+            writeln!(
+                out,
+                "{}: expected {}, found {}",
+                args.display_key(&mismatch.key),
+                mismatch.expected,
+                mismatch.found
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that flattening and rebuilding the given toml files gives back their content
+///
+/// Returns true if all files pass.
+fn validate_roundtrip(out: &mut dyn Write, args: &Args, paths: &[&Path]) -> Result<bool, Error> {
+    let color = args.color;
+    let mut valid = true;
+    for path in paths.iter() {
+        let value = parse::parse_toml_value(path)?;
+        let discrepancies = parse::roundtrip_discrepancies(&value)?;
+        if discrepancies.is_empty() {
+            if color {
+                writeln!(out, "{} {}", "Roundtrip ok:".green(), path.display())?;
+            } else {
+                writeln!(out, "Roundtrip ok: {}", path.display())?;
+            }
+        } else {
+            valid = false;
+            if color {
+                writeln!(out, "{} {}", "Roundtrip failed:".red(), path.display())?;
+            } else {
+                writeln!(out, "Roundtrip failed: {}", path.display())?;
+            }
+            for key in discrepancies.iter() {
+                writeln!(out, "{}", args.display_key(key))?;
+            }
+        }
+    }
+    Ok(valid)
+}
+
+/// The hashes of the two input files
+#[cfg(feature = "hashes")]
+fn file_hashes(args: &Args) -> Result<(String, String), Error> {
+    Ok((
+        parse::file_hash(&args.first_path)?,
+        parse::file_hash(&args.second_path)?,
+    ))
+}
+
+#[cfg(not(feature = "hashes"))]
+fn file_hashes(_args: &Args) -> Result<(String, String), Error> {
+    Err(anyhow!(
+        "--show-hashes requires difftoml to be built with the 'hashes' feature"
+    ))
+}
+
+/// Exit status when an input file fails to parse with --ignore-parse-errors
+const PARSE_ERROR_STATUS: i32 = 3;
+
+/// Exit status of a comparison, which like diff is 1 if the files differ and 0 otherwise, unless
+/// the status is turned off with --no-exit-code
+fn exit_code(args: &Args, differs: bool) -> i32 {
+    if differs && !args.no_exit_code {
+        1
+    } else {
+        0
+    }
+}
+
+/// Run the command, and return the exit status
+fn run() -> Result<i32, Error> {
+    let args = input_args()?;
+    // The color decision is made in input_args, so colored should not second-guess it
+    colored::control::set_override(args.color);
+
+    if let Some(options) = &args.canonicalize {
+        let value = parse::parse_toml_value(&args.first_path)?;
+        let mut out = args.output()?;
+        write!(out, "{}", canonical::canonicalize(value, options)?)?;
+        out.flush()?;
+        return Ok(0);
+    }
+
+    if args.validate_roundtrip {
+        let mut paths = vec![args.first_path.as_path()];
+        if args.second_path != Path::new("") {
+            paths.push(args.second_path.as_path());
+        }
+        let mut out = args.output()?;
+        let valid = validate_roundtrip(&mut out, &args, &paths)?;
+        out.flush()?;
+        return Ok(if valid { 0 } else { 1 });
+    }
+
+    if args.check {
+        if args.layers.is_empty() {
+            parse::parse_input(&args.first_path, args.first_format)?;
+        } else {
+            parse::parse_layers(&args.layers)?;
+        }
+        parse::parse_input(&args.second_path, args.second_format)?;
+        return Ok(0);
+    }
+
+    if !args.baselines.is_empty() {
+        let baselines = args
+            .baselines
+            .iter()
+            .map(|path| parse::parse_input(path, InputFormat::default()))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let target = parse::parse_input(&args.second_path, args.second_format)?;
+        let mut out = args.output()?;
+        let drift = drift::baseline_drift(&baselines, &target, &args.comparison(), &|key| {
+            args.is_compared(key)
+        });
+        let names: Vec<String> = args
+            .baselines
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        let differs = drift.has_changes();
+        write!(
+            out,
+            "{}",
+            drift::render(&drift, &names, &baselines, &target, &args.text_style())
+        )?;
+        out.flush()?;
+        return Ok(exit_code(&args, differs));
+    }
+
+    if let Some(third_path) = &args.third_path {
+        let base = parse::parse_input(&args.first_path, args.first_format)?;
+        let ours = parse::parse_input(&args.second_path, args.second_format)?;
+        let theirs = parse::parse_input(third_path, input_format(third_path, None)?)?;
+        let mut out = args.output()?;
+        let mut origins =
+            key_handling::compare_three_way(&base, &ours, &theirs, &args.comparison());
+        origins.retain(|key| args.is_compared(key));
+        write!(
+            out,
+            "{}",
+            three_way::render(
+                &origins,
+                &base,
+                &ours,
+                &theirs,
+                &args.second_name(),
+                &third_path.display().to_string(),
+                &args.text_style()
+            )
+        )?;
+        let conflicts = !origins.conflicts().is_empty();
+        out.flush()?;
+        return Ok(exit_code(&args, conflicts));
+    }
+
+    if args.first_path.is_dir() && args.second_path.is_dir() {
+        return diff_directories(args);
+    }
+    diff_files(&args, &mut args.output()?)
+}
+
+/// Paths of the toml files in a directory and its subdirectories, relative to the directory
+fn toml_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::<PathBuf>::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let entries = std::fs::read_dir(dir.join(&relative))
+            .map_err(|msg| anyhow!("Could not read {}: {}", dir.join(&relative).display(), msg))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if parse::format_extension(&path) == Some("toml") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Editors that open a file at a line given as `+LINE` before the file
+const LINE_ARG_EDITORS: &[&str] = &[
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "micro",
+    "kak",
+    "joe",
+];
+
+/// Editors that open a file at a line given as `--goto file:LINE`
+const GOTO_ARG_EDITORS: &[&str] = &["code", "codium"];
+
+/// The earliest line of the keys in a file, if any of them is in it
+fn first_difference_line<'a>(
+    lines: &LineNumbers,
+    keys: impl Iterator<Item = &'a Key>,
+) -> Option<usize> {
+    keys.filter_map(|key| lines::line_of(lines, key)).min()
+}
+
+/// Command line opening the files in the editor, which may have arguments of its own, like
+/// `code -w`. The files are opened at their lines if the editor is known to support it, and
+/// otherwise only the files are given.
+fn editor_command(editor: &str, files: &[(&Path, Option<usize>)]) -> Vec<String> {
+    let mut command: Vec<String> = editor.split_whitespace().map(String::from).collect();
+    let name = command
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string();
+    for (path, line) in files.iter() {
+        match line {
+            Some(line) if LINE_ARG_EDITORS.contains(&name.as_str()) => {
+                command.push(format!("+{}", line));
+                command.push(path.display().to_string());
+            }
+            Some(line) if GOTO_ARG_EDITORS.contains(&name.as_str()) => {
+                command.push(String::from("--goto"));
+                command.push(format!("{}:{}", path.display(), line));
+            }
+            _ => command.push(path.display().to_string()),
+        }
+    }
+    command
+}
+
+/// Open the files in `$VISUAL` or `$EDITOR`, and wait for the editor to exit
+fn edit_files(files: &[(&Path, Option<usize>)]) -> Result<(), Error> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .ok_or_else(|| anyhow!("--edit requires the VISUAL or EDITOR environment variable"))?;
+    let command = editor_command(&editor, files);
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .map_err(|msg| anyhow!("Could not start the editor '{}': {}", editor, msg))?;
+    if !status.success() {
+        return Err(anyhow!("The editor '{}' failed with {}", editor, status));
+    }
+    Ok(())
+}
+
+/// Exit status of a run that failed, like for a file that could not be read
+const ERROR_STATUS: i32 = 2;
+
+/// Run `task` on each of the items with the given number of threads, or with a thread per CPU if
+/// no number is given, and return the results in the order of the items
+#[cfg(feature = "parallel")]
+fn map_parallel<T: Sync, R: Send>(
+    items: &[T],
+    threads: Option<usize>,
+    task: impl Fn(&T) -> R + Send + Sync,
+) -> Result<Vec<R>, Error> {
+    use rayon::prelude::*;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or_default())
+        .build()?;
+    Ok(pool.install(|| items.par_iter().map(task).collect()))
+}
+
+/// Run `task` on each of the items, one at a time, without the 'parallel' feature
+#[cfg(not(feature = "parallel"))]
+fn map_parallel<T: Sync, R: Send>(
+    items: &[T],
+    _threads: Option<usize>,
+    task: impl Fn(&T) -> R + Send + Sync,
+) -> Result<Vec<R>, Error> {
+    Ok(items.iter().map(task).collect())
+}
+
+/// Diff the toml files with the same relative paths in two directories, and list the files that
+/// are only in one of them. Return the highest exit status of the files.
+///
+/// The files are diffed in parallel, as given by --threads, and their diffs are written in the
+/// order of the files. A file that can not be diffed is reported on stderr without stopping the
+/// others, and the number of such files is reported at the end.
+fn diff_directories(args: Args) -> Result<i32, Error> {
+    if args.edit {
+        return Err(anyhow!("--edit can not be used when comparing directories"));
+    }
+    let mut out = args.output()?;
+    let first_dir = args.first_path.clone();
+    let second_dir = args.second_path.clone();
+    let file_origins =
+        key_handling::compare_vectors(&toml_files(&first_dir)?, &toml_files(&second_dir)?)?;
+
+    let mut status = 0;
+    for (dir, files) in [
+        (&first_dir, file_origins.first_only()),
+        (&second_dir, file_origins.second_only()),
+    ] {
+        if files.is_empty() {
+            continue;
+        }
+        status = exit_code(&args, true);
+        if args.quiet {
+            continue;
+        }
+        let heading = format!("\nFiles only found in {}", dir.display());
+        if !args.color {
+            writeln!(out, "{}", heading)?;
+        } else if dir == &first_dir {
+            writeln!(out, "{}", heading.blue())?;
+        } else {
+            writeln!(out, "{}", heading.yellow())?;
+        }
+        for file in files.iter() {
+            writeln!(out, "{}", file.display())?;
+        }
+    }
+
+    // In quiet mode, the status of the files in both directories can not make the status any
+    // higher
+    let files = if args.quiet && status > 0 {
+        Vec::new()
+    } else {
+        file_origins.both()
+    };
+    let diffs = map_parallel(&files, args.threads, |file| {
+        let mut file_args = args.clone();
+        file_args.first_path = first_dir.join(file);
+        file_args.second_path = second_dir.join(file);
+        let mut diff = Vec::<u8>::new();
+        let status = diff_files(&file_args, &mut diff);
+        (diff, status)
+    })?;
+
+    let mut failed = 0;
+    for (file, (diff, file_status)) in files.iter().zip(diffs) {
+        if !args.quiet {
+            let header = format!("\n=== {} ===", file.display());
+            if args.color {
+                writeln!(out, "{}", header.bold())?;
+            } else {
+                writeln!(out, "{}", header)?;
+            }
+        }
+        out.write_all(&diff)?;
+        match file_status {
+            Ok(file_status) => status = status.max(file_status),
+            Err(msg) => {
+                eprintln!("Error: {}: {:#}", file.display(), msg);
+                failed += 1;
+            }
+        }
+    }
+    out.flush()?;
+    if failed > 0 {
+        eprintln!("{} of {} files could not be diffed", failed, files.len());
+        status = status.max(ERROR_STATUS);
+    }
+    Ok(status)
+}
+
+/// Find the line numbers of the keys of the input files that are parsed as toml, where the merge
+/// of several layers has no line numbers
+fn source_lines(args: &Args) -> Result<SourceLines, Error> {
+    let mut source_lines = SourceLines::default();
+    if args.layers.is_empty() && args.first_format == InputFormat::Toml {
+        source_lines.first = lines::parse_file(&args.first_path)?;
+    }
+    if args.second_format == InputFormat::Toml {
+        source_lines.second = lines::parse_file(&args.second_path)?;
+    }
+    // The keys of the second file are aligned with the first file, like the keys of the collections
+    if args.ignore_key_case {
+        let first_keys: Vec<Key> = source_lines.first.keys().cloned().collect();
+        let second = source_lines.second.into_iter().collect();
+        source_lines.second = key_handling::align_key_case(&first_keys, second)
+            .into_iter()
+            .collect();
+    }
+    Ok(source_lines)
+}
+
+/// Diff the files given by the arguments, write the diff to `out`, and return the exit status
+fn diff_files(args: &Args, out: &mut dyn Write) -> Result<i32, Error> {
+    let (first_value, second_value) = if args.lenient_parse {
+        let (first_value, first_failures) = parse::parse_toml_lenient_value(&args.first_path)?;
+        let (second_value, second_failures) = parse::parse_toml_lenient_value(&args.second_path)?;
+        if !args.quiet {
+            let mut reports = args.report_output(out);
+            display_section_errors(&mut reports, &args.first_path, &first_failures, args.color)?;
+            display_section_errors(
+                &mut reports,
+                &args.second_path,
+                &second_failures,
+                args.color,
+            )?;
+        }
+        (first_value, second_value)
+    } else {
+        let first = if args.layers.is_empty() {
+            parse::parse_input_value(&args.first_path, args.first_format)
+        } else {
+            parse::parse_layers_value(&args.layers)
+        };
+        let second = parse::parse_input_value(&args.second_path, args.second_format);
+        if args.ignore_parse_errors && (first.is_err() || second.is_err()) {
+            if !args.quiet {
+                let mut reports = args.report_output(out);
+                for (name, result) in [(args.first_name(), &first), (args.second_name(), &second)] {
+                    if let Err(msg) = result {
+                        let output = format!("{} failed to parse: {}", name, msg.root_cause());
+                        if args.color {
+                            writeln!(reports, "{}", output.red())?;
+                        } else {
+                            writeln!(reports, "{}", output)?;
+                        }
+                    }
+                }
+            }
+            out.flush()?;
+            return Ok(PARSE_ERROR_STATUS);
+        }
+        (first?, second?)
+    };
+    // The keys of the arrays of tables are kept for rebuilding the arrays, rather than guessing
+    // them from tables keyed by indices
+    let (mut first_collection, first_arrays) = parse::flatten_value_arrays(first_value);
+    let (mut second_collection, second_arrays) = parse::flatten_value_arrays(second_value);
+
+    // Arrays of tables are flattened with indexed keys, which are compared positionally, so they
+    // are compared as whole arrays with the other algorithms
+    if args.array_algorithm != array_diff::Algorithm::Positional
+        || !args.array_identities.is_empty()
+    {
+        let grouped = |key: &[String]| {
+            args.array_algorithm != array_diff::Algorithm::Positional
+                || array_diff::spec_for(&args.array_identities, key).is_some()
+        };
+        first_collection = parse::group_arrays(&first_collection, &first_arrays, &grouped)?;
+        second_collection = parse::group_arrays(&second_collection, &second_arrays, &grouped)?;
+    }
+
+    // The keys that only differ in case are aligned before they are used by anything else, like the
+    // merge. Only the keys of the directives are left to be aligned when the collections are
+    // compared.
+    if args.ignore_key_case {
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        second_collection = key_handling::align_key_case(&first_keys, second_collection);
+    }
+
+    if let Some(merge_path) = &args.merge {
+        let (merged, conflicts) = merge::merge_collections(
+            &first_collection,
+            &second_collection,
+            args.merge_prefer,
+            &args.comparison(),
+        );
+        let arrays: HashSet<Key> = first_arrays.union(&second_arrays).cloned().collect();
+        let content = merge::render(&merged, &arrays)?;
+        std::fs::write(merge_path, content)
+            .map_err(|msg| anyhow!("Could not write {}: {}", merge_path.display(), msg))?;
+        let preferred = match args.merge_prefer {
+            merge::Preference::First => args.first_name(),
+            merge::Preference::Second => args.second_name(),
+        };
+        writeln!(
+            out,
+            "Merged into {}, keeping the values of {} for {} keys with unequal values",
+            merge_path.display(),
+            preferred,
+            conflicts
+        )?;
+        out.flush()?;
+        return Ok(0);
+    }
+
+    if args.parse_comment_directives {
+        if args.layers.is_empty() {
+            first_collection.extend(directives::parse_file(&args.first_path)?);
+        } else {
+            first_collection.extend(directives::parse_files(&args.layers)?);
+        }
+        second_collection.extend(directives::parse_file(&args.second_path)?);
+    }
+
+    if !args.quiet {
+        let keys: Vec<Key> = first_collection
+            .keys()
+            .chain(second_collection.keys())
+            .cloned()
+            .collect();
+        for pattern in
+            key_handling::unused_patterns(&keys, args.exclude.as_deref(), &args.exclude_match)
+        {
+            eprintln!(
+                "Warning: exclude key '{}' matches no key in either file",
+                pattern
+            );
+        }
+    }
+
+    let DiffResult {
+        first: mut first_collection,
+        second: mut second_collection,
+        mut key_origins,
+        ..
+    } = difftoml::diff_collections(first_collection, second_collection, &args.diff_options())?;
+
+    // The keys are compared after the transform, which does not change them
+    if let Some(transform) = &args.value_transform {
+        first_collection = value_handling::transform_values(first_collection, transform.as_ref());
+        second_collection = value_handling::transform_values(second_collection, transform.as_ref());
+    }
+
+    // The defaults are filled in after the keys and values of the files are transformed, and get
+    // the same transforms, so they match the keys and compare like the values of the files
+    if let Some(defaults) = &args.defaults {
+        let mut defaults = defaults.clone();
+        if args.ignore_key_case {
+            let keys: Vec<Key> = first_collection
+                .keys()
+                .chain(second_collection.keys())
+                .cloned()
+                .collect();
+            defaults = key_handling::align_key_case(&keys, defaults);
+        }
+        if let Some(transform) = &args.value_transform {
+            defaults = value_handling::transform_values(defaults, transform.as_ref());
+        }
+        key_origins = fill_defaults(
+            args,
+            &defaults,
+            &mut first_collection,
+            &mut second_collection,
+            &key_origins,
+        )?;
+    }
+
+    if let Some(key) = &args.explain_exclusions {
+        for (stage, outcome) in explain_exclusions(args, key, &first_collection, &second_collection)
+        {
+            writeln!(out, "{}: {}", stage, outcome)?;
+        }
+        out.flush()?;
+        return Ok(0);
+    }
+
+    let hashes = if args.show_hashes {
+        Some(file_hashes(args)?)
+    } else {
+        None
+    };
+
+    if args.quiet {
+        let differs = has_differences(args, &first_collection, &second_collection, &key_origins);
+        return Ok(exit_code(args, differs));
+    }
+
+    let full_report = || {
+        let mut report = diff_report(args, &first_collection, &second_collection, &key_origins);
+        if let Some((first_hash, second_hash)) = &hashes {
+            report.first_hash = Some(first_hash.clone());
+            report.second_hash = Some(second_hash.clone());
+        }
+        report
+    };
+    // The text and JSON reports display the hashes, while they come before the other formats
+    if let Some((first_hash, second_hash)) = &hashes {
+        let in_report = !args.stat
+            && (args.format == OutputFormat::Json
+                || args.format == OutputFormat::Text && args.side_by_side.is_none());
+        if !in_report {
+            let mut reports = args.report_output(out);
+            writeln!(reports, "first: {} second: {}", first_hash, second_hash)?;
+        }
+    }
+    match args.format {
+        OutputFormat::Text if args.stat => {
+            let stat = diff_stat(args, &first_collection, &second_collection, &key_origins);
+            display_stat(out, args, &stat)?;
+        }
+        OutputFormat::Json if args.stat => {
+            let stat = diff_stat(args, &first_collection, &second_collection, &key_origins);
+            writeln!(out, "{}", json::render_stat(&stat))?;
+        }
+        OutputFormat::Text if args.side_by_side.is_some() => {
+            let report = full_report();
+            let width = args.side_by_side.unwrap_or_default();
+            write!(out, "{}", side_by_side::render(&report, width, args.color))?;
+        }
+        OutputFormat::Text => {
+            let report = full_report();
+            let annotations =
+                annotations(args, &first_collection, &second_collection, &key_origins)?;
+            display(out, args, &report, &annotations)?;
+        }
+        OutputFormat::Html => {
+            let report = full_report();
+            write!(out, "{}", html::render(&report))?;
+        }
+        OutputFormat::Tap => {
+            let report = full_report();
+            write!(out, "{}", tap::render(&report))?;
+        }
+        OutputFormat::Json => {
+            let report = full_report();
+            writeln!(out, "{}", json::render(&report))?;
+        }
+        OutputFormat::Unified => {
+            let report = full_report();
+            write!(out, "{}", unified::render(&report, args.color))?;
+        }
+        OutputFormat::TomlPatch => {
+            let report = full_report();
+            write!(out, "{}", toml_patch::render(&report)?)?;
+        }
+    }
+
+    let differs = has_differences(args, &first_collection, &second_collection, &key_origins);
+
+    let mut failed = false;
+    if args.audit_excluded {
+        let differed = excluded_differences(args, &first_collection, &second_collection);
+        display_excluded_audit(
+            &mut args.report_output(out),
+            &differed,
+            &first_collection,
+            &second_collection,
+            args,
+        )?;
+        failed |= !differed.is_empty();
+    }
+
+    if let Some(schema_path) = &args.schema {
+        let schema = parse::parse_toml(schema_path)?;
+        let first_report = schema::check(&first_collection, &schema, args.check_types);
+        let second_report = schema::check(&second_collection, &schema, args.check_types);
+        let mut reports = args.report_output(out);
+        display_schema_report(&mut reports, args, &args.first_name(), &first_report)?;
+        display_schema_report(&mut reports, args, &args.second_name(), &second_report)?;
+        failed |= first_report.has_mismatches() || second_report.has_mismatches();
+    }
+    out.flush()?;
+
+    if args.summary_line {
+        let stat = diff_stat(args, &first_collection, &second_collection, &key_origins);
+        eprintln!("{}", summary_line(&stat));
+    }
+
+    if args.edit && differs {
+        let report = full_report();
+        let source_lines = source_lines(args)?;
+        let first_keys = report.first_only.iter().map(|(key, _)| key);
+        let second_keys = report.second_only.iter().map(|(key, _)| key);
+        let unequal_keys = || report.unequal.iter().map(|(key, _, _)| key);
+        let mut files = Vec::<(&Path, Option<usize>)>::new();
+        // The merge of several layers has no single file to edit
+        if args.layers.is_empty() {
+            let line = first_difference_line(&source_lines.first, first_keys.chain(unequal_keys()));
+            files.push((&args.first_path, line));
+        }
+        let line = first_difference_line(&source_lines.second, second_keys.chain(unequal_keys()));
+        files.push((&args.second_path, line));
+        edit_files(&files)?;
+    }
+
+    if failed {
+        return Ok(1);
+    }
+    Ok(exit_code(args, differs))
+}
+
+fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(msg) => {
+            eprintln!("Error: {:#}", msg);
+            process::exit(ERROR_STATUS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_excludes() {
+        assert_eq!(None, merge_excludes(None, None));
+        assert_eq!(None, merge_excludes(Some(String::new()), None));
+        assert_eq!(
+            Some(String::from("key1")),
+            merge_excludes(Some(String::from("key1")), None)
+        );
+        assert_eq!(
+            Some(String::from("key2.key3")),
+            merge_excludes(None, Some(String::from("key2.key3")))
+        );
+        assert_eq!(
+            Some(String::from("key1,key2.key3")),
+            merge_excludes(Some(String::from("key1")), Some(String::from("key2.key3")))
+        );
+    }
+
+    #[test]
+    fn test_context_size() {
+        assert_eq!(1, context_size(5.0, 0));
+        assert_eq!(1, context_size(5.0, 20));
+        assert_eq!(5, context_size(5.0, 100));
+        assert_eq!(10, context_size(10.0, 100));
+        assert_eq!(10, context_size(5.0, 200));
+        // Large files get at most the maximum
+        assert_eq!(MAX_CONTEXT, context_size(5.0, 10_000));
+        assert_eq!(MAX_CONTEXT, context_size(100.0, 30));
+    }
+
+    #[test]
+    fn test_editor_command() {
+        let first = Path::new("first.toml");
+        let second = Path::new("second.toml");
+        let files = [(first, Some(3)), (second, None)];
+        assert_eq!(
+            vec!["vim", "+3", "first.toml", "second.toml"],
+            editor_command("vim", &files)
+        );
+        assert_eq!(
+            vec!["/usr/bin/nano", "+3", "first.toml", "second.toml"],
+            editor_command("/usr/bin/nano", &files)
+        );
+        assert_eq!(
+            vec!["code", "-w", "--goto", "first.toml:3", "second.toml"],
+            editor_command("code -w", &files)
+        );
+        // Editors without known line arguments only get the files
+        assert_eq!(
+            vec!["ed", "first.toml", "second.toml"],
+            editor_command("ed", &files)
+        );
+    }
+
+    #[test]
+    fn test_first_difference_line() {
+        let lines = lines::parse_str("a = 1\n[table]\nb = 2\nc = 3\n");
+        let key = |dotted: &str| dotted.split('.').map(String::from).collect::<Key>();
+        let keys = [key("table.c"), key("table.b"), key("missing")];
+        assert_eq!(Some(3), first_difference_line(&lines, keys.iter()));
+        assert_eq!(None, first_difference_line(&lines, keys[2..].iter()));
+    }
+
+    #[test]
+    fn test_use_pager() {
+        assert!(use_pager(true, false, true));
+        // Output that is not to a terminal is never paged
+        assert!(!use_pager(true, false, false));
+        assert!(!use_pager(true, true, true));
+        assert!(!use_pager(false, false, true));
+    }
+
+    #[test]
+    fn test_exclude_file() {
+        assert_eq!(None, exclude_file_keys("# Only comments\n\n"));
+
+        let content =
+            parse::read_file_to_string(Path::new("assets/exclude.txt")).expect("Could not read");
+        let exclude = merge_excludes(
+            Some(String::from("float_value")),
+            exclude_file_keys(&content),
+        );
+        assert_eq!(
+            Some(String::from("float_value,version,field1.values,*.name")),
+            exclude
+        );
+
+        let collection =
+            parse::parse_toml(Path::new("assets/test_1.toml")).expect("Could not parse");
+        let keys: Vec<Key> = collection.keys().cloned().collect();
+        let test = key_handling::filter_keys(&keys, exclude, &MatchOptions::default());
+        let expected: Vec<Key> = vec![
+            vec!["name"],
+            vec!["int_value"],
+            vec!["field0", "values"],
+            vec!["field2", "values"],
+        ]
+        .into_iter()
+        .map(|key| key.into_iter().map(String::from).collect())
+        .collect();
+        assert_eq!(expected, test);
+    }
+
+    #[test]
+    fn test_config_parse() {
+        let config = Config::parse(
+            "exclude = [\"package.version\", \"dependencies.*.path\"]\ncolor = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Config {
+                exclude: Some(String::from("package.version,dependencies.*.path")),
+                color: Some(ColorChoice::Always),
+                display_equal: None,
+            },
+            config
+        );
+        let config =
+            Config::parse("exclude = \"a,b\"\ncolor = \"never\"\ndisplay_equal = true").unwrap();
+        assert_eq!(Some(String::from("a,b")), config.exclude);
+        assert_eq!(Some(ColorChoice::Never), config.color);
+        assert_eq!(Some(true), config.display_equal);
+        assert_eq!(Config::default(), Config::parse("").unwrap());
+
+        assert!(Config::parse("exclude = [1]").is_err());
+        assert!(Config::parse("color = \"sometimes\"").is_err());
+        assert!(Config::parse("display_equal = \"yes\"").is_err());
+        assert!(Config::parse("excludes = [\"a\"]").is_err());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert_eq!(ColorChoice::Auto, ColorChoice::from_arg("auto"));
+        assert_eq!(ColorChoice::Always, ColorChoice::from_arg("always"));
+        assert_eq!(ColorChoice::Never, ColorChoice::from_arg("never"));
+
+        assert!(ColorChoice::Auto.use_color(true, false));
+        assert!(!ColorChoice::Auto.use_color(false, false));
+        assert!(ColorChoice::Always.use_color(true, false));
+        assert!(ColorChoice::Always.use_color(false, false));
+        assert!(!ColorChoice::Never.use_color(true, false));
+        assert!(!ColorChoice::Never.use_color(false, false));
+
+        // NO_COLOR wins over everything
+        assert!(!ColorChoice::Auto.use_color(true, true));
+        assert!(!ColorChoice::Always.use_color(true, true));
+    }
+
+    #[test]
+    fn test_has_differences() {
+        let first_collection = parse::parse_toml(Path::new("assets/test_1.toml")).unwrap();
+        let second_collection = parse::parse_toml(Path::new("assets/test_2.toml")).unwrap();
+
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+
+        let identical = key_handling::compare_vectors(&first_keys, &first_keys).unwrap();
+        assert!(!has_differences(
+            &Args::default(),
+            &first_collection,
+            &first_collection,
+            &identical
+        ));
+
+        let different = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+        assert!(has_differences(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &different
+        ));
+    }
+
+    #[test]
+    fn test_one_sided_subtrees() {
+        let first = parse::flatten_value(
+            r#"
+            name = "a"
+            [db]
+            host = "localhost"
+            port = 5432
+            [db.pool]
+            size = 4
+            timeout = 30
+            [cache.redis]
+            host = "localhost"
+            port = 6379
+            [log]
+            level = "info"
+        "#
+            .parse()
+            .unwrap(),
+        );
+        let second = parse::flatten_value(
+            r#"
+            name = "a"
+            [db]
+            host = "localhost"
+        "#
+            .parse()
+            .unwrap(),
+        );
+        let first_keys: Vec<Key> = first.keys().cloned().collect();
+        let second_keys: Vec<Key> = second.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+        let first_only = key_origins.first_only();
+
+        // The db table is partially shared, so only its pool subtable is collapsed, and the log
+        // table has a single key
+        let key = |s: &str| -> Key { s.split('.').map(String::from).collect() };
+        let subtrees = one_sided_subtrees(&first_only, &second);
+        assert_eq!(vec![key("db.pool"), key("cache")], subtrees);
+
+        let values: Vec<(Key, &toml::Value)> =
+            first_only.iter().map(|k| (k.clone(), &first[k])).collect();
+        let no_lines = |_: &[String]| String::new();
+        let lines = one_sided_lines(
+            &values,
+            &subtrees,
+            &HashMap::new(),
+            ".",
+            &no_lines,
+            None,
+            None,
+        );
+        assert_eq!(
+            vec![
+                "db.port: 5432",
+                "[db.pool.*] (2 keys)",
+                "[cache.*] (2 keys)",
+                "log.level: \"info\""
+            ],
+            lines
+        );
+        assert!(one_sided_subtrees(&key_origins.second_only(), &first).is_empty());
+    }
+
+    #[test]
+    fn test_closest_siblings() {
+        let keys: Vec<Key> = vec![
+            "a",
+            "db.host",
+            "db.port",
+            "b",
+            "db.user",
+            "db.name",
+            "db.tls.on",
+        ]
+        .into_iter()
+        .map(|key| key.split('.').map(String::from).collect())
+        .collect();
+        let groups = group_by_parent(&keys);
+        let db = &groups[&vec![String::from("db")]];
+        assert_eq!(4, db.len());
+        assert_eq!(2, groups[&Key::new()].len());
+
+        let port = vec![String::from("db"), String::from("port")];
+        let names = |siblings: Vec<Key>| -> Vec<String> {
+            siblings.iter().map(|key| key.join(".")).collect()
+        };
+        assert!(closest_siblings(&port, db, 0).is_empty());
+        assert_eq!(vec!["db.host"], names(closest_siblings(&port, db, 1)));
+        assert_eq!(
+            vec!["db.host", "db.user", "db.name"],
+            names(closest_siblings(&port, db, 5))
+        );
+        let missing = vec![String::from("db"), String::from("pass")];
+        assert!(closest_siblings(&missing, db, 2).is_empty());
+    }
+
+    #[test]
+    fn test_group_by_type() {
+        let first_collection = parse::parse_toml(Path::new("assets/test_1.toml")).unwrap();
+        let mut second_collection = first_collection.clone();
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        second_collection.insert(key("name"), toml::Value::String(String::from("changed")));
+        second_collection.insert(key("field0.name"), toml::Value::Integer(1));
+        second_collection.insert(key("int_value"), toml::Value::Float(123.5));
+        second_collection.insert(key("float_value"), toml::Value::Float(3.21));
+        second_collection.insert(key("field1.values"), toml::Value::Array(vec![]));
+
+        let keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&keys, &keys).unwrap();
+        let mut unequal: Vec<(Key, &toml::Value, &toml::Value)> = unequal_keys(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &key_origins,
+        )
+        .into_iter()
+        .map(|key| {
+            let (first_val, second_val) = (&first_collection[&key], &second_collection[&key]);
+            (key, first_val, second_val)
+        })
+        .collect();
+        unequal.sort_by(|(first_key, _, _), (second_key, _, _)| first_key.cmp(second_key));
+        let groups = group_by_type(&unequal);
+
+        assert_eq!(
+            vec![
+                (ChangeKind::String, vec![key("name")]),
+                (
+                    ChangeKind::Numeric,
+                    vec![key("float_value"), key("int_value")]
+                ),
+                (ChangeKind::Array, vec![key("field1.values")]),
+                (ChangeKind::TypeChange, vec![key("field0.name")]),
+            ],
+            groups
+        );
+    }
+
+    #[test]
+    fn test_group_by_regex() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let keys = vec![
+            key("services.web.port"),
+            key("name"),
+            key("services.db.port"),
+            key("services.web.host"),
+            key("services"),
+        ];
+        let regex = Regex::new(r"^services\.([^.]+)").unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("db"), vec![key("services.db.port")]),
+                (
+                    String::from("web"),
+                    vec![key("services.web.port"), key("services.web.host")]
+                ),
+                (String::from("other"), vec![key("name"), key("services")]),
+            ],
+            group_by_regex(&keys, &regex)
+        );
+
+        // A capture group that does not participate in the match puts the key in "other"
+        let regex = Regex::new(r"^name|^services\.([^.]+)").unwrap();
+        assert_eq!(
+            (String::from("other"), vec![key("name"), key("services")]),
+            group_by_regex(&keys, &regex).pop().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_collection = parse::flatten_value(
+            "removed = 1\nsame = 2\n[table]\nchanged = 3\nclose = 1.0"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "same = 2\nadded = 4\n[table]\nchanged = 5\nclose = 1.0001"
+                .parse()
+                .unwrap(),
+        );
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+        let args = Args {
+            first_path: PathBuf::from("first.toml"),
+            second_path: PathBuf::from("second.toml"),
+            display_equal: true,
+            float_tolerance: Some(0.001),
+            ..Default::default()
+        };
+        let report = diff_report(&args, &first_collection, &second_collection, &key_origins);
+
+        assert_eq!("first.toml", report.first_name);
+        assert_eq!("second.toml", report.second_name);
+        assert_eq!(
+            vec![(key("removed"), &toml::Value::Integer(1))],
+            report.first_only
+        );
+        assert_eq!(
+            vec![(key("added"), &toml::Value::Integer(4))],
+            report.second_only
+        );
+        assert_eq!(
+            vec![(
+                key("table.changed"),
+                &toml::Value::Integer(3),
+                &toml::Value::Integer(5)
+            )],
+            report.unequal
+        );
+        // Equal entries keep both values, which differ within the tolerance
+        assert_eq!(
+            vec![
+                (
+                    key("same"),
+                    &toml::Value::Integer(2),
+                    &toml::Value::Integer(2)
+                ),
+                (
+                    key("table.close"),
+                    &toml::Value::Float(1.0),
+                    &toml::Value::Float(1.0001)
+                ),
+            ],
+            report.equal
+        );
+
+        // Hidden sections are left out
+        let args = Args {
+            changed_only: true,
+            ..Default::default()
+        };
+        let report = diff_report(&args, &first_collection, &second_collection, &key_origins);
+        assert!(report.first_only.is_empty());
+        assert!(report.second_only.is_empty());
+        assert!(report.equal.is_empty());
+        assert_eq!(2, report.unequal.len());
+    }
+
+    #[test]
+    fn test_cross_references() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_collection = parse::flatten_value(
+            "[old]\nport = 80\nhost = \"a\"\n[other]\nport = 80"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection =
+            parse::flatten_value("[new]\nport = 80\nhost = \"b\"".parse().unwrap());
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+
+        let first_references = cross_references(
+            &first_keys,
+            &first_collection,
+            &second_keys,
+            &second_collection,
+        );
+        let second_references = cross_references(
+            &second_keys,
+            &second_collection,
+            &first_keys,
+            &first_collection,
+        );
+
+        // The relocated port is cross-referenced both ways, and the new host is not
+        assert_eq!(2, first_references.len());
+        assert_eq!(
+            " (also appears as new.port)",
+            also_appears_as(&first_references, &key("old.port"), ".")
+        );
+        assert_eq!(
+            " (also appears as old.port, other.port)",
+            also_appears_as(&second_references, &key("new.port"), ".")
+        );
+        assert_eq!(
+            "",
+            also_appears_as(&first_references, &key("old.host"), ".")
+        );
+        assert_eq!(
+            "",
+            also_appears_as(&second_references, &key("new.host"), ".")
+        );
+    }
+
+    #[test]
+    fn test_excluded_differences() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_collection = parse::flatten_value(
+            "[secret]\ntoken = 1\nsalt = 1\n[public]\nname = 1"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "[secret]\ntoken = 2\nsalt = 1\nextra = 1\n[public]\nname = 2"
+                .parse()
+                .unwrap(),
+        );
+
+        let args = Args {
+            exclude: Some(String::from("secret")),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![key("secret.extra"), key("secret.token")],
+            excluded_differences(&args, &first_collection, &second_collection)
+        );
+
+        // The excluded key is equal
+        let args = Args {
+            exclude: Some(String::from("salt")),
+            ..Default::default()
+        };
+        assert!(excluded_differences(&args, &first_collection, &second_collection).is_empty());
+
+        // Keys left out by the exclude value are audited too
+        let args = Args {
+            exclude_value: Some(String::from("2")),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![key("public.name"), key("secret.token")],
+            excluded_differences(&args, &first_collection, &second_collection)
+        );
+        assert!(
+            excluded_differences(&Args::default(), &first_collection, &second_collection)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_diff_stat() {
+        let first_collection = parse::flatten_value(
+            "removed = 1
+changed = 1
+same = 1
+[table]
+same = 1"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "added = 1
+changed = 2
+same = 1
+[table]
+same = 1"
+                .parse()
+                .unwrap(),
+        );
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+
+        let stat = diff_stat(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &key_origins,
+        );
+        assert_eq!(
+            DiffStat {
+                first_only: 1,
+                second_only: 1,
+                changed: 1,
+                equal: 2,
+            },
+            stat
+        );
+        assert_eq!(
+            "difftoml: added=1 removed=1 changed=1 equal=2",
+            summary_line(&stat)
+        );
+
+        let args = Args {
+            added_only: true,
+            ..Default::default()
+        };
+        let stat = diff_stat(&args, &first_collection, &second_collection, &key_origins);
+        assert_eq!(
+            DiffStat {
+                second_only: 1,
+                ..Default::default()
+            },
+            stat
+        );
+    }
+
+    #[test]
+    fn test_toml_files() {
+        let first_files = toml_files(Path::new("assets/dirs/first")).unwrap();
+        let second_files = toml_files(Path::new("assets/dirs/second")).unwrap();
+        assert_eq!(
+            vec![
+                PathBuf::from("logging.toml"),
+                PathBuf::from("services/api.toml"),
+                PathBuf::from("services/db.toml"),
+            ],
+            first_files
+        );
+
+        let file_origins = key_handling::compare_vectors(&first_files, &second_files).unwrap();
+        assert_eq!(
+            vec![PathBuf::from("services/db.toml")],
+            file_origins.first_only()
+        );
+        assert_eq!(
+            vec![PathBuf::from("services/cache.toml")],
+            file_origins.second_only()
+        );
+        assert_eq!(
+            vec![
+                PathBuf::from("logging.toml"),
+                PathBuf::from("services/api.toml"),
+            ],
+            file_origins.both()
+        );
+        assert!(toml_files(Path::new("assets/dirs/missing")).is_err());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(0, exit_code(&Args::default(), false));
+        assert_eq!(1, exit_code(&Args::default(), true));
+        let args = Args {
+            no_exit_code: true,
+            ..Default::default()
+        };
+        assert_eq!(0, exit_code(&args, false));
+        assert_eq!(0, exit_code(&args, true));
+    }
+
+    #[test]
+    fn test_approved() {
+        let first_collection =
+            parse::flatten_value("approved = 1\nunapproved = 1\nother = 1".parse().unwrap());
+        let second_collection =
+            parse::flatten_value("approved = 2\nunapproved = 3\nother = 2".parse().unwrap());
+        let approved = parse::flatten_value("approved = 2\nunapproved = 2".parse().unwrap());
+        let args = Args {
+            approved: Some(approved),
+            ..Default::default()
+        };
+
+        let keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&keys, &keys).unwrap();
+        let mut unequal = unequal_keys(&args, &first_collection, &second_collection, &key_origins);
+        unequal.sort();
+        assert_eq!(
+            vec![
+                vec![String::from("other")],
+                vec![String::from("unapproved")]
+            ],
+            unequal
+        );
+    }
+
+    #[test]
+    fn test_fill_defaults() {
+        let mut first_collection =
+            parse::flatten_value("same = 1\nremoved = 80\nremoved_other = 1".parse().unwrap());
+        let mut second_collection = parse::flatten_value(
+            "same = 1\nadded = true\nadded_other = false\nno_default = 1"
+                .parse()
+                .unwrap(),
+        );
+        let defaults = parse::flatten_value(
+            "removed = 80\nremoved_other = 2\nadded = true\nadded_other = true"
+                .parse()
+                .unwrap(),
+        );
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+
+        let key_origins = fill_defaults(
+            &Args::default(),
+            &defaults,
+            &mut first_collection,
+            &mut second_collection,
+            &key_origins,
+        )
+        .unwrap();
+        let key = |s: &str| vec![String::from(s)];
+        // Keys with the default value are equal, while keys with other values are still only in
+        // one of the files
+        assert_eq!(vec![key("removed_other")], key_origins.first_only());
+        assert_eq!(
+            vec![key("added_other"), key("no_default")],
+            key_origins.second_only()
+        );
+        assert_eq!(
+            vec![key("same"), key("removed"), key("added")],
+            key_origins.both()
+        );
+        assert_eq!(toml::Value::Integer(80), second_collection[&key("removed")]);
+        assert_eq!(toml::Value::Boolean(true), first_collection[&key("added")]);
+        let args = Args::default();
+        assert!(
+            unequal_keys(&args, &first_collection, &second_collection, &key_origins).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_became() {
+        let first_collection = parse::flatten_value(
+            "enabled = true\nflipped = true\ncount = 1\nfloat = 1\nsame = false"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "enabled = true\nflipped = false\ncount = 0\nfloat = 0.0\nsame = false"
+                .parse()
+                .unwrap(),
+        );
+        let keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&keys, &keys).unwrap();
+
+        let args = Args {
+            became: Some(toml::Value::Boolean(false)),
+            ..Default::default()
+        };
+        let unequal = unequal_keys(&args, &first_collection, &second_collection, &key_origins);
+        assert_eq!(vec![vec![String::from("flipped")]], unequal);
+        assert!(!args.sections().first_only && !args.sections().second_only);
+
+        // The type must match, so the float does not become the integer
+        let args = Args {
+            became: Some(toml::Value::Integer(0)),
+            ..Default::default()
+        };
+        let unequal = unequal_keys(&args, &first_collection, &second_collection, &key_origins);
+        assert_eq!(vec![vec![String::from("count")]], unequal);
+
+        let args = Args {
+            became: Some(toml::Value::String(String::from("false"))),
+            ..Default::default()
+        };
+        assert!(
+            unequal_keys(&args, &first_collection, &second_collection, &key_origins).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_ignore_types() {
+        let first_collection = parse::flatten_value(
+            "name = \"a\"\ncount = 1\nlabel = \"1\"\nsame = \"b\""
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "name = \"b\"\ncount = 2\nlabel = 1\nsame = \"b\""
+                .parse()
+                .unwrap(),
+        );
+        let keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&keys, &keys).unwrap();
+        let key = |s: &str| vec![String::from(s)];
+
+        let unequal = unequal_keys(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &key_origins,
+        );
+        assert_eq!(vec![key("count"), key("label"), key("name")], unequal);
+
+        // A change from a string to an integer is not a change between strings
+        let args = Args {
+            ignore_types: vec![String::from("string")],
+            ..Default::default()
+        };
+        let unequal = unequal_keys(&args, &first_collection, &second_collection, &key_origins);
+        assert_eq!(vec![key("count"), key("label")], unequal);
+
+        let args = Args {
+            ignore_types: vec![String::from("string"), String::from("integer")],
+            ..Default::default()
+        };
+        assert!(
+            unequal_keys(&args, &first_collection, &second_collection, &key_origins).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_explain_exclusions() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_collection = parse::flatten_value(
+            "a = 1\nsecret = 1\nsame = 1\nremoved = 1\n[table]\nb = 1"
+                .parse()
+                .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            "a = 2\nsecret = 2\nsame = 1\n[table]\nb = 1"
+                .parse()
+                .unwrap(),
+        );
+        let args = Args {
+            exclude: Some(String::from("other,secret")),
+            ..Default::default()
+        };
+        let outcome = |args: &Args, k: &str| {
+            explain_exclusions(args, &key(k), &first_collection, &second_collection)
+                .last()
+                .cloned()
+                .unwrap()
+        };
+
+        assert_eq!(
+            (
+                "exclude",
+                String::from("left out by the exclude key 'secret'")
+            ),
+            outcome(&args, "secret")
+        );
+        assert_eq!(("values", String::from("unequal")), outcome(&args, "a"));
+        assert_eq!("values", outcome(&args, "same").0);
+        assert_eq!(
+            ("input", String::from("left out: in none of the files")),
+            outcome(&args, "missing")
+        );
+        assert_eq!("input", outcome(&args, "table").0);
+
+        let args = Args {
+            added_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ("section", String::from("left out by --added-only")),
+            outcome(&args, "removed")
+        );
+        assert_eq!(
+            ("section", String::from("left out by --added-only")),
+            outcome(&args, "a")
+        );
+
+        let args = Args {
+            changed_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ("section", String::from("left out by --changed-only")),
+            outcome(&args, "removed")
+        );
+        assert_eq!(("values", String::from("unequal")), outcome(&args, "a"));
+
+        let args = Args {
+            exclude_value: Some(String::from("2")),
+            ..Default::default()
+        };
+        assert_eq!(
+            (
+                "exclude-value",
+                String::from("left out: a value is written as the exclude value '2'")
+            ),
+            outcome(&args, "a")
+        );
+        assert_eq!("values", outcome(&args, "same").0);
+    }
+
+    #[test]
+    fn test_input_format() {
+        let path = Path::new("assets/test_1.toml");
+        assert_eq!(InputFormat::Toml, input_format(path, None).unwrap());
+        assert_eq!(InputFormat::Toml, input_format(path, Some("toml")).unwrap());
+
+        // The format override makes it possible to parse files without a known extension
+        let path = Path::new("README.md");
+        assert!(input_format(path, None).is_err());
+        assert_eq!(InputFormat::Toml, input_format(path, Some("toml")).unwrap());
+
+        assert!(input_format(Path::new("does_not_exist.toml"), Some("toml")).is_err());
+
+        let path = Path::new("assets/test_1.yaml");
+        assert_eq!(InputFormat::Yaml, input_format(path, None).unwrap());
+    }
+
+    #[test]
+    fn test_sort_changes() {
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let first_collection = parse::flatten_value(
+            r#"
+            b = 1
+            z = "a"
+            [a]
+            x = 10.0
+            [a.b]
+            c = 100
+            d = true
+            "#
+            .parse()
+            .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            r#"
+            b = 3
+            z = "b"
+            [a]
+            x = 12.5
+            [a.b]
+            c = 99
+            d = false
+            "#
+            .parse()
+            .unwrap(),
+        );
+        let unequal = vec![key("z"), key("a.b.d"), key("b"), key("a.x"), key("a.b.c")];
+        let sorted = |order: ChangeOrder| {
+            let mut keys = unequal.clone();
+            sort_changes(&mut keys, order, &first_collection, &second_collection);
+            keys
+        };
+
+        assert_eq!(
+            vec![key("b"), key("z"), key("a.x"), key("a.b.c"), key("a.b.d")],
+            sorted(ChangeOrder::File)
+        );
+        assert_eq!(
+            vec![key("a.b.c"), key("a.b.d"), key("a.x"), key("b"), key("z")],
+            sorted(ChangeOrder::Key)
+        );
+        assert_eq!(
+            vec![key("a.x"), key("b"), key("a.b.c"), key("a.b.d"), key("z")],
+            sorted(ChangeOrder::Delta)
+        );
+        assert_eq!(
+            vec![key("b"), key("z"), key("a.x"), key("a.b.c"), key("a.b.d")],
+            sorted(ChangeOrder::Depth)
+        );
+        assert_eq!(
+            vec![key("b"), key("z"), key("a.x"), key("a.b.c"), key("a.b.d")],
+            sorted(ChangeOrder::Section)
+        );
+    }
+
+    #[test]
+    fn test_sections() {
+        assert_eq!(
+            Sections {
+                first_only: true,
+                second_only: true,
+                both: true,
+            },
+            Args::default().sections()
+        );
+
+        let args = Args {
+            added_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Sections {
+                first_only: false,
+                second_only: true,
+                both: false,
+            },
+            args.sections()
+        );
+
+        let args = Args {
+            removed_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Sections {
+                first_only: true,
+                second_only: false,
+                both: false,
+            },
+            args.sections()
+        );
+
+        let args = Args {
+            changed_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Sections {
+                first_only: false,
+                second_only: false,
+                both: true,
+            },
+            args.sections()
+        );
+    }
+
+    #[test]
+    fn test_entry_budget() {
+        let mut budget = EntryBudget::new(Some(5));
+        assert_eq!(3, budget.take(3));
+        assert_eq!(2, budget.take(4));
+        assert_eq!(0, budget.take(1));
+        assert_eq!(0, budget.take(0));
+
+        let mut budget = EntryBudget::new(None);
+        assert_eq!(100, budget.take(100));
+    }
+
+    #[test]
+    fn test_common_summary() {
+        let first_collection = parse::parse_toml(Path::new("assets/test_1.toml")).unwrap();
+        let second_collection = parse::parse_toml(Path::new("assets/test_2.toml")).unwrap();
+        let first_keys: Vec<Key> = first_collection.keys().cloned().collect();
+        let second_keys: Vec<Key> = second_collection.keys().cloned().collect();
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+
+        // version, float_value, field0.name, field2.name and field2.values
+        let equal = equal_keys(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &key_origins,
+        );
+        assert_eq!((5, 3), common_summary(&equal));
+
+        // Excluded keys are not counted
+        let first_keys = key_handling::filter_keys(
+            &first_keys,
+            Some(String::from("field2")),
+            &MatchOptions::default(),
+        );
+        let second_keys = key_handling::filter_keys(
+            &second_keys,
+            Some(String::from("field2")),
+            &MatchOptions::default(),
+        );
+        let key_origins = key_handling::compare_vectors(&first_keys, &second_keys).unwrap();
+        let equal = equal_keys(
+            &Args::default(),
+            &first_collection,
+            &second_collection,
+            &key_origins,
+        );
+        assert_eq!((3, 2), common_summary(&equal));
+    }
 }
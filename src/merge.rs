@@ -0,0 +1,175 @@
+//! Merge of two files into one, with the keys of both
+//!
+//! Keys with unequal values, and values at the path of a table in the other file, are conflicts,
+//! which are resolved by keeping the content of the preferred file.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Error};
+use indexmap::IndexMap;
+
+use crate::key_handling::Key;
+use crate::parse;
+use crate::value_handling::ValueComparator;
+
+/// Which file's value to keep for keys with unequal values when merging
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Preference {
+    #[default]
+    First,
+    Second,
+}
+
+/// Merge two collections into one with the keys of both, where keys with unequal values get the
+/// value of the preferred collection. Return the merge and the number of keys with unequal values.
+///
+/// A value in one collection at the path of a table in the other collection, like `a = 1` and
+/// `a.c = 3`, is a conflict too, where the preferred collection keeps its value or table, and the
+/// other one is left out.
+pub fn merge_collections(
+    first_collection: &IndexMap<Key, toml::Value>,
+    second_collection: &IndexMap<Key, toml::Value>,
+    prefer: Preference,
+    comparison: &dyn ValueComparator,
+) -> (IndexMap<Key, toml::Value>, usize) {
+    let mut merged = first_collection.clone();
+    let mut conflicts = 0;
+    for (key, second_val) in second_collection.iter() {
+        match merged.get(key) {
+            Some(first_val) if comparison.equal(key, first_val, second_val) => {}
+            Some(_) => {
+                conflicts += 1;
+                if prefer == Preference::Second {
+                    merged.insert(key.clone(), second_val.clone());
+                }
+            }
+            None => {
+                merged.insert(key.clone(), second_val.clone());
+            }
+        }
+    }
+
+    let preferred = match prefer {
+        Preference::First => first_collection,
+        Preference::Second => second_collection,
+    };
+    // Pairs of a key with a value, and a key in the table at its path
+    let clashes: Vec<(Key, Key)> = merged
+        .keys()
+        .flat_map(|key| {
+            (1..key.len())
+                .map(move |len| &key[..len])
+                .filter(|prefix| merged.contains_key(*prefix))
+                .map(move |prefix| (prefix.to_vec(), key.clone()))
+        })
+        .collect();
+    let mut clashed = HashSet::<Key>::new();
+    for (prefix, key) in clashes {
+        // Only one of the collections has the value, and the other one has the table
+        if preferred.contains_key(&prefix) {
+            merged.shift_remove(&key);
+        } else {
+            merged.shift_remove(&prefix);
+        }
+        clashed.insert(prefix);
+    }
+    (merged, conflicts + clashed.len())
+}
+
+/// Write a merge as a toml document, where the tables at the keys in `arrays`, as recorded by
+/// `parse::flatten_value_arrays`, are written as arrays of tables
+pub fn render(merged: &IndexMap<Key, toml::Value>, arrays: &HashSet<Key>) -> Result<String, Error> {
+    toml::to_string(&parse::unflatten(merged, arrays)?).map_err(|msg| anyhow!(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_handling::Comparison;
+
+    #[test]
+    fn test_merge_collections() {
+        let (first_collection, arrays) = parse::flatten_value_arrays(
+            r#"
+            name = "first"
+            [server]
+            port = 80
+            tls = { enabled = true, versions = ["1.2"] }
+            [[items]]
+            id = 1
+        "#
+            .parse()
+            .unwrap(),
+        );
+        let second_collection = parse::flatten_value(
+            r#"
+            name = "second"
+            [server]
+            port = 80
+            host = "localhost"
+            tls = { enabled = false }
+        "#
+            .parse()
+            .unwrap(),
+        );
+
+        let merge = |prefer: Preference| -> (toml::Value, usize) {
+            let (merged, conflicts) = merge_collections(
+                &first_collection,
+                &second_collection,
+                prefer,
+                &Comparison::default(),
+            );
+            let content = toml::to_string(&parse::unflatten(&merged, &arrays).unwrap()).unwrap();
+            (content.parse().unwrap(), conflicts)
+        };
+
+        let expected: toml::Value = r#"
+            name = "first"
+            [server]
+            port = 80
+            host = "localhost"
+            tls = { enabled = true, versions = ["1.2"] }
+            [[items]]
+            id = 1
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!((expected, 2), merge(Preference::First));
+
+        let (merged, _) = merge(Preference::Second);
+        assert_eq!(Some("second"), merged["name"].as_str());
+        assert_eq!(Some(false), merged["server"]["tls"]["enabled"].as_bool());
+        assert_eq!(Some(80), merged["server"]["port"].as_integer());
+    }
+
+    #[test]
+    fn test_merge_collections_value_and_table() {
+        let scalar = parse::flatten_value("a = 1\nb = 2".parse().unwrap());
+        let table = parse::flatten_value("b = 2\n[a]\nc = 3\nd = 4".parse().unwrap());
+        let merge = |first: &IndexMap<Key, toml::Value>,
+                     second: &IndexMap<Key, toml::Value>,
+                     prefer: Preference|
+         -> (toml::Value, usize) {
+            let (merged, conflicts) =
+                merge_collections(first, second, prefer, &Comparison::default());
+            let merged =
+                parse::unflatten(&merged, &HashSet::new()).expect("Could not unflatten the merge");
+            (merged, conflicts)
+        };
+        let scalar_kept: toml::Value = "a = 1\nb = 2".parse().unwrap();
+        let table_kept: toml::Value = "b = 2\n[a]\nc = 3\nd = 4".parse().unwrap();
+
+        // The preferred file keeps its value or table, in both orders of the files
+        assert_eq!(
+            (scalar_kept.clone(), 1),
+            merge(&scalar, &table, Preference::First)
+        );
+        assert_eq!(
+            (table_kept.clone(), 1),
+            merge(&scalar, &table, Preference::Second)
+        );
+        assert_eq!((table_kept, 1), merge(&table, &scalar, Preference::First));
+        assert_eq!((scalar_kept, 1), merge(&table, &scalar, Preference::Second));
+    }
+}
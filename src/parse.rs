@@ -1,36 +1,594 @@
 //! Misc utility functions
 //!
 
-use anyhow::{anyhow, Error};
-use std::{collections::HashMap, fs::File, io::Read, path::Path};
+use anyhow::{anyhow, Context, Error};
+use flate2::read::MultiGzDecoder;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 use crate::key_handling::Key;
 
-fn read_file_to_string(path: &Path) -> Result<String, Error> {
+/// The first bytes of gzip compressed data
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read a file as UTF-8 text, where gzip compressed files are decompressed
+///
+/// Compressed files are decompressed as they are read, without keeping the compressed content in
+/// memory.
+pub fn read_file_to_string(path: &Path) -> Result<String, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        let mut buffer = String::new();
+        MultiGzDecoder::new(reader).read_to_string(&mut buffer)?;
+        Ok(buffer)
+    } else {
+        let mut bytes = Vec::<u8>::new();
+        reader.read_to_end(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|msg| anyhow!("{} is not UTF-8: {}", path.display(), msg))
+    }
+}
+
+/// Extension that gives the format of a file, which for gzip compressed files like
+/// `config.toml.gz` is the extension before `.gz`
+pub fn format_extension(path: &Path) -> Option<&str> {
+    let extension = path.extension()?.to_str()?;
+    if extension == "gz" {
+        Path::new(path.file_stem()?).extension()?.to_str()
+    } else {
+        Some(extension)
+    }
+}
+
+/// Hex encoded sha256 hash of the content of a file
+#[cfg(feature = "hashes")]
+pub fn file_hash(path: &Path) -> Result<String, Error> {
+    use sha2::{Digest, Sha256};
+
     let mut file = File::open(path)?;
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
-    Ok(buffer)
+    let mut buffer = Vec::<u8>::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(format!("{:x}", Sha256::digest(&buffer)))
+}
+
+/// Parse a toml file without flattening it
+pub fn parse_toml_value(path: &Path) -> Result<toml::Value, Error> {
+    let string_content =
+        read_file_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    string_content
+        .parse()
+        .with_context(|| format!("Could not parse {} as toml", path.display()))
+}
+
+/// Parse a toml string into a collection of its innermost values, like `parse_toml` does for the
+/// content of a file
+///
+/// An owned string is freed before the parsed value is flattened, since the parsed value holds its
+/// own copy of the content, rather than being kept in memory along with the flattened collection.
+pub fn parse_toml_str<'a>(
+    content: impl Into<Cow<'a, str>>,
+) -> Result<IndexMap<Key, toml::Value>, Error> {
+    let content = content.into();
+    let parsed = content.parse::<toml::Value>();
+    drop(content);
+    Ok(flatten_value(parsed.map_err(|msg| anyhow!(msg))?))
+}
+
+pub fn parse_toml(path: &Path) -> Result<IndexMap<Key, toml::Value>, Error> {
+    let string_content =
+        read_file_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    parse_toml_str(string_content)
+        .with_context(|| format!("Could not parse {} as toml", path.display()))
+}
+
+/// Parse a single toml value, like `false`, `"text"` or `[1, 2]`
+pub fn parse_value_str(value: &str) -> Result<toml::Value, Error> {
+    let document: toml::Value = format!("value = {}", value)
+        .parse()
+        .map_err(|msg| anyhow!("Invalid toml value '{}': {}", value, msg))?;
+    match document.get("value") {
+        Some(parsed) if document.as_table().map_or(0, |table| table.len()) == 1 => {
+            Ok(parsed.clone())
+        }
+        _ => Err(anyhow!("Invalid toml value '{}'", value)),
+    }
+}
+
+/// Format of an input file, which decides how it is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InputFormat {
+    #[default]
+    Toml,
+    /// YAML, converted to the corresponding toml values
+    Yaml,
+    /// JSON, converted to the corresponding toml values
+    Json,
+}
+
+impl InputFormat {
+    /// Names of the supported formats, as given on the command line
+    pub const NAMES: &'static [&'static str] = &["toml", "yaml", "json"];
+
+    /// Get the format with the given name, or file extension
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "toml" => Some(InputFormat::Toml),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            "json" => Some(InputFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Detect the format from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        format_extension(path).and_then(InputFormat::from_name)
+    }
+}
+
+/// Parse a file in the given format into a collection of its innermost values
+pub fn parse_input(path: &Path, format: InputFormat) -> Result<IndexMap<Key, toml::Value>, Error> {
+    match format {
+        InputFormat::Toml => parse_toml(path),
+        InputFormat::Yaml => parse_yaml(path),
+        InputFormat::Json => parse_json(path),
+    }
+}
+
+/// Parse a file in the given format without flattening it
+pub fn parse_input_value(path: &Path, format: InputFormat) -> Result<toml::Value, Error> {
+    match format {
+        InputFormat::Toml => parse_toml_value(path),
+        InputFormat::Yaml => parse_yaml_value(path),
+        InputFormat::Json => parse_json_value(path),
+    }
+}
+
+/// Description of where a value is, for error messages
+fn location(key: &[String]) -> String {
+    if key.is_empty() {
+        String::from("the top level")
+    } else {
+        format!("'{}'", key.iter().join("."))
+    }
+}
+
+/// Convert a YAML value to the corresponding toml value, where key is the key of the value, for
+/// error messages
+///
+/// Mapping keys that are scalars are converted to strings, and tags are ignored. Toml has no null
+/// value, so nulls are errors, as are integers that do not fit in an i64.
+fn yaml_to_toml(value: serde_yaml::Value, key: &mut Key) -> Result<toml::Value, Error> {
+    match value {
+        serde_yaml::Value::Null => Err(anyhow!(
+            "YAML null at {} has no toml equivalent",
+            location(key)
+        )),
+        serde_yaml::Value::Bool(val) => Ok(toml::Value::Boolean(val)),
+        serde_yaml::Value::Number(val) => {
+            if let Some(int) = val.as_i64() {
+                Ok(toml::Value::Integer(int))
+            } else if val.is_f64() {
+                Ok(toml::Value::Float(val.as_f64().unwrap_or(f64::NAN)))
+            } else {
+                Err(anyhow!(
+                    "YAML integer {} at {} does not fit in a toml integer",
+                    val,
+                    location(key)
+                ))
+            }
+        }
+        serde_yaml::Value::String(val) => Ok(toml::Value::String(val)),
+        serde_yaml::Value::Sequence(seq) => seq
+            .into_iter()
+            .enumerate()
+            .map(|(index, element)| {
+                key.push(index.to_string());
+                let converted = yaml_to_toml(element, key);
+                key.pop();
+                converted
+            })
+            .collect::<Result<Vec<toml::Value>, Error>>()
+            .map(toml::Value::Array),
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut table = toml::value::Table::new();
+            for (name, val) in mapping.into_iter() {
+                let name = match name {
+                    serde_yaml::Value::String(name) => name,
+                    serde_yaml::Value::Bool(name) => name.to_string(),
+                    serde_yaml::Value::Number(name) => name.to_string(),
+                    _ => {
+                        return Err(anyhow!(
+                            "YAML mapping key at {} is not a scalar",
+                            location(key)
+                        ))
+                    }
+                };
+                key.push(name.clone());
+                let converted = yaml_to_toml(val, key)?;
+                key.pop();
+                table.insert(name, converted);
+            }
+            Ok(toml::Value::Table(table))
+        }
+        serde_yaml::Value::Tagged(tagged) => yaml_to_toml(tagged.value, key),
+    }
+}
+
+/// Parse a YAML string into the corresponding toml document, which must be a table
+pub fn parse_yaml_str(content: &str) -> Result<toml::Value, Error> {
+    let value: serde_yaml::Value = serde_yaml::from_str(content).map_err(|msg| anyhow!(msg))?;
+    match yaml_to_toml(value, &mut Key::new())? {
+        table @ toml::Value::Table(_) => Ok(table),
+        _ => Err(anyhow!("The YAML document is not a mapping")),
+    }
+}
+
+/// Convert a JSON value to the corresponding toml value, where key is the key of the value, for
+/// error messages
+///
+/// Toml has no null value, so nulls are errors, as are integers that do not fit in an i64.
+fn json_to_toml(value: serde_json::Value, key: &mut Key) -> Result<toml::Value, Error> {
+    match value {
+        serde_json::Value::Null => Err(anyhow!(
+            "JSON null at {} has no toml equivalent",
+            location(key)
+        )),
+        serde_json::Value::Bool(val) => Ok(toml::Value::Boolean(val)),
+        serde_json::Value::Number(val) => {
+            if let Some(int) = val.as_i64() {
+                Ok(toml::Value::Integer(int))
+            } else if val.is_f64() {
+                Ok(toml::Value::Float(val.as_f64().unwrap_or(f64::NAN)))
+            } else {
+                Err(anyhow!(
+                    "JSON integer {} at {} does not fit in a toml integer",
+                    val,
+                    location(key)
+                ))
+            }
+        }
+        serde_json::Value::String(val) => Ok(toml::Value::String(val)),
+        serde_json::Value::Array(array) => array
+            .into_iter()
+            .enumerate()
+            .map(|(index, element)| {
+                key.push(index.to_string());
+                let converted = json_to_toml(element, key);
+                key.pop();
+                converted
+            })
+            .collect::<Result<Vec<toml::Value>, Error>>()
+            .map(toml::Value::Array),
+        serde_json::Value::Object(object) => {
+            let mut table = toml::value::Table::new();
+            for (name, val) in object.into_iter() {
+                key.push(name.clone());
+                let converted = json_to_toml(val, key)?;
+                key.pop();
+                table.insert(name, converted);
+            }
+            Ok(toml::Value::Table(table))
+        }
+    }
+}
+
+/// Parse a JSON string into the corresponding toml document, which must be a table
+pub fn parse_json_str(content: &str) -> Result<toml::Value, Error> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|msg| anyhow!(msg))?;
+    match json_to_toml(value, &mut Key::new())? {
+        table @ toml::Value::Table(_) => Ok(table),
+        _ => Err(anyhow!("The JSON document is not an object")),
+    }
+}
+
+/// Parse a JSON file into a toml value without flattening it
+pub fn parse_json_value(path: &Path) -> Result<toml::Value, Error> {
+    parse_json_str(&read_file_to_string(path)?)
+        .map_err(|msg| anyhow!("Error parsing {} as JSON: {}", path.display(), msg))
+}
+
+/// Parse a JSON file into a collection of its innermost values, like a toml file
+pub fn parse_json(path: &Path) -> Result<IndexMap<Key, toml::Value>, Error> {
+    Ok(flatten_value(parse_json_value(path)?))
+}
+
+/// Parse a YAML file into a toml value without flattening it
+pub fn parse_yaml_value(path: &Path) -> Result<toml::Value, Error> {
+    parse_yaml_str(&read_file_to_string(path)?)
+        .map_err(|msg| anyhow!("Error parsing {} as YAML: {}", path.display(), msg))
+}
+
+/// Parse a YAML file into a collection of its innermost values, like a toml file
+pub fn parse_yaml(path: &Path) -> Result<IndexMap<Key, toml::Value>, Error> {
+    Ok(flatten_value(parse_yaml_value(path)?))
 }
 
-pub fn parse_toml(path: &Path) -> Result<HashMap<Key, toml::Value>, Error> {
-    let string_content = match read_file_to_string(path) {
-        Ok(val) => val,
-        Err(msg) => {
-            println!("Error reading {} to string", path.display());
-            return Err(msg);
+/// A section of a toml document that could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionError {
+    /// The section header, or an empty string for the keys before the first header
+    pub header: String,
+    /// Line number of the start of the section, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+/// Parse a toml file, skipping sections that can not be parsed
+///
+/// See `parse_str_lenient` for how the sections are handled. Errors reading the file are still
+/// returned as errors.
+pub fn parse_toml_lenient(
+    path: &Path,
+) -> Result<(IndexMap<Key, toml::Value>, Vec<SectionError>), Error> {
+    let (content, failures) = parse_toml_lenient_value(path)?;
+    Ok((flatten_value(content), failures))
+}
+
+/// Parse a toml file like `parse_toml_lenient`, without flattening it
+pub fn parse_toml_lenient_value(path: &Path) -> Result<(toml::Value, Vec<SectionError>), Error> {
+    let string_content =
+        read_file_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    Ok(parse_str_lenient(&string_content))
+}
+
+/// Check if a line is a table header like `[key]` or `[[key]]`
+///
+/// This is a textual heuristic, so a line inside a multi-line array consisting only of a nested
+/// array without a trailing comma is also seen as a header.
+fn is_header(line: &str) -> bool {
+    let line = match line.find('#') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    let line = line.trim();
+    line.starts_with('[') && line.ends_with(']') && !line.contains('=')
+}
+
+/// Parse a toml string, skipping sections that can not be parsed
+///
+/// The toml crate fails on the first error in a document, so when the whole document does not
+/// parse, it is split on the table headers. The sections are then added one by one, and sections
+/// that make the accumulated document fail to parse are left out and reported.
+fn parse_str_lenient(content: &str) -> (toml::Value, Vec<SectionError>) {
+    if let Ok(value) = content.parse() {
+        return (value, Vec::new());
+    }
+
+    let mut sections = Vec::<(String, usize, String)>::new();
+    for (number, line) in content.lines().enumerate() {
+        if is_header(line) || sections.is_empty() {
+            let header = if is_header(line) {
+                String::from(line.trim())
+            } else {
+                String::new()
+            };
+            sections.push((header, number + 1, String::new()));
+        }
+        if let Some((_, _, text)) = sections.last_mut() {
+            text.push_str(line);
+            text.push('\n');
         }
+    }
+
+    let mut accepted = String::new();
+    let mut failures = Vec::<SectionError>::new();
+    for (header, line, text) in sections {
+        let candidate = format!("{}{}", accepted, text);
+        match candidate.parse::<toml::Value>() {
+            Ok(_) => accepted = candidate,
+            Err(msg) => failures.push(SectionError {
+                header,
+                line,
+                message: msg.to_string(),
+            }),
+        }
+    }
+
+    // Every accepted section parsed together with the previous ones
+    match accepted.parse() {
+        Ok(value) => (value, failures),
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Parse a list of layered toml files into the collection of their merged content
+///
+/// The layers are merged from left to right with `merge_values`.
+pub fn parse_layers(paths: &[PathBuf]) -> Result<IndexMap<Key, toml::Value>, Error> {
+    Ok(flatten_value(parse_layers_value(paths)?))
+}
+
+/// Parse a list of layered toml files like `parse_layers`, without flattening their merged content
+pub fn parse_layers_value(paths: &[PathBuf]) -> Result<toml::Value, Error> {
+    let mut merged = toml::Value::Table(toml::value::Table::new());
+    for path in paths.iter() {
+        merged = merge_values(merged, parse_toml_value(path)?);
+    }
+    Ok(merged)
+}
+
+/// Merge the overriding value into the base value
+///
+/// Tables are merged key by key, recursively. Any other value in the overriding value replaces
+/// the corresponding base value, including arrays, which are replaced as a whole.
+pub fn merge_values(base: toml::Value, overriding: toml::Value) -> toml::Value {
+    match (base, overriding) {
+        (toml::Value::Table(mut base_map), toml::Value::Table(overriding_map)) => {
+            for (k, v) in overriding_map.into_iter() {
+                let merged = match base_map.remove(&k) {
+                    Some(base_val) => merge_values(base_val, v),
+                    None => v,
+                };
+                base_map.insert(k, merged);
+            }
+            toml::Value::Table(base_map)
+        }
+        (_, overriding) => overriding,
+    }
+}
+
+/// Check if an array is not empty, and only has tables as elements
+fn is_array_of_tables(array: &[toml::Value]) -> bool {
+    !array.is_empty() && array.iter().all(|element| element.is_table())
+}
+
+/// Flatten every array of tables with indexed keys
+fn index_all(_key: &[String]) -> bool {
+    true
+}
+
+/// Flatten a toml value into a collection of its innermost values, keyed relative to the value
+pub fn flatten_value(value: toml::Value) -> IndexMap<Key, toml::Value> {
+    flatten_value_arrays(value).0
+}
+
+/// Flatten a toml value like `flatten_value`, along with the keys of the arrays of tables that are
+/// flattened with indexed keys, for rebuilding them with `unflatten`
+pub fn flatten_value_arrays(value: toml::Value) -> (IndexMap<Key, toml::Value>, HashSet<Key>) {
+    let mut collection = IndexMap::new();
+    let mut arrays = HashSet::new();
+    flatten_into(
+        &mut collection,
+        &mut arrays,
+        &mut Key::new(),
+        value,
+        &index_all,
+    );
+    (collection, arrays)
+}
+
+/// Turn the tables at the keys in `arrays`, nested in a value at the given key, back into arrays
+/// of tables, when they are keyed by the indices 0, 1, ... in order
+fn rebuild_arrays(value: toml::Value, key: &mut Key, arrays: &HashSet<Key>) -> toml::Value {
+    let table = match value {
+        toml::Value::Table(table) => table,
+        other => return other,
     };
+    let table: toml::value::Table = table
+        .into_iter()
+        .map(|(k, v)| {
+            key.push(k);
+            let rebuilt = rebuild_arrays(v, key, arrays);
+            (key.pop().unwrap_or_default(), rebuilt)
+        })
+        .collect();
+    let is_indexed = table
+        .iter()
+        .enumerate()
+        .all(|(index, (k, v))| *k == index.to_string() && v.is_table());
+    if !table.is_empty() && is_indexed && arrays.contains(key.as_slice()) {
+        toml::Value::Array(table.into_iter().map(|(_, v)| v).collect())
+    } else {
+        toml::Value::Table(table)
+    }
+}
 
-    match string_content.parse() {
-        Ok(content) => {
-            let collection = HashMap::<Key, toml::Value>::new();
-            let key = Key::new();
-            Ok(parse_to_inner(collection, key, content))
+/// Flatten the arrays of tables at the keys where `grouped` is true back into single values, for
+/// comparing them as whole arrays
+///
+/// The keys of the arrays of tables are given by `arrays`, as recorded by `flatten_value_arrays`.
+pub fn group_arrays(
+    collection: &IndexMap<Key, toml::Value>,
+    arrays: &HashSet<Key>,
+    grouped: &dyn Fn(&[String]) -> bool,
+) -> Result<IndexMap<Key, toml::Value>, Error> {
+    Ok(parse_to_inner(
+        IndexMap::new(),
+        Key::new(),
+        unflatten(collection, arrays)?,
+        &|key| !grouped(key),
+    ))
+}
+
+/// Rebuild a nested toml table from a collection of innermost values
+///
+/// This is the inverse of `flatten_value` for tables without empty sub-tables, since empty
+/// tables have no innermost values to be kept in the collection. The tables at the keys in
+/// `arrays`, as recorded by `flatten_value_arrays`, are rebuilt as arrays of tables, while other
+/// tables keyed by indices, like `[ports."0"]`, are kept as tables.
+///
+/// A key that has a value at the path of a table of another key, like `a` and `a.b`, is an error,
+/// since the value and the table can not both be kept. Flattened keys never do, but collections
+/// combined from several files may.
+pub fn unflatten(
+    collection: &IndexMap<Key, toml::Value>,
+    arrays: &HashSet<Key>,
+) -> Result<toml::Value, Error> {
+    let mut root = toml::value::Table::new();
+    for (key, value) in collection.iter() {
+        let (last, parents) = match key.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
+        let clash = || {
+            anyhow!(
+                "Key '{}' has both a value and a table of other keys",
+                key.iter().join(".")
+            )
+        };
+        let mut table = &mut root;
+        for part in parents.iter() {
+            let entry = table
+                .entry(part.clone())
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+            table = match entry {
+                toml::Value::Table(inner) => inner,
+                _ => return Err(clash()),
+            };
+        }
+        if table.contains_key(last) {
+            return Err(clash());
+        }
+        table.insert(last.clone(), value.clone());
+    }
+    Ok(rebuild_arrays(
+        toml::Value::Table(root),
+        &mut Key::new(),
+        arrays,
+    ))
+}
+
+/// Find the keys where flattening and rebuilding a value does not give back the original
+///
+/// The keys are sorted, and point to the outermost place where the values differ.
+pub fn roundtrip_discrepancies(value: &toml::Value) -> Result<Vec<Key>, Error> {
+    let (collection, arrays) = flatten_value_arrays(value.clone());
+    let rebuilt = unflatten(&collection, &arrays)?;
+    let mut discrepancies = Vec::<Key>::new();
+    find_discrepancies(value, &rebuilt, &mut Key::new(), &mut discrepancies);
+    discrepancies.sort();
+    Ok(discrepancies)
+}
+
+fn find_discrepancies(
+    original: &toml::Value,
+    rebuilt: &toml::Value,
+    key: &mut Key,
+    discrepancies: &mut Vec<Key>,
+) {
+    match (original, rebuilt) {
+        (toml::Value::Table(original_map), toml::Value::Table(rebuilt_map)) => {
+            for k in original_map.keys().chain(rebuilt_map.keys()).unique() {
+                key.push(k.clone());
+                match (original_map.get(k), rebuilt_map.get(k)) {
+                    (Some(original_val), Some(rebuilt_val)) => {
+                        find_discrepancies(original_val, rebuilt_val, key, discrepancies)
+                    }
+                    _ => discrepancies.push(key.clone()),
+                }
+                key.pop();
+            }
         }
-        Err(msg) => {
-            println!("Error parsing {} from string to toml", path.display());
-            Err(anyhow!(msg))
+        _ => {
+            if original != rebuilt {
+                discrepancies.push(key.clone());
+            }
         }
     }
 }
@@ -39,7 +597,7 @@ pub fn parse_toml(path: &Path) -> Result<HashMap<Key, toml::Value>, Error> {
 ///
 /// toml::Value is an enum
 ///
-/// ```rust,no-run
+/// ```ignore
 /// pub enum Value {
 ///     String(String),
 ///     Integer(i64),
@@ -72,7 +630,7 @@ pub fn parse_toml(path: &Path) -> Result<HashMap<Key, toml::Value>, Error> {
 /// this (sorted for ease of reading), where toml::InnerValue a subset of toml::Value excluding
 /// toml::Value::Table
 ///
-/// ```
+/// ```text
 /// {
 ///     ["lvl0_key0"]: toml::InnerValue,
 ///     ["lvl0_key1"]: toml::InnerValue,
@@ -84,24 +642,68 @@ pub fn parse_toml(path: &Path) -> Result<HashMap<Key, toml::Value>, Error> {
 /// }
 /// ```
 ///
+/// Inline tables like `point = { x = 1, y = 2 }` are tables too, and are flattened the same way as
+/// `[point]` sections, into `["point", "x"]` and `["point", "y"]`. Dotted keys like
+/// `point.x = 1` and quoted key parts like `"point".x = 1` are split into levels by the toml
+/// parser, so they are flattened the same way, while a quoted dot like `"point.x" = 1` is part of a
+/// single key level.
+///
+/// Arrays where every element is a table, written with `[[servers]]` sections or as arrays of
+/// inline tables, are flattened with the index of each table as a key level, like
+/// `["servers", "0", "host"]` and `["servers", "1", "host"]`, where `indexed` is true for the key
+/// of the array. Other arrays, including empty arrays and arrays mixing tables with other values,
+/// are innermost values.
+///
+/// The keys of the innermost values of a value are distinct, since the toml parser rejects keys
+/// that are defined twice, and a value can not be both a table and an array of tables.
 fn parse_to_inner(
-    mut collection: HashMap<Key, toml::Value>,
-    key: Key,
+    mut collection: IndexMap<Key, toml::Value>,
+    mut key: Key,
     toml_val: toml::Value,
-) -> HashMap<Key, toml::Value> {
+    indexed: &dyn Fn(&[String]) -> bool,
+) -> IndexMap<Key, toml::Value> {
+    flatten_into(
+        &mut collection,
+        &mut HashSet::new(),
+        &mut key,
+        toml_val,
+        indexed,
+    );
+    collection
+}
+
+/// Move the innermost values of a value into the collection, like `parse_to_inner`
+///
+/// The key of the value is extended with the levels below it while they are flattened, and
+/// restored afterwards, so only the keys of the innermost values are allocated. The tables and
+/// arrays of the value are consumed as they are flattened, so the values are moved rather than
+/// copied. The keys of the arrays of tables that are flattened with indexed keys are added to
+/// `arrays`.
+fn flatten_into(
+    collection: &mut IndexMap<Key, toml::Value>,
+    arrays: &mut HashSet<Key>,
+    key: &mut Key,
+    toml_val: toml::Value,
+    indexed: &dyn Fn(&[String]) -> bool,
+) {
     match toml_val {
         toml::Value::Table(map) => {
-            let mut key = key;
             for (k, v) in map.into_iter() {
                 key.push(k);
-                collection = parse_to_inner(collection, key.clone(), v);
+                flatten_into(collection, arrays, key, v, indexed);
+                key.pop();
+            }
+        }
+        toml::Value::Array(array) if is_array_of_tables(&array) && indexed(key) => {
+            arrays.insert(key.clone());
+            for (index, element) in array.into_iter().enumerate() {
+                key.push(index.to_string());
+                flatten_into(collection, arrays, key, element, indexed);
                 key.pop();
             }
-            collection
         }
         _ => {
-            collection.insert(key, toml_val);
-            collection
+            collection.insert(key.clone(), toml_val);
         }
     }
 }
@@ -132,10 +734,10 @@ mod tests {
         "#;
         match toml_str.parse() {
             Ok(content) => {
-                let test_collection = HashMap::<Vec<String>, toml::Value>::new();
+                let test_collection = IndexMap::<Vec<String>, toml::Value>::new();
                 let key = Key::new();
-                let test_collection = parse_to_inner(test_collection, key, content);
-                let mut true_collection = HashMap::new();
+                let test_collection = parse_to_inner(test_collection, key, content, &index_all);
+                let mut true_collection = IndexMap::new();
                 true_collection.insert(
                     vec![String::from("lvl0_key0")],
                     toml::Value::String(String::from("Hello world")),
@@ -184,18 +786,246 @@ mod tests {
             }
             Err(msg) => {
                 println!("Error parsing string to toml");
-                println!("{:?}", msg);
-                assert!(false)
+                panic!("{:?}", msg)
             }
         }
     }
 
+    #[test]
+    fn test_parse_toml_str_duplicate() {
+        // Keys defined twice are rejected by the toml parser, so they never collide when flattened
+        for duplicate in [
+            "[server]\nport = 80\n[server]\nport = 8080",
+            "server.port = 80\n[server]\nport = 8080",
+            "[server]\nport = 80\n\"port\" = 8080",
+        ] {
+            assert!(parse_toml_str(duplicate).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_toml_str() {
+        let collection = parse_toml_str(
+            r#"
+            name = "generated"
+
+            [server]
+            host = "localhost"
+            ports = [80, 443]
+
+            [[server.routes]]
+            path = "/"
+        "#,
+        )
+        .expect("Could not parse toml");
+        let expected: IndexMap<Key, toml::Value> = vec![
+            (vec!["name"], toml::Value::String(String::from("generated"))),
+            (
+                vec!["server", "host"],
+                toml::Value::String(String::from("localhost")),
+            ),
+            (
+                vec!["server", "ports"],
+                toml::Value::Array(vec![toml::Value::Integer(80), toml::Value::Integer(443)]),
+            ),
+            (
+                vec!["server", "routes", "0", "path"],
+                toml::Value::String(String::from("/")),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, val)| (key.into_iter().map(String::from).collect(), val))
+        .collect();
+        assert_eq!(expected, collection);
+
+        // Flattening the content of a file gives the same collection as parsing the file
+        let content =
+            read_file_to_string(Path::new("assets/test_1.toml")).expect("Could not read file");
+        assert_eq!(
+            parse_toml(Path::new("assets/test_1.toml")).expect("Could not parse file"),
+            parse_toml_str(&content).expect("Could not parse toml")
+        );
+
+        assert!(parse_toml_str("name = ").is_err());
+        assert!(parse_toml_str("").expect("Could not parse toml").is_empty());
+    }
+
+    #[test]
+    fn test_parse_to_inner_inline_tables() {
+        let flatten = |toml_str: &str| {
+            let content: toml::Value = toml_str.parse().expect("Could not parse toml");
+            parse_to_inner(IndexMap::new(), Key::new(), content, &index_all)
+        };
+        let inline = flatten(
+            r#"
+            point = { x = 1, y = { z = 2 } }
+            points = [{ x = 1 }, { x = 2 }]
+        "#,
+        );
+        let sections = flatten(
+            r#"
+            [point]
+            x = 1
+
+            [point.y]
+            z = 2
+
+            [[points]]
+            x = 1
+
+            [[points]]
+            x = 2
+        "#,
+        );
+        assert_eq!(sections, inline);
+
+        let keys: Vec<String> = inline.keys().map(|key| key.join(".")).collect();
+        assert_eq!(
+            vec!["point.x", "point.y.z", "points.0.x", "points.1.x"],
+            keys
+        );
+    }
+
+    #[test]
+    fn test_parse_to_inner_key_encodings() {
+        let flatten = |toml_str: &str| {
+            let content: toml::Value = toml_str.parse().expect("Could not parse toml");
+            parse_to_inner(IndexMap::new(), Key::new(), content, &index_all)
+        };
+        let section = flatten("[server]\nhost = \"x\"\ntls.enabled = true");
+        let encodings = [
+            "server.host = \"x\"\nserver.tls.enabled = true",
+            "\"server\" = { \"host\" = \"x\", tls = { enabled = true } }",
+            "\"server\".'host' = \"x\"\n\"server\".tls.\"enabled\" = true",
+            "[server.tls]\nenabled = true\n[server]\nhost = \"x\"",
+        ];
+        // The maps are equal regardless of the order of the keys
+        for encoding in encodings.iter() {
+            assert_eq!(section, flatten(encoding), "{}", encoding);
+        }
+
+        // A quoted dot is part of the key level
+        let quoted = flatten("\"server.host\" = \"x\"");
+        assert_eq!(
+            vec![&vec![String::from("server.host")]],
+            quoted.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_to_inner_arrays_of_tables() {
+        let content: toml::Value = r#"
+            mixed = [{ x = 1 }, 2]
+            empty = []
+
+            [[servers]]
+            host = "a"
+            port = 80
+
+            [[servers]]
+            host = "b"
+            tags = [{ name = "main" }]
+        "#
+        .parse()
+        .expect("Could not parse toml");
+        let (collection, arrays) = flatten_value_arrays(content.clone());
+
+        let keys: Vec<String> = collection.keys().map(|key| key.join(".")).collect();
+        assert_eq!(
+            vec![
+                "mixed",
+                "empty",
+                "servers.0.host",
+                "servers.0.port",
+                "servers.1.host",
+                "servers.1.tags.0.name",
+            ],
+            keys
+        );
+        assert_eq!(
+            Some(&toml::Value::String(String::from("b"))),
+            collection.get(&vec![
+                String::from("servers"),
+                String::from("1"),
+                String::from("host")
+            ])
+        );
+        assert_eq!(content, unflatten(&collection, &arrays).unwrap());
+
+        let grouped = group_arrays(&collection, &arrays, &|key| {
+            key == [String::from("servers")]
+        })
+        .unwrap();
+        let keys: Vec<String> = grouped.keys().map(|key| key.join(".")).collect();
+        assert_eq!(vec!["mixed", "empty", "servers"], keys);
+        assert_eq!(
+            content.get("servers"),
+            grouped.get(&vec![String::from("servers")])
+        );
+    }
+
+    #[test]
+    fn test_flatten_value_order() {
+        let content: toml::Value = "b = 1\na = 2\n[z]\nc = 3\n[m]\nd = 4"
+            .parse()
+            .expect("Could not parse toml");
+        let keys: Vec<String> = flatten_value(content)
+            .keys()
+            .map(|key| key.join("."))
+            .collect();
+        assert_eq!(vec!["b", "a", "z.c", "m.d"], keys);
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let yaml = parse_input(Path::new("assets/test_1.yaml"), InputFormat::Yaml)
+            .expect("Could not parse YAML");
+        let toml = parse_toml(Path::new("assets/test_1.toml")).expect("Could not parse toml");
+        assert_eq!(toml, yaml);
+    }
+
+    #[test]
+    fn test_parse_yaml_str_errors() {
+        let err = parse_yaml_str("server:\n  host: ~\n").expect_err("Null not detected");
+        assert_eq!(
+            "YAML null at 'server.host' has no toml equivalent",
+            err.to_string()
+        );
+        assert!(parse_yaml_str("values: [1, null]").is_err());
+        assert!(parse_yaml_str("- 1\n- 2").is_err());
+        assert!(parse_yaml_str("big: 18446744073709551615").is_err());
+        assert_eq!(
+            "1 = true\n",
+            toml::to_string(&parse_yaml_str("1: true").unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json = parse_input(Path::new("assets/test_1.json"), InputFormat::Json)
+            .expect("Could not parse JSON");
+        let toml = parse_toml(Path::new("assets/test_1.toml")).expect("Could not parse toml");
+        assert_eq!(toml, json);
+    }
+
+    #[test]
+    fn test_parse_json_str_errors() {
+        let err =
+            parse_json_str(r#"{"server": {"ports": [80, null]}}"#).expect_err("Null not detected");
+        assert_eq!(
+            "JSON null at 'server.ports.1' has no toml equivalent",
+            err.to_string()
+        );
+        assert!(parse_json_str("[1, 2]").is_err());
+        assert!(parse_json_str(r#"{"big": 18446744073709551615}"#).is_err());
+    }
+
     #[test]
     fn test_parse_toml() {
         let path = Path::new("assets/test_3.toml");
-        let test_collection = parse_toml(&path).expect("Could not parse toml");
+        let test_collection = parse_toml(path).expect("Could not parse toml");
 
-        let mut true_collection = HashMap::new();
+        let mut true_collection = IndexMap::new();
         true_collection.insert(
             vec![String::from("lvl0_key0")],
             toml::Value::String(String::from("Hello world")),
@@ -242,4 +1072,233 @@ mod tests {
 
         assert_eq!(true_collection, test_collection)
     }
+
+    #[test]
+    fn test_parse_str_lenient() {
+        let toml_str = r#"
+key0 = "top"
+
+[valid]
+key1 = 1
+
+[broken]
+key2 = = 2
+
+[[servers]]
+name = "a"
+
+[[servers]]
+name = "b"
+"#;
+        let (content, failures) = parse_str_lenient(toml_str);
+        let correct: toml::Value = r#"
+            key0 = "top"
+
+            [valid]
+            key1 = 1
+
+            [[servers]]
+            name = "a"
+
+            [[servers]]
+            name = "b"
+        "#
+        .parse()
+        .expect("Could not parse correct");
+        assert_eq!(correct, content);
+        assert_eq!(1, failures.len());
+        assert_eq!("[broken]", failures[0].header);
+        assert_eq!(7, failures[0].line);
+    }
+
+    #[test]
+    fn test_parse_str_lenient_valid() {
+        let (content, failures) = parse_str_lenient("[a]\nb = 1\n");
+        assert!(failures.is_empty());
+        assert_eq!(
+            Some(&toml::Value::Integer(1)),
+            content.get("a").and_then(|a| a.get("b"))
+        );
+    }
+
+    #[test]
+    fn test_merge_values() {
+        let base: toml::Value = r#"
+            scalar = 1
+            array = [1, 2, 3]
+            replaced = { key = "value" }
+
+            [table]
+            kept = "base"
+            overwritten = "base"
+        "#
+        .parse()
+        .expect("Could not parse base");
+        let overriding: toml::Value = r#"
+            scalar = "two"
+            array = [4]
+            replaced = 5
+
+            [table]
+            overwritten = "overriding"
+            added = "overriding"
+        "#
+        .parse()
+        .expect("Could not parse overriding");
+        let correct: toml::Value = r#"
+            scalar = "two"
+            array = [4]
+            replaced = 5
+
+            [table]
+            kept = "base"
+            overwritten = "overriding"
+            added = "overriding"
+        "#
+        .parse()
+        .expect("Could not parse correct");
+
+        assert_eq!(correct, merge_values(base, overriding));
+    }
+
+    #[test]
+    fn test_parse_layers() {
+        let layers = vec![
+            PathBuf::from("assets/layers/base.toml"),
+            PathBuf::from("assets/layers/env.toml"),
+            PathBuf::from("assets/layers/local.toml"),
+        ];
+        let test_collection = parse_layers(&layers).expect("Could not parse layers");
+        let target_collection =
+            parse_toml(Path::new("assets/layers/target.toml")).expect("Could not parse target");
+        assert_eq!(target_collection, test_collection);
+    }
+
+    #[cfg(feature = "hashes")]
+    #[test]
+    fn test_file_hash() {
+        let hash = file_hash(Path::new("assets/test_1.toml")).expect("Could not hash file");
+        assert_eq!(
+            "95873d9fe6c90d6aa77ade36750850b1cedb201afe9e72236a5633c51f899f60",
+            hash
+        );
+    }
+
+    #[test]
+    fn test_input_format() {
+        assert_eq!(Some(InputFormat::Toml), InputFormat::from_name("toml"));
+        assert_eq!(Some(InputFormat::Yaml), InputFormat::from_name("yaml"));
+        assert_eq!(Some(InputFormat::Yaml), InputFormat::from_name("yml"));
+        assert_eq!(Some(InputFormat::Json), InputFormat::from_name("json"));
+        assert_eq!(None, InputFormat::from_name("ini"));
+        assert_eq!(
+            Some(InputFormat::Toml),
+            InputFormat::from_path(Path::new("assets/test_1.toml"))
+        );
+        assert_eq!(None, InputFormat::from_path(Path::new("config.conf")));
+        assert_eq!(None, InputFormat::from_path(Path::new("config")));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let toml_str = r#"
+            string = "Hello world"
+            integer = 123
+            float = 1.23
+            boolean = true
+            offset_datetime = 1979-05-27T07:32:00Z
+            local_datetime = 1979-05-27T07:32:00
+            local_date = 1979-05-27
+            local_time = 07:32:00
+            nested_array = [[1, 2], ["a", "b"], []]
+            inline = { x = 1, y = { z = 2 } }
+
+            [table.sub_table]
+            key = "value"
+
+            [[array_of_tables]]
+            name = "first"
+
+            [[array_of_tables]]
+            name = "second"
+        "#;
+        let value: toml::Value = toml_str.parse().expect("Could not parse toml");
+        let (collection, arrays) = flatten_value_arrays(value.clone());
+        assert_eq!(value, unflatten(&collection, &arrays).unwrap());
+
+        // Combined collections may have a value at the path of a table
+        let key = |s: &str| -> Key { s.split('.').map(String::from).collect() };
+        for keys in [["a", "a.c"], ["a.c", "a"]] {
+            let collection: IndexMap<Key, toml::Value> = keys
+                .iter()
+                .map(|k| (key(k), toml::Value::Integer(1)))
+                .collect();
+            assert!(unflatten(&collection, &HashSet::new()).is_err());
+        }
+        assert!(roundtrip_discrepancies(&value).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip_indexed_tables() {
+        // Tables keyed by indices are only rebuilt as arrays where the source had arrays
+        let value: toml::Value = r#"
+            [ports."0"]
+            name = "http"
+
+            [ports."1"]
+            name = "https"
+
+            [[servers]]
+            host = "a"
+        "#
+        .parse()
+        .expect("Could not parse toml");
+        let (collection, arrays) = flatten_value_arrays(value.clone());
+        assert_eq!(
+            vec![vec![String::from("servers")]],
+            arrays.into_iter().collect::<Vec<Key>>()
+        );
+        assert!(roundtrip_discrepancies(&value).unwrap().is_empty());
+
+        let rebuilt = unflatten(&collection, &HashSet::new()).unwrap();
+        assert!(rebuilt["ports"].is_table());
+        assert!(rebuilt["servers"].is_table());
+    }
+
+    #[test]
+    fn test_roundtrip_empty_table() {
+        let toml_str = r#"
+            key = 1
+
+            [empty]
+
+            [table]
+            key = 2
+            empty = {}
+        "#;
+        let value: toml::Value = toml_str.parse().expect("Could not parse toml");
+        assert_eq!(
+            vec![
+                vec![String::from("empty")],
+                vec![String::from("table"), String::from("empty")],
+            ],
+            roundtrip_discrepancies(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_value_str() {
+        assert_eq!(
+            toml::Value::Boolean(false),
+            parse_value_str("false").unwrap()
+        );
+        assert_eq!(toml::Value::Integer(1), parse_value_str("1").unwrap());
+        assert_eq!(toml::Value::Float(1.0), parse_value_str("1.0").unwrap());
+        assert_eq!(
+            toml::Value::String(String::from("a")),
+            parse_value_str("\"a\"").unwrap()
+        );
+        assert!(parse_value_str("a").is_err());
+        assert!(parse_value_str("1\nother = 2").is_err());
+    }
 }
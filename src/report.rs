@@ -0,0 +1,111 @@
+//! The entries of a diff, for rendering in the output formats
+//!
+
+use crate::key_handling::Key;
+
+/// Entries of a diff, grouped by how the two files differ, each group in display order
+pub struct DiffReport<'a> {
+    pub first_name: String,
+    pub second_name: String,
+    /// SHA-256 hashes of the files, if they are displayed
+    pub first_hash: Option<String>,
+    pub second_hash: Option<String>,
+    /// Maximum number of characters of the displayed values, see `truncate`
+    pub max_value_width: Option<usize>,
+    /// Entries only in the first file
+    pub first_only: Vec<(Key, &'a toml::Value)>,
+    /// Entries only in the second file
+    pub second_only: Vec<(Key, &'a toml::Value)>,
+    /// Entries in both files with unequal values
+    pub unequal: Vec<(Key, &'a toml::Value, &'a toml::Value)>,
+    /// Entries in both files with equal values, which may be written differently under the
+    /// comparison options
+    pub equal: Vec<(Key, &'a toml::Value, &'a toml::Value)>,
+}
+
+/// Marker at the end of values truncated by `truncate`
+pub const TRUNCATION_MARKER: &str = "…(truncated)";
+
+/// Cut a displayed value after the first `max_width` characters, followed by the truncation
+/// marker, if it is longer than that
+pub fn truncate(text: String, max_width: Option<usize>) -> String {
+    match max_width.and_then(|max_width| text.char_indices().nth(max_width)) {
+        Some((end, _)) => format!("{}{}", &text[..end], TRUNCATION_MARKER),
+        None => text,
+    }
+}
+
+impl DiffReport<'_> {
+    /// A value as it is displayed, truncated to the maximum width, if any
+    pub fn value_text(&self, value: &toml::Value) -> String {
+        truncate(value.to_string(), self.max_value_width)
+    }
+}
+
+/// How keys and values are written in the reports of the other modes than the diff, like the drift
+/// from baselines
+#[derive(Debug, Clone)]
+pub struct TextStyle {
+    pub color: bool,
+    /// Separator between the levels of the keys
+    pub key_separator: String,
+    /// Maximum number of characters of the values, see `truncate`
+    pub max_value_width: Option<usize>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            color: false,
+            key_separator: String::from("."),
+            max_value_width: None,
+        }
+    }
+}
+
+impl TextStyle {
+    /// A key with its levels joined by the key separator
+    pub fn key_text(&self, key: &[String]) -> String {
+        key.join(&self.key_separator)
+    }
+
+    /// A value as it is displayed, truncated to the maximum width, or `(missing)` if there is no
+    /// value
+    pub fn value_text(&self, value: Option<&toml::Value>) -> String {
+        value.map_or_else(
+            || String::from("(missing)"),
+            |value| truncate(value.to_string(), self.max_value_width),
+        )
+    }
+}
+
+/// Number of entries in each group of a diff
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiffStat {
+    /// Entries only in the first file
+    pub first_only: usize,
+    /// Entries only in the second file
+    pub second_only: usize,
+    /// Entries in both files with unequal values
+    pub changed: usize,
+    /// Entries in both files with equal values
+    pub equal: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("abcdef", truncate(String::from("abcdef"), None));
+        assert_eq!("abcdef", truncate(String::from("abcdef"), Some(6)));
+        assert_eq!("abc…(truncated)", truncate(String::from("abcdef"), Some(3)));
+        // Characters are not split at their bytes
+        assert_eq!(
+            "\"æø…(truncated)",
+            truncate(String::from("\"æøå\""), Some(3))
+        );
+        assert_eq!("…(truncated)", truncate(String::from("abc"), Some(0)));
+    }
+}
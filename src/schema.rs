@@ -0,0 +1,141 @@
+//! Checking toml collections against a schema
+//!
+//! A schema is an ordinary toml file with an example value for every allowed key. The types of
+//! the values in a collection are checked against the types of the schema values for the same
+//! keys. Keys not in the schema are unknown, and are reported separately from type mismatches.
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+
+use crate::key_handling::Key;
+use crate::value_handling;
+
+/// A key with a value of another type than in the schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeMismatch {
+    pub key: Key,
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+/// Result of checking a collection against a schema
+#[derive(Debug, Default, PartialEq)]
+pub struct SchemaReport {
+    /// Keys with values of another type than in the schema, sorted by key
+    pub mismatches: Vec<TypeMismatch>,
+    /// Keys that are not in the schema, sorted
+    pub unknown: Vec<Key>,
+}
+
+impl SchemaReport {
+    pub fn has_mismatches(&self) -> bool {
+        !self.mismatches.is_empty()
+    }
+}
+
+/// Check the collection against the schema
+///
+/// Types are compared by their toml variant, so e.g. an integer where the schema has a float is
+/// a mismatch. Type mismatches are only checked if `check_types` is set.
+pub fn check(
+    collection: &IndexMap<Key, toml::Value>,
+    schema: &IndexMap<Key, toml::Value>,
+    check_types: bool,
+) -> SchemaReport {
+    let mut report = SchemaReport::default();
+    for key in collection.keys().sorted() {
+        let value = match collection.get(key) {
+            Some(val) => val,
+            None => unreachable!(),
+        };
+        match schema.get(key) {
+            Some(schema_val) => {
+                let expected = value_handling::type_name(schema_val);
+                let found = value_handling::type_name(value);
+                if check_types && expected != found {
+                    report.mismatches.push(TypeMismatch {
+                        key: key.clone(),
+                        expected,
+                        found,
+                    });
+                }
+            }
+            None => report.unknown.push(key.clone()),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::parse;
+
+    fn collection(toml_str: &str) -> IndexMap<Key, toml::Value> {
+        parse::flatten_value(toml_str.parse().expect("Could not parse toml"))
+    }
+
+    #[test]
+    fn test_check_conforming() {
+        let schema = collection(
+            r#"
+            name = ""
+            port = 0
+            [database]
+            ratio = 0.5
+        "#,
+        );
+        let input = collection(
+            r#"
+            name = "service"
+            port = 8080
+            [database]
+            ratio = 1.5
+        "#,
+        );
+        assert_eq!(SchemaReport::default(), check(&input, &schema, true));
+    }
+
+    #[test]
+    fn test_check_mismatched() {
+        let schema = collection(
+            r#"
+            name = ""
+            port = 0
+            [database]
+            ratio = 0.5
+        "#,
+        );
+        let input = collection(
+            r#"
+            name = 1
+            port = 8080
+            extra = true
+            [database]
+            ratio = 1
+        "#,
+        );
+        let report = check(&input, &schema, true);
+        assert_eq!(
+            vec![
+                TypeMismatch {
+                    key: vec![String::from("database"), String::from("ratio")],
+                    expected: "Float",
+                    found: "Integer",
+                },
+                TypeMismatch {
+                    key: vec![String::from("name")],
+                    expected: "String",
+                    found: "Integer",
+                },
+            ],
+            report.mismatches
+        );
+        assert_eq!(vec![vec![String::from("extra")]], report.unknown);
+
+        let report = check(&input, &schema, false);
+        assert!(report.mismatches.is_empty());
+        assert_eq!(vec![vec![String::from("extra")]], report.unknown);
+    }
+}
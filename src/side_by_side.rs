@@ -0,0 +1,148 @@
+//! Rendering of the diff in two columns
+//!
+//! Every entry is a row with the dotted key, the value in the first file, and the value in the
+//! second file, where the value of a file without the key is left blank. The columns are aligned
+//! to fit a total width, and longer keys and values are truncated with an ellipsis.
+
+use colored::Colorize;
+use itertools::Itertools;
+
+use crate::report::DiffReport;
+
+/// Separator between the columns
+const SEPARATOR: &str = " | ";
+
+/// The text cut to at most `width` characters, where a cut text ends with an ellipsis
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return String::from(text);
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// The text truncated or padded with spaces to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let truncated = truncate(text, width);
+    let padding = width - truncated.chars().count();
+    format!("{}{}", truncated, " ".repeat(padding))
+}
+
+/// Render the entries only in one of the files, and with unequal values, as rows of at most
+/// `width` characters, with the first values in blue and the second values in yellow if color is
+/// set
+pub fn render(report: &DiffReport, width: usize, color: bool) -> String {
+    let mut rows = Vec::<(String, String, String)>::new();
+    for (key, val) in report.first_only.iter() {
+        rows.push((key.iter().join("."), report.value_text(val), String::new()));
+    }
+    for (key, val) in report.second_only.iter() {
+        rows.push((key.iter().join("."), String::new(), report.value_text(val)));
+    }
+    for (key, first_val, second_val) in report.unequal.iter() {
+        rows.push((
+            key.iter().join("."),
+            report.value_text(first_val),
+            report.value_text(second_val),
+        ));
+    }
+
+    // The key column is as wide as the longest key, but at most a third of the width, and the
+    // value columns share the rest
+    let available = width.saturating_sub(2 * SEPARATOR.len());
+    let key_width = rows
+        .iter()
+        .map(|(key, _, _)| key.chars().count())
+        .chain(std::iter::once("key".len()))
+        .max()
+        .unwrap_or(0)
+        .min(available / 3);
+    let value_width = (available - key_width) / 2;
+
+    let row = |key: &str, first_val: String, second_val: String| {
+        let line = [fit(key, key_width), first_val, second_val].join(SEPARATOR);
+        format!("{}\n", line.trim_end())
+    };
+    let mut side_by_side = row(
+        "key",
+        fit(&report.first_name, value_width),
+        fit(&report.second_name, value_width),
+    );
+    side_by_side.push_str(&format!(
+        "{}\n",
+        "-".repeat(key_width + 2 * value_width + 2 * SEPARATOR.len())
+    ));
+    for (key, first_val, second_val) in rows.iter() {
+        let first_val = fit(first_val, value_width);
+        let second_val = String::from(fit(second_val, value_width).trim_end());
+        if color {
+            side_by_side.push_str(&row(
+                key,
+                first_val.blue().to_string(),
+                second_val.yellow().to_string(),
+            ));
+        } else {
+            side_by_side.push_str(&row(key, first_val, second_val));
+        }
+    }
+    side_by_side
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("short", truncate("short", 5));
+        assert_eq!("shor…", truncate("shorter", 5));
+        assert_eq!("", truncate("text", 0));
+    }
+
+    #[test]
+    fn test_render() {
+        let one = toml::Value::Integer(1);
+        let two = toml::Value::Integer(2);
+        let long = toml::Value::String("x".repeat(40));
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(vec![String::from("removed")], &one)],
+            second_only: vec![(vec![String::from("a"), String::from("added")], &two)],
+            unequal: vec![
+                (vec![String::from("count")], &one, &two),
+                (vec![String::from("name")], &long, &one),
+            ],
+            equal: vec![],
+        };
+        let rendered = render(&report, 50, false);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(
+            vec![
+                "key     | first.toml         | second.toml",
+                "-------------------------------------------------",
+                "removed | 1                  |",
+                "a.added |                    | 2",
+                "count   | 1                  | 2",
+                "name    | \"xxxxxxxxxxxxxxxx… | 1",
+            ],
+            lines
+        );
+
+        // The columns are aligned for every entry
+        for line in lines.iter().filter(|line| !line.starts_with('-')) {
+            let separators: Vec<usize> = line
+                .char_indices()
+                .filter(|(_, c)| *c == '|')
+                .map(|(pos, _)| line[..pos].chars().count())
+                .collect();
+            assert_eq!(vec![8, 29], separators, "{}", line);
+        }
+    }
+}
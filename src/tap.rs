@@ -0,0 +1,156 @@
+//! Rendering of the diff in the Test Anything Protocol (TAP) format
+//!
+//! Every entry of the diff is a test named by its key. Entries only in one of the files and
+//! entries with unequal values are failing tests, with the values as diagnostic comments, and
+//! entries with equal values are passing tests.
+
+use itertools::Itertools;
+
+use crate::report::DiffReport;
+
+/// Escape a test description, where `#` would start a directive
+fn description(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('#', "\\#")
+}
+
+/// A TAP test, with its diagnostic lines
+struct Test {
+    ok: bool,
+    name: String,
+    diagnostics: Vec<String>,
+}
+
+/// Render the report as a TAP version 13 document
+pub fn render(report: &DiffReport) -> String {
+    let mut tests = Vec::<Test>::new();
+    for (key, val) in report.first_only.iter() {
+        tests.push(Test {
+            ok: false,
+            name: format!("{} only in {}", key.iter().join("."), report.first_name),
+            diagnostics: vec![format!("< {}", report.value_text(val))],
+        });
+    }
+    for (key, val) in report.second_only.iter() {
+        tests.push(Test {
+            ok: false,
+            name: format!("{} only in {}", key.iter().join("."), report.second_name),
+            diagnostics: vec![format!("> {}", report.value_text(val))],
+        });
+    }
+    for (key, first_val, second_val) in report.unequal.iter() {
+        tests.push(Test {
+            ok: false,
+            name: format!("{} has unequal values", key.iter().join(".")),
+            diagnostics: vec![
+                format!("< {}", report.value_text(first_val)),
+                format!("> {}", report.value_text(second_val)),
+            ],
+        });
+    }
+    for (key, _, _) in report.equal.iter() {
+        tests.push(Test {
+            ok: true,
+            name: format!("{} has equal values", key.iter().join(".")),
+            diagnostics: vec![],
+        });
+    }
+
+    let mut tap = format!("TAP version 13\n1..{}\n", tests.len());
+    for (index, test) in tests.iter().enumerate() {
+        let status = if test.ok { "ok" } else { "not ok" };
+        tap.push_str(&format!(
+            "{} {} - {}\n",
+            status,
+            index + 1,
+            description(&test.name)
+        ));
+        for line in test.diagnostics.iter() {
+            tap.push_str(&format!("# {}\n", line));
+        }
+    }
+    let passed = tests.iter().filter(|test| test.ok).count();
+    tap.push_str(&format!(
+        "# {} failed, {} passed\n",
+        tests.len() - passed,
+        passed
+    ));
+    tap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the version line, the plan, and the numbering of the tests, and count the passing and
+    /// failing tests
+    fn parse_tap(tap: &str) -> Option<(usize, usize)> {
+        let mut lines = tap.lines();
+        if lines.next()? != "TAP version 13" {
+            return None;
+        }
+        let planned: usize = lines.next()?.strip_prefix("1..")?.parse().ok()?;
+        let (mut passed, mut failed) = (0, 0);
+        for line in lines.filter(|line| !line.starts_with('#')) {
+            let (rest, ok) = match line.strip_prefix("not ok ") {
+                Some(rest) => (rest, false),
+                None => (line.strip_prefix("ok ")?, true),
+            };
+            let (number, name) = rest.split_once(" - ")?;
+            if number.parse::<usize>().ok()? != passed + failed + 1 || name.contains(" #") {
+                return None;
+            }
+            if ok {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+        }
+        if passed + failed != planned {
+            return None;
+        }
+        Some((passed, failed))
+    }
+
+    #[test]
+    fn test_render() {
+        let one = toml::Value::Integer(1);
+        let two = toml::Value::Integer(2);
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(vec![String::from("removed")], &one)],
+            second_only: vec![(vec![String::from("a"), String::from("#added")], &two)],
+            unequal: vec![(vec![String::from("unequal")], &one, &two)],
+            equal: vec![
+                (vec![String::from("equal")], &one, &one),
+                (vec![String::from("other")], &two, &two),
+            ],
+        };
+        let tap = render(&report);
+
+        assert_eq!(Some((2, 3)), parse_tap(&tap));
+        assert!(tap.contains("not ok 1 - removed only in first.toml\n# < 1\n"));
+        assert!(tap.contains("not ok 2 - a.\\#added only in second.toml\n# > 2\n"));
+        assert!(tap.contains("not ok 3 - unequal has unequal values\n# < 1\n# > 2\n"));
+        assert!(tap.contains("ok 5 - other has equal values\n"));
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![],
+            second_only: vec![],
+            unequal: vec![],
+            equal: vec![],
+        };
+        assert_eq!(Some((0, 0)), parse_tap(&render(&report)));
+    }
+}
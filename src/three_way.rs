@@ -0,0 +1,127 @@
+//! Rendering of a three-way diff, where our file and their file are compared against a common base
+//!
+//! The keys changed only in our file, only in their file, and the same way in both are listed with
+//! their base and changed values, followed by the conflicts, which are changed to different values
+//! in the two files.
+
+use colored::Colorize;
+use indexmap::IndexMap;
+
+use crate::key_handling::{Key, ThreeWayOrigins};
+use crate::report::TextStyle;
+
+/// Render the changes of a three-way diff, where our file and their file are displayed with the
+/// given names
+pub fn render(
+    origins: &ThreeWayOrigins<Key>,
+    base: &IndexMap<Key, toml::Value>,
+    ours: &IndexMap<Key, toml::Value>,
+    theirs: &IndexMap<Key, toml::Value>,
+    our_name: &str,
+    their_name: &str,
+    style: &TextStyle,
+) -> String {
+    let mut lines = Vec::<String>::new();
+    let changes = [
+        (
+            format!("Changed only in {}", our_name),
+            origins.ours(),
+            ours,
+        ),
+        (
+            format!("Changed only in {}", their_name),
+            origins.theirs(),
+            theirs,
+        ),
+        (
+            String::from("Changed the same way in both"),
+            origins.both(),
+            ours,
+        ),
+    ];
+    for (heading, keys, changed) in changes.iter() {
+        if keys.is_empty() {
+            continue;
+        }
+        if style.color {
+            lines.push(format!("\n{}", heading.yellow()));
+        } else {
+            lines.push(format!("\n{}", heading));
+        }
+        for key in keys.iter() {
+            lines.push(format!(
+                "{}: {} -> {}",
+                style.key_text(key),
+                style.value_text(base.get(key)),
+                style.value_text(changed.get(key))
+            ));
+        }
+    }
+
+    let conflicts = origins.conflicts();
+    if !conflicts.is_empty() {
+        if style.color {
+            lines.push(format!("\n{}", "Conflicts".red().bold()));
+        } else {
+            lines.push(String::from("\nConflicts"));
+        }
+    }
+    for key in conflicts.iter() {
+        let output = style.key_text(key);
+        let (base_val, our_val, their_val) = (
+            style.value_text(base.get(key)),
+            style.value_text(ours.get(key)),
+            style.value_text(theirs.get(key)),
+        );
+        if style.color {
+            lines.push(format!("\n{}", output.red()));
+            lines.push(format!("{} {}", "=".bold(), base_val));
+            lines.push(format!("{} {}", "<".blue(), our_val));
+            lines.push(format!("{} {}", ">".yellow(), their_val));
+        } else {
+            lines.push(format!("\nConflicting values for key '{}'", output));
+            lines.push(format!("= {}", base_val));
+            lines.push(format!("< {}", our_val));
+            lines.push(format!("> {}", their_val));
+        }
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_handling;
+    use crate::value_handling::Comparison;
+
+    #[test]
+    fn test_render() {
+        let collection = |content: &str| -> IndexMap<Key, toml::Value> {
+            crate::parse::parse_toml_str(content).unwrap()
+        };
+        let base = collection("ours = 1\ntheirs = 1\nboth = 1\nconflict = 1\nsecret = 1");
+        let ours = collection("ours = 2\ntheirs = 1\nboth = 2\nconflict = 2\nsecret = 2");
+        let theirs = collection("ours = 1\ntheirs = 2\nboth = 2\nconflict = 3\nsecret = 1");
+        let mut origins =
+            key_handling::compare_three_way(&base, &ours, &theirs, &Comparison::default());
+        origins.retain(|key| *key != ["secret"]);
+
+        let rendered = render(
+            &origins,
+            &base,
+            &ours,
+            &theirs,
+            "ours.toml",
+            "theirs.toml",
+            &TextStyle::default(),
+        );
+        assert_eq!(
+            "\nChanged only in ours.toml\nours: 1 -> 2\n\
+             \nChanged only in theirs.toml\ntheirs: 1 -> 2\n\
+             \nChanged the same way in both\nboth: 1 -> 2\n\
+             \nConflicts\n\
+             \nConflicting values for key 'conflict'\n= 1\n< 2\n> 3\n",
+            rendered
+        );
+    }
+}
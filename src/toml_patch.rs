@@ -0,0 +1,143 @@
+//! Rendering of the diff as a toml patch document, for applying the changes with other tools
+//!
+//! The document has the tables `added` with the entries only in the second file, `removed` with
+//! the entries only in the first file, and `changed` with a table of the `old` and `new` value of
+//! each entry with unequal values. The entries are keyed by their dotted keys, written as in toml,
+//! so a level with dots or other characters that are not allowed in bare keys is quoted, like
+//! `servers."alpha.example".ip`.
+
+use anyhow::{anyhow, Error};
+
+use crate::key_handling::Key;
+use crate::report::DiffReport;
+
+/// Write a key as a dotted toml key, quoting the levels that can not be bare keys
+pub fn dotted_key(key: &Key) -> String {
+    key.iter()
+        .map(|level| {
+            let bare = !level.is_empty()
+                && level
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if bare {
+                level.clone()
+            } else {
+                toml::Value::String(level.clone()).to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// Split a dotted toml key, as written by `dotted_key`, into its levels
+pub fn split_dotted_key(dotted: &str) -> Result<Key, Error> {
+    let invalid = || anyhow!("Invalid dotted key: {}", dotted);
+    let mut value = format!("{} = 0", dotted)
+        .parse::<toml::Value>()
+        .map_err(|_| invalid())?;
+    let mut key = Key::new();
+    while let toml::Value::Table(table) = value {
+        let mut levels = table.into_iter();
+        let (level, inner) = levels.next().ok_or_else(invalid)?;
+        key.push(level);
+        value = inner;
+    }
+    Ok(key)
+}
+
+/// Render the report as a toml patch document
+pub fn render(report: &DiffReport) -> Result<String, Error> {
+    let entries = |entries: &[(Key, &toml::Value)]| {
+        toml::Value::Table(
+            entries
+                .iter()
+                .map(|(key, val)| (dotted_key(key), (*val).clone()))
+                .collect(),
+        )
+    };
+    let changed = report
+        .unequal
+        .iter()
+        .map(|(key, first_val, second_val)| {
+            let mut change = toml::value::Table::new();
+            change.insert(String::from("old"), (*first_val).clone());
+            change.insert(String::from("new"), (*second_val).clone());
+            (dotted_key(key), toml::Value::Table(change))
+        })
+        .collect();
+
+    let mut patch = toml::value::Table::new();
+    patch.insert(String::from("added"), entries(&report.second_only));
+    patch.insert(String::from("removed"), entries(&report.first_only));
+    patch.insert(String::from("changed"), toml::Value::Table(changed));
+    toml::to_string(&toml::Value::Table(patch)).map_err(|msg| anyhow!(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        s.split('/').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_dotted_key() {
+        for (levels, dotted) in [
+            ("db/port", "db.port"),
+            ("servers/alpha.example/ip", "servers.\"alpha.example\".ip"),
+            ("a b/c\"d", "\"a b\".\"c\\\"d\""),
+            ("items/0/id", "items.0.id"),
+        ] {
+            assert_eq!(dotted, dotted_key(&key(levels)));
+            assert_eq!(key(levels), split_dotted_key(dotted).unwrap());
+        }
+        assert!(split_dotted_key("a..b").is_err());
+    }
+
+    #[test]
+    fn test_render_round_trip() {
+        let port = toml::Value::Integer(80);
+        let new_port = toml::Value::Integer(8080);
+        let host = toml::Value::String(String::from("localhost"));
+        let ports = toml::Value::Array(vec![port.clone(), new_port.clone()]);
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(key("servers/alpha.example/ip"), &host)],
+            second_only: vec![(key("db/ports"), &ports), (key("name"), &host)],
+            unequal: vec![(key("db/port"), &port, &new_port)],
+            equal: vec![(key("db/host"), &host, &host)],
+        };
+        let rendered = render(&report).expect("Could not render the patch");
+        let patch: toml::Value = rendered.parse().expect("Could not parse the patch");
+
+        let entries = |section: &str| -> Vec<(Key, toml::Value)> {
+            patch[section]
+                .as_table()
+                .expect("Section is not a table")
+                .iter()
+                .map(|(dotted, val)| (split_dotted_key(dotted).unwrap(), val.clone()))
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                (key("db/ports"), ports.clone()),
+                (key("name"), host.clone())
+            ],
+            entries("added")
+        );
+        assert_eq!(
+            vec![(key("servers/alpha.example/ip"), host.clone())],
+            entries("removed")
+        );
+        let changed = entries("changed");
+        assert_eq!(1, changed.len());
+        assert_eq!(key("db/port"), changed[0].0);
+        assert_eq!(port, changed[0].1["old"]);
+        assert_eq!(new_port, changed[0].1["new"]);
+    }
+}
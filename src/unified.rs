@@ -0,0 +1,87 @@
+//! Rendering of the diff in the style of a unified diff
+//!
+//! Every entry is a line `key = value`, with the dotted key. Values only in the first file, and the
+//! first of unequal values, are `-` lines, values only in the second file, and the second of
+//! unequal values, are `+` lines, and equal values are context lines. The entries are sorted by
+//! their dotted keys.
+
+use colored::Colorize;
+use itertools::Itertools;
+
+use crate::report::DiffReport;
+
+/// Render the report as unified diff lines, with red `-` lines and green `+` lines if color is set
+pub fn render(report: &DiffReport, color: bool) -> String {
+    let mut entries = Vec::<(String, Vec<(char, String)>)>::new();
+    for (key, val) in report.first_only.iter() {
+        let key = key.iter().join(".");
+        entries.push((
+            key.clone(),
+            vec![('-', format!("{} = {}", key, report.value_text(val)))],
+        ));
+    }
+    for (key, val) in report.second_only.iter() {
+        let key = key.iter().join(".");
+        entries.push((
+            key.clone(),
+            vec![('+', format!("{} = {}", key, report.value_text(val)))],
+        ));
+    }
+    for (key, first_val, second_val) in report.unequal.iter() {
+        let key = key.iter().join(".");
+        let lines = vec![
+            ('-', format!("{} = {}", key, report.value_text(first_val))),
+            ('+', format!("{} = {}", key, report.value_text(second_val))),
+        ];
+        entries.push((key, lines));
+    }
+    for (key, val, _) in report.equal.iter() {
+        let key = key.iter().join(".");
+        entries.push((
+            key.clone(),
+            vec![(' ', format!("{} = {}", key, report.value_text(val)))],
+        ));
+    }
+    entries.sort_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key));
+
+    let mut unified = format!("--- {}\n+++ {}\n", report.first_name, report.second_name);
+    for (_, lines) in entries.iter() {
+        for (marker, text) in lines.iter() {
+            let line = format!("{}{}", marker, text);
+            let line = match (color, marker) {
+                (true, '-') => line.red().to_string(),
+                (true, '+') => line.green().to_string(),
+                _ => line,
+            };
+            unified.push_str(&line);
+            unified.push('\n');
+        }
+    }
+    unified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let one = toml::Value::Integer(1);
+        let two = toml::Value::Integer(2);
+        let report = DiffReport {
+            first_name: String::from("first.toml"),
+            second_name: String::from("second.toml"),
+            first_hash: None,
+            second_hash: None,
+            max_value_width: None,
+            first_only: vec![(vec![String::from("b")], &one)],
+            second_only: vec![(vec![String::from("a"), String::from("added")], &two)],
+            unequal: vec![(vec![String::from("c")], &one, &two)],
+            equal: vec![(vec![String::from("a"), String::from("same")], &one, &one)],
+        };
+        assert_eq!(
+            "--- first.toml\n+++ second.toml\n+a.added = 2\n a.same = 1\n-b = 1\n-c = 1\n+c = 2\n",
+            render(&report, false)
+        );
+    }
+}
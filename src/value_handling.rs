@@ -0,0 +1,733 @@
+//! Misc utility functions regarding value handling
+//!
+
+use anyhow::{anyhow, Error};
+use indexmap::IndexMap;
+use std::sync::Arc;
+
+use crate::key_handling::Key;
+
+/// Decides if two values of the same key are equal
+pub trait ValueComparator {
+    fn equal(&self, key: &[String], first: &toml::Value, second: &toml::Value) -> bool;
+}
+
+/// Compares values with their exact equality
+pub struct ExactComparator;
+
+impl ValueComparator for ExactComparator {
+    fn equal(&self, _key: &[String], first: &toml::Value, second: &toml::Value) -> bool {
+        first == second
+    }
+}
+
+/// Rewrite of the values of a collection before comparison, given the key of each value
+///
+/// The transform is shared, so it can be used by several threads comparing files at once.
+pub type ValueTransform = Arc<dyn Fn(&[String], toml::Value) -> toml::Value + Send + Sync>;
+
+/// Apply a transform to every value of a collection
+pub fn transform_values(
+    collection: IndexMap<Key, toml::Value>,
+    transform: &dyn Fn(&[String], toml::Value) -> toml::Value,
+) -> IndexMap<Key, toml::Value> {
+    collection
+        .into_iter()
+        .map(|(key, val)| {
+            let transformed = transform(&key, val);
+            (key, transformed)
+        })
+        .collect()
+}
+
+/// Names of the built-in transforms of string values
+pub const TRANSFORM_NAMES: &[&str] = &["lowercase", "trim", "sort-csv"];
+
+/// Rewrite a string value with a built-in transform. Other values are left as they are.
+fn builtin_transform(name: &str, value: toml::Value) -> toml::Value {
+    let text = match value {
+        toml::Value::String(text) => text,
+        other => return other,
+    };
+    let transformed = match name {
+        "lowercase" => text.to_lowercase(),
+        "trim" => String::from(text.trim()),
+        "sort-csv" => {
+            let mut items: Vec<&str> = text.split(',').map(str::trim).collect();
+            items.sort_unstable();
+            items.join(",")
+        }
+        _ => unreachable!(),
+    };
+    toml::Value::String(transformed)
+}
+
+/// Parse built-in transforms on the form "name=key1.key2", separated by commas, into a single
+/// transform applying each of them to the values of the given keys
+pub fn parse_transforms(specstr: &str) -> Result<ValueTransform, Error> {
+    let mut specs = Vec::<(String, Key)>::new();
+    for spec in specstr.split(',') {
+        match spec.split_once('=') {
+            Some((name, key)) if TRANSFORM_NAMES.contains(&name) && !key.is_empty() => {
+                specs.push((
+                    String::from(name),
+                    key.split('.').map(String::from).collect(),
+                ));
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Invalid transform '{}', expected 'name=key' with a name among {}",
+                    spec,
+                    TRANSFORM_NAMES.join(", ")
+                ))
+            }
+        }
+    }
+    Ok(Arc::new(move |key: &[String], value: toml::Value| {
+        specs
+            .iter()
+            .filter(|(_, spec_key)| spec_key == key)
+            .fold(value, |value, (name, _)| builtin_transform(name, value))
+    }))
+}
+
+/// Name of the variant of a toml value
+pub fn type_name(value: &toml::Value) -> &'static str {
+    match value {
+        toml::Value::String(_) => "String",
+        toml::Value::Integer(_) => "Integer",
+        toml::Value::Float(_) => "Float",
+        toml::Value::Boolean(_) => "Boolean",
+        toml::Value::Datetime(_) => "Datetime",
+        toml::Value::Array(_) => "Array",
+        toml::Value::Table(_) => "Table",
+    }
+}
+
+/// Check if a value is written as the given text, either as the content of a string, like `TODO`
+/// for `"TODO"`, or in toml, like `false` or `[1, 2]`
+pub fn written_as(value: &toml::Value, text: &str) -> bool {
+    match value {
+        toml::Value::String(content) if content == text => true,
+        other => other.to_string() == text,
+    }
+}
+
+/// Names of the variants of toml values, as given on the command line, which are the lowercase
+/// names of `type_name`
+pub const TYPE_NAMES: &[&str] = &[
+    "string", "integer", "float", "boolean", "datetime", "array", "table",
+];
+
+/// Label like `Integer -> String` for values of different types, or None for values of the same
+/// type
+pub fn type_change(first: &toml::Value, second: &toml::Value) -> Option<String> {
+    let (first_type, second_type) = (type_name(first), type_name(second));
+    if first_type == second_type {
+        return None;
+    }
+    Some(format!("{} -> {}", first_type, second_type))
+}
+
+/// Compares values according to the comparison options given on the command line
+///
+/// Arrays and tables are compared element by element, so the options apply at every level of
+/// nesting, and nested values are compared with the key of the outermost value. Values not
+/// affected by any of the options are compared exactly.
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    /// Let a local date equal a datetime at midnight on that date
+    pub lenient_datetime: bool,
+    /// Let offset datetimes denoting the same instant be equal
+    pub normalize_datetime: bool,
+    /// Let arrays with the same elements in any order be equal
+    pub unordered_arrays: bool,
+    /// Let floats with at most this absolute difference be equal
+    pub float_tolerance: Option<f64>,
+    /// Let an integer equal a float with the same numeric value
+    pub numeric_loose: bool,
+}
+
+impl ValueComparator for Comparison {
+    fn equal(&self, key: &[String], first: &toml::Value, second: &toml::Value) -> bool {
+        match (first, second) {
+            (toml::Value::Datetime(first_dt), toml::Value::Datetime(second_dt)) => {
+                (self.normalize_datetime && same_instant(first_dt, second_dt))
+                    || datetimes_equal(first_dt, second_dt, self.lenient_datetime)
+            }
+            (toml::Value::Float(first_fl), toml::Value::Float(second_fl)) => {
+                floats_equal(*first_fl, *second_fl, self.float_tolerance)
+            }
+            (toml::Value::Integer(_), toml::Value::Float(_))
+            | (toml::Value::Float(_), toml::Value::Integer(_))
+                if self.numeric_loose =>
+            {
+                numbers_equal(first, second, self.float_tolerance)
+            }
+            (toml::Value::Array(first_arr), toml::Value::Array(second_arr))
+                if self.unordered_arrays =>
+            {
+                multisets_equal(first_arr, second_arr, |first_el, second_el| {
+                    self.equal(key, first_el, second_el)
+                })
+            }
+            (toml::Value::Array(first_arr), toml::Value::Array(second_arr)) => {
+                first_arr.len() == second_arr.len()
+                    && first_arr
+                        .iter()
+                        .zip(second_arr.iter())
+                        .all(|(first_el, second_el)| self.equal(key, first_el, second_el))
+            }
+            (toml::Value::Table(first_table), toml::Value::Table(second_table)) => {
+                first_table.len() == second_table.len()
+                    && first_table.iter().all(|(name, first_val)| {
+                        second_table
+                            .get(name)
+                            .is_some_and(|second_val| self.equal(key, first_val, second_val))
+                    })
+            }
+            _ => ExactComparator.equal(key, first, second),
+        }
+    }
+}
+
+/// Compare two floats, which are equal if they differ by at most `tolerance`, or exactly equal if
+/// no tolerance is given
+pub fn floats_equal(first: f64, second: f64, tolerance: Option<f64>) -> bool {
+    match tolerance {
+        Some(tolerance) => first == second || (first - second).abs() <= tolerance,
+        None => first == second,
+    }
+}
+
+/// Compare two integers or floats by their numeric value, so an integer and a float can be equal,
+/// like `30` and `30.0`. Other values are never equal.
+pub fn numbers_equal(first: &toml::Value, second: &toml::Value, tolerance: Option<f64>) -> bool {
+    let as_f64 = |value: &toml::Value| match value {
+        toml::Value::Integer(int) => Some(*int as f64),
+        toml::Value::Float(fl) => Some(*fl),
+        _ => None,
+    };
+    match (as_f64(first), as_f64(second)) {
+        (Some(first_fl), Some(second_fl)) => floats_equal(first_fl, second_fl, tolerance),
+        _ => false,
+    }
+}
+
+/// Compare two datetimes, which may be offset datetimes, local datetimes, local dates, or local
+/// times
+///
+/// Datetimes of different kinds are never equal, except that a local date is equal to a local or
+/// offset datetime at midnight on the same date if `lenient` is set. The offsets are never
+/// filled in, so local datetimes and offset datetimes are always unequal.
+pub fn datetimes_equal(
+    first: &toml::value::Datetime,
+    second: &toml::value::Datetime,
+    lenient: bool,
+) -> bool {
+    if !lenient {
+        return first == second;
+    }
+    let midnight = || toml::value::Time {
+        hour: 0,
+        minute: 0,
+        second: 0,
+        nanosecond: 0,
+    };
+    let with_time = |dt: &toml::value::Datetime| match (&dt.date, &dt.time) {
+        (Some(_), None) => Some(midnight()),
+        (_, time) => time.clone(),
+    };
+    let date_only = |dt: &toml::value::Datetime| dt.date.is_some() && dt.time.is_none();
+    if date_only(first) || date_only(second) {
+        first.date == second.date && with_time(first) == with_time(second)
+    } else {
+        first == second
+    }
+}
+
+/// Seconds since the Unix epoch, and the nanoseconds within that second, of an offset datetime, or
+/// None for datetimes without an offset
+fn instant(datetime: &toml::value::Datetime) -> Option<(i64, u32)> {
+    let date = datetime.date.as_ref()?;
+    let time = datetime.time.as_ref()?;
+    let offset_minutes = match datetime.offset.as_ref()? {
+        toml::value::Offset::Z => 0,
+        toml::value::Offset::Custom { hours, minutes } => {
+            let minutes = i64::from(hours.unsigned_abs()) * 60 + i64::from(*minutes);
+            if *hours < 0 {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+    };
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar, counting years from March, so
+    // the leap day is the last day of the year
+    let (month, day) = (i64::from(date.month), i64::from(date.day));
+    let year = i64::from(date.year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400
+        + i64::from(time.hour) * 3_600
+        + i64::from(time.minute) * 60
+        + i64::from(time.second)
+        - offset_minutes * 60;
+    Some((seconds, time.nanosecond))
+}
+
+/// Check if two offset datetimes denote the same instant, like `1979-05-27T07:32:00Z` and
+/// `1979-05-27T00:32:00-07:00`
+///
+/// Datetimes without an offset do not denote an instant, so they are never the same instant as
+/// anything, and have to be compared with `datetimes_equal`.
+pub fn same_instant(first: &toml::value::Datetime, second: &toml::value::Datetime) -> bool {
+    match (instant(first), instant(second)) {
+        (Some(first_instant), Some(second_instant)) => first_instant == second_instant,
+        _ => false,
+    }
+}
+
+/// Check if two arrays have the same elements, with the same number of occurrences, in any order,
+/// where elements are compared with the given function
+pub fn multisets_equal<F>(first: &[toml::Value], second: &[toml::Value], equal: F) -> bool
+where
+    F: Fn(&toml::Value, &toml::Value) -> bool,
+{
+    if first.len() != second.len() {
+        return false;
+    }
+    let mut second_matched: Vec<bool> = vec![false; second.len()];
+    first.iter().all(|element| {
+        match (0..second.len()).find(|&j| !second_matched[j] && equal(element, &second[j])) {
+            Some(j) => {
+                second_matched[j] = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Absolute difference between two numeric values, or None if any of them is not a number
+pub fn numeric_delta(first: &toml::Value, second: &toml::Value) -> Option<f64> {
+    let as_float = |value: &toml::Value| match value {
+        toml::Value::Integer(val) => Some(*val as f64),
+        toml::Value::Float(val) => Some(*val),
+        _ => None,
+    };
+    Some((as_float(first)? - as_float(second)?).abs())
+}
+
+/// The kind of values involved in a change, ordered as they are displayed when grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChangeKind {
+    String,
+    /// Integers and floats, in any combination
+    Numeric,
+    Boolean,
+    Datetime,
+    Array,
+    Table,
+    /// Values of different kinds
+    TypeChange,
+}
+
+impl ChangeKind {
+    /// Classify the change from one value to another
+    pub fn of(first: &toml::Value, second: &toml::Value) -> Self {
+        match (first, second) {
+            (toml::Value::String(_), toml::Value::String(_)) => ChangeKind::String,
+            (toml::Value::Integer(_), toml::Value::Integer(_))
+            | (toml::Value::Integer(_), toml::Value::Float(_))
+            | (toml::Value::Float(_), toml::Value::Integer(_))
+            | (toml::Value::Float(_), toml::Value::Float(_)) => ChangeKind::Numeric,
+            (toml::Value::Boolean(_), toml::Value::Boolean(_)) => ChangeKind::Boolean,
+            (toml::Value::Datetime(_), toml::Value::Datetime(_)) => ChangeKind::Datetime,
+            (toml::Value::Array(_), toml::Value::Array(_)) => ChangeKind::Array,
+            (toml::Value::Table(_), toml::Value::Table(_)) => ChangeKind::Table,
+            _ => ChangeKind::TypeChange,
+        }
+    }
+
+    /// Heading used when displaying changes grouped by kind
+    pub fn heading(self) -> &'static str {
+        match self {
+            ChangeKind::String => "String changes",
+            ChangeKind::Numeric => "Numeric changes",
+            ChangeKind::Boolean => "Boolean changes",
+            ChangeKind::Datetime => "Datetime changes",
+            ChangeKind::Array => "Array changes",
+            ChangeKind::Table => "Table changes",
+            ChangeKind::TypeChange => "Type changes",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!("String", type_name(&toml::Value::String(String::from("a"))));
+        assert_eq!("Integer", type_name(&toml::Value::Integer(1)));
+        assert_eq!("Float", type_name(&toml::Value::Float(1.0)));
+        assert_eq!("Boolean", type_name(&toml::Value::Boolean(true)));
+        assert_eq!("Array", type_name(&toml::Value::Array(vec![])));
+    }
+
+    #[test]
+    fn test_written_as() {
+        let todo = toml::Value::String(String::from("TODO"));
+        assert!(written_as(&todo, "TODO"));
+        assert!(written_as(&todo, "\"TODO\""));
+        assert!(!written_as(&todo, "todo"));
+        assert!(written_as(&toml::Value::Boolean(false), "false"));
+        assert!(!written_as(&toml::Value::Boolean(false), "\"false\""));
+        let array = toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)]);
+        assert!(written_as(&array, "[1, 2]"));
+    }
+
+    #[test]
+    fn test_type_change() {
+        let one = toml::Value::Integer(1);
+        assert_eq!(None, type_change(&one, &toml::Value::Integer(2)));
+        assert_eq!(
+            Some(String::from("Integer -> String")),
+            type_change(&one, &toml::Value::String(String::from("1")))
+        );
+    }
+
+    fn datetime(s: &str) -> toml::value::Datetime {
+        s.parse().expect("Could not parse datetime")
+    }
+
+    #[test]
+    fn test_datetimes_equal() {
+        let offset = datetime("2020-01-01T00:00:00Z");
+        let offset_later = datetime("2020-01-01T07:32:00Z");
+        let local = datetime("2020-01-01T00:00:00");
+        let local_later = datetime("2020-01-01T07:32:00");
+        let date = datetime("2020-01-01");
+        let other_date = datetime("2020-01-02");
+        let time = datetime("00:00:00");
+
+        for lenient in [false, true].iter() {
+            assert!(datetimes_equal(&offset, &offset, *lenient));
+            assert!(datetimes_equal(&local, &local, *lenient));
+            assert!(datetimes_equal(&date, &date, *lenient));
+            assert!(datetimes_equal(&time, &time, *lenient));
+
+            assert!(!datetimes_equal(&offset, &local, *lenient));
+            assert!(!datetimes_equal(&offset, &time, *lenient));
+            assert!(!datetimes_equal(&local, &time, *lenient));
+            assert!(!datetimes_equal(&date, &time, *lenient));
+            assert!(!datetimes_equal(&date, &other_date, *lenient));
+            assert!(!datetimes_equal(&date, &local_later, *lenient));
+            assert!(!datetimes_equal(&date, &offset_later, *lenient));
+        }
+
+        assert!(!datetimes_equal(&date, &local, false));
+        assert!(!datetimes_equal(&offset, &date, false));
+        assert!(datetimes_equal(&date, &local, true));
+        assert!(datetimes_equal(&local, &date, true));
+        assert!(datetimes_equal(&offset, &date, true));
+        assert!(!datetimes_equal(&other_date, &local, true));
+    }
+
+    #[test]
+    fn test_instant() {
+        assert_eq!(Some((0, 0)), instant(&datetime("1970-01-01T00:00:00Z")));
+        assert_eq!(
+            Some((951_782_400, 500_000_000)),
+            instant(&datetime("2000-02-29T00:00:00.5Z"))
+        );
+        assert_eq!(
+            Some((-3_600, 0)),
+            instant(&datetime("1970-01-01T00:30:00+01:30"))
+        );
+        assert_eq!(None, instant(&datetime("1970-01-01T00:00:00")));
+        assert_eq!(None, instant(&datetime("1970-01-01")));
+    }
+
+    #[test]
+    fn test_same_instant() {
+        let utc = datetime("1979-05-27T07:32:00Z");
+        let pacific = datetime("1979-05-27T00:32:00-07:00");
+        let india = datetime("1979-05-27T13:02:00+05:30");
+        assert!(same_instant(&utc, &pacific));
+        assert!(same_instant(&pacific, &india));
+        assert!(!same_instant(&utc, &datetime("1979-05-27T07:32:00-07:00")));
+        assert!(!same_instant(&utc, &datetime("1979-05-27T07:32:00.5Z")));
+    }
+
+    #[test]
+    fn test_same_instant_without_offset() {
+        let local = datetime("1979-05-27T07:32:00");
+        let date = datetime("1979-05-27");
+        assert!(!same_instant(&local, &local));
+        assert!(!same_instant(&local, &datetime("1979-05-27T07:32:00Z")));
+        assert!(!same_instant(&date, &date));
+
+        // Without offsets, the comparison falls back to the textual form
+        let comparison = Comparison {
+            normalize_datetime: true,
+            ..Default::default()
+        };
+        let key = vec![String::from("key")];
+        let value = |s: &str| toml::Value::Datetime(datetime(s));
+        assert!(comparison.equal(
+            &key,
+            &value("1979-05-27T07:32:00"),
+            &value("1979-05-27T07:32:00")
+        ));
+        assert!(!comparison.equal(
+            &key,
+            &value("1979-05-27T07:32:00"),
+            &value("1979-05-27T00:32:00")
+        ));
+        assert!(comparison.equal(&key, &value("1979-05-27"), &value("1979-05-27")));
+        assert!(comparison.equal(
+            &key,
+            &value("1979-05-27T07:32:00Z"),
+            &value("1979-05-27T00:32:00-07:00")
+        ));
+        assert!(!Comparison::default().equal(
+            &key,
+            &value("1979-05-27T07:32:00Z"),
+            &value("1979-05-27T00:32:00-07:00")
+        ));
+    }
+
+    #[test]
+    fn test_comparison() {
+        let date = toml::Value::Datetime(datetime("2020-01-01"));
+        let local = toml::Value::Datetime(datetime("2020-01-01T00:00:00"));
+        let key = vec![String::from("key")];
+
+        assert!(!Comparison::default().equal(&key, &date, &local));
+        let comparison = Comparison {
+            lenient_datetime: true,
+            ..Default::default()
+        };
+        assert!(comparison.equal(&key, &date, &local));
+        assert!(!comparison.equal(&key, &date, &toml::Value::Integer(1)));
+
+        let ordered = toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)]);
+        let reversed = toml::Value::Array(vec![toml::Value::Integer(2), toml::Value::Integer(1)]);
+        assert!(!Comparison::default().equal(&key, &ordered, &reversed));
+        let comparison = Comparison {
+            unordered_arrays: true,
+            ..Default::default()
+        };
+        assert!(comparison.equal(&key, &ordered, &reversed));
+
+        let array = |values: &[i64]| {
+            toml::Value::Array(values.iter().map(|&v| toml::Value::Integer(v)).collect())
+        };
+        assert!(comparison.equal(&key, &array(&[1, 2, 3]), &array(&[3, 2, 1])));
+        assert!(!comparison.equal(&key, &array(&[1, 2]), &array(&[1, 2, 2])));
+        assert!(!Comparison::default().equal(&key, &array(&[1, 2, 3]), &array(&[3, 2, 1])));
+    }
+
+    #[test]
+    fn test_comparison_float_tolerance() {
+        let one = toml::Value::Float(1.0);
+        let close = toml::Value::Float(1.0000001);
+        let key = vec![String::from("key")];
+
+        assert!(!Comparison::default().equal(&key, &one, &close));
+        let comparison = Comparison {
+            float_tolerance: Some(1e-6),
+            ..Default::default()
+        };
+        assert!(comparison.equal(&key, &one, &close));
+        assert!(comparison.equal(
+            &key,
+            &toml::Value::Array(vec![one.clone()]),
+            &toml::Value::Array(vec![close])
+        ));
+        assert!(!comparison.equal(&key, &one, &toml::Value::Float(1.00001)));
+        assert!(!comparison.equal(&key, &one, &toml::Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_comparison_numeric_loose() {
+        let key = vec![String::from("timeout")];
+        let int = toml::Value::Integer(30);
+
+        assert!(!Comparison::default().equal(&key, &int, &toml::Value::Float(30.0)));
+        let comparison = Comparison {
+            numeric_loose: true,
+            ..Default::default()
+        };
+        assert!(comparison.equal(&key, &int, &toml::Value::Float(30.0)));
+        assert!(comparison.equal(&key, &toml::Value::Float(30.0), &int));
+        assert!(!comparison.equal(&key, &int, &toml::Value::Float(30.5)));
+        assert!(!comparison.equal(&key, &int, &toml::Value::String(String::from("30"))));
+        assert!(comparison.equal(
+            &key,
+            &toml::Value::Array(vec![int.clone()]),
+            &toml::Value::Array(vec![toml::Value::Float(30.0)])
+        ));
+    }
+
+    #[test]
+    fn test_comparison_nested() {
+        let value = |s: &str| -> toml::Value {
+            let document: toml::Value = s.parse().expect("Could not parse toml");
+            document.get("servers").cloned().expect("Missing servers")
+        };
+        let first = value(
+            r#"
+            [[servers]]
+            name = "a"
+            ports = [80, 443]
+            tls = { versions = ["1.2", "1.3"], since = 2020-01-01 }
+        "#,
+        );
+        let reordered = value(
+            r#"
+            [[servers]]
+            name = "a"
+            ports = [443, 80]
+            tls = { versions = ["1.3", "1.2"], since = 2020-01-01 }
+        "#,
+        );
+        let changed = value(
+            r#"
+            [[servers]]
+            name = "a"
+            ports = [443, 8080]
+            tls = { versions = ["1.3", "1.2"], since = 2020-01-01 }
+        "#,
+        );
+        let key = vec![String::from("servers")];
+
+        let ordered = Comparison::default();
+        assert!(ordered.equal(&key, &first, &first));
+        assert!(!ordered.equal(&key, &first, &reordered));
+
+        let unordered = Comparison {
+            unordered_arrays: true,
+            ..Default::default()
+        };
+        assert!(unordered.equal(&key, &first, &reordered));
+        assert!(!unordered.equal(&key, &first, &changed));
+
+        // The datetime option also applies to nested values
+        let date = value("servers = [{ since = 2020-01-01 }]");
+        let midnight = value("servers = [{ since = 2020-01-01T00:00:00 }]");
+        assert!(!ordered.equal(&key, &date, &midnight));
+        let lenient = Comparison {
+            lenient_datetime: true,
+            ..Default::default()
+        };
+        assert!(lenient.equal(&key, &date, &midnight));
+    }
+
+    #[test]
+    fn test_transform_values() {
+        let key = |k: &str| vec![String::from(k)];
+        let collection = |version: &str| -> IndexMap<Key, toml::Value> {
+            vec![
+                (key("version"), toml::Value::String(String::from(version))),
+                (key("count"), toml::Value::Integer(1)),
+            ]
+            .into_iter()
+            .collect()
+        };
+
+        // Compare only the major version
+        let major = |key: &[String], value: toml::Value| match (key, value) {
+            ([name], toml::Value::String(text)) if name == "version" => {
+                toml::Value::String(String::from(text.split('.').next().unwrap_or("")))
+            }
+            (_, value) => value,
+        };
+        assert_ne!(collection("1.2.0"), collection("1.3.1"));
+        assert_eq!(
+            transform_values(collection("1.2.0"), &major),
+            transform_values(collection("1.3.1"), &major)
+        );
+        assert_ne!(
+            transform_values(collection("1.2.0"), &major),
+            transform_values(collection("2.0.0"), &major)
+        );
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let string = |text: &str| toml::Value::String(String::from(text));
+        let key = |k: &str| k.split('.').map(String::from).collect::<Key>();
+        let transform = parse_transforms("lowercase=a.name,trim=a.name,sort-csv=tags").unwrap();
+
+        assert_eq!(string("web"), transform(&key("a.name"), string(" Web ")));
+        assert_eq!(string(" Web "), transform(&key("b.name"), string(" Web ")));
+        assert_eq!(string("a,b,c"), transform(&key("tags"), string("c, a,b")));
+        assert_eq!(
+            toml::Value::Integer(1),
+            transform(&key("tags"), toml::Value::Integer(1))
+        );
+
+        assert!(parse_transforms("uppercase=a").is_err());
+        assert!(parse_transforms("lowercase").is_err());
+        assert!(parse_transforms("lowercase=").is_err());
+    }
+
+    #[test]
+    fn test_multisets_equal() {
+        let one = toml::Value::Integer(1);
+        let two = toml::Value::Integer(2);
+        let exact = |first: &toml::Value, second: &toml::Value| first == second;
+        assert!(multisets_equal(&[], &[], exact));
+        assert!(multisets_equal(
+            &[one.clone(), two.clone(), one.clone()],
+            &[two.clone(), one.clone(), one.clone()],
+            exact
+        ));
+        assert!(!multisets_equal(
+            &[one.clone(), two.clone(), two.clone()],
+            &[two.clone(), one.clone(), one.clone()],
+            exact
+        ));
+        assert!(!multisets_equal(
+            &[one.clone(), one.clone()],
+            &[one, two],
+            exact
+        ));
+    }
+
+    #[test]
+    fn test_numeric_delta() {
+        let string = toml::Value::String(String::from("a"));
+        assert_eq!(
+            Some(2.0),
+            numeric_delta(&toml::Value::Integer(3), &toml::Value::Integer(1))
+        );
+        assert_eq!(
+            Some(0.5),
+            numeric_delta(&toml::Value::Integer(1), &toml::Value::Float(1.5))
+        );
+        assert_eq!(None, numeric_delta(&string, &toml::Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_change_kind() {
+        let string = toml::Value::String(String::from("a"));
+        let integer = toml::Value::Integer(1);
+        let float = toml::Value::Float(1.5);
+        assert_eq!(ChangeKind::String, ChangeKind::of(&string, &string));
+        assert_eq!(ChangeKind::Numeric, ChangeKind::of(&integer, &float));
+        assert_eq!(ChangeKind::Numeric, ChangeKind::of(&float, &float));
+        assert_eq!(ChangeKind::TypeChange, ChangeKind::of(&string, &integer));
+        assert!(ChangeKind::String < ChangeKind::Numeric);
+        assert!(ChangeKind::Table < ChangeKind::TypeChange);
+    }
+}
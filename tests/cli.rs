@@ -0,0 +1,749 @@
+//! Tests running the difftoml binary
+
+use std::process::Command;
+
+/// Run difftoml with the given arguments, and return the exit status and the output
+fn difftoml(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_difftoml"))
+        .args(args)
+        .output()
+        .expect("Could not run difftoml");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+    )
+}
+
+/// Run difftoml with the given arguments, and return the error output
+fn difftoml_stderr(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_difftoml"))
+        .args(args)
+        .output()
+        .expect("Could not run difftoml");
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_quiet_differing_files() {
+    let (status, stdout) = difftoml(&["-q", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert_eq!(Some(1), status);
+    assert_eq!("", stdout);
+}
+
+#[test]
+fn test_quiet_identical_files() {
+    let (status, stdout) = difftoml(&["--quiet", "assets/test_1.toml", "assets/test_1.toml"]);
+    assert_eq!(Some(0), status);
+    assert_eq!("", stdout);
+
+    // Files that only differ in excluded keys are identical
+    let (status, _) = difftoml(&[
+        "-q",
+        "-x",
+        "name,field0,field1,field3,int_value,integer_value",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert_eq!(Some(0), status);
+}
+
+#[test]
+fn test_quiet_directories() {
+    let (status, stdout) = difftoml(&["-q", "assets/dirs/first", "assets/dirs/second"]);
+    assert_eq!(Some(1), status);
+    assert_eq!("", stdout);
+}
+
+#[test]
+fn test_quiet_errors() {
+    let (status, _) = difftoml(&["-q", "assets/test_1.toml", "does_not_exist.toml"]);
+    assert_eq!(Some(2), status);
+}
+
+#[test]
+fn test_quiet_parse_errors() {
+    let path = std::env::temp_dir().join(format!("difftoml_quiet_{}.toml", std::process::id()));
+    std::fs::write(&path, "name = \"first\"\n[broken\n").expect("Could not write the file");
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+
+    let (status, stdout) = difftoml(&[
+        "-q",
+        "--ignore-parse-errors",
+        path_str,
+        "assets/test_1.toml",
+    ]);
+    let (error_status, error_stdout) = difftoml(&["-q", path_str, "assets/test_1.toml"]);
+    let stderr = difftoml_stderr(&[path_str, "assets/test_1.toml"]);
+    std::fs::remove_file(&path).expect("Could not remove the file");
+
+    assert_eq!(Some(3), status);
+    assert_eq!("", stdout);
+    assert_eq!(Some(2), error_status);
+    assert_eq!("", error_stdout);
+    assert!(stderr.contains(&format!("Could not parse {} as toml", path_str)));
+}
+
+#[test]
+fn test_changed_only() {
+    let (_, stdout) = difftoml(&["assets/test_1.toml", "assets/test_2.toml"]);
+    assert!(stdout.contains("Entries only found in"));
+
+    let (status, stdout) =
+        difftoml(&["--changed-only", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert_eq!(Some(1), status);
+    assert!(!stdout.contains("Entries only found in"));
+    assert!(stdout.contains("Unequal value for key"));
+    assert!(!stdout.contains("Equal value for key"));
+
+    let (_, stdout) = difftoml(&[
+        "--changed-only",
+        "-e",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert!(!stdout.contains("Entries only found in"));
+    assert!(stdout.contains("Equal value for key"));
+}
+
+#[test]
+fn test_context() {
+    let (_, stdout) = difftoml(&["assets/test_1.toml", "assets/test_2.toml"]);
+    assert!(!stdout.contains("(context)"));
+    let (_, stdout) = difftoml(&["--context", "0", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert!(!stdout.contains("(context)"));
+
+    let (status, stdout) =
+        difftoml(&["--context", "1", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert_eq!(Some(1), status);
+    assert!(stdout.contains("> \"second\"\n  version: \"0.1.0\" (context)\n"));
+    assert!(!stdout.contains("int_value: 123 (context)"));
+    assert!(stdout.contains("  field0.name: \"a\" (context)"));
+
+    // With colors, context lines are dimmed, whatever the status of their keys, so the changes
+    // stand out, while without colors they are told apart by their marker
+    let (_, stdout) = difftoml(&[
+        "--color",
+        "always",
+        "--context",
+        "1",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert!(stdout.contains("\u{1b}[2m  version: \"0.1.0\" (context)\u{1b}[0m\n"));
+    assert!(stdout.contains("\u{1b}[2m  field0.name: \"a\" (context)\u{1b}[0m\n"));
+
+    // A small file gets at least one sibling
+    let (_, expected) = difftoml(&["--context", "1", "assets/test_1.toml", "assets/test_2.toml"]);
+    let (_, stdout) = difftoml(&[
+        "--context-percent",
+        "5",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert_eq!(expected, stdout);
+}
+
+#[test]
+fn test_output_file() {
+    let path = std::env::temp_dir().join(format!("difftoml_output_{}.txt", std::process::id()));
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+
+    let (_, expected) = difftoml(&["-e", "assets/test_1.toml", "assets/test_2.toml"]);
+    let (status, stdout) = difftoml(&[
+        "-e",
+        "--output",
+        path_str,
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    let written = std::fs::read_to_string(&path).expect("Could not read the output file");
+    std::fs::remove_file(&path).expect("Could not remove the output file");
+
+    assert_eq!(Some(1), status);
+    assert_eq!("", stdout);
+    assert_eq!(expected, written);
+    // Colors are only written to files when forced
+    assert!(!written.contains('\u{1b}'));
+}
+
+#[test]
+fn test_paginate_not_terminal() {
+    // The output is captured rather than written to a terminal, so it is not paged
+    let (status, expected) = difftoml(&["assets/test_1.toml", "assets/test_2.toml"]);
+    let (paged_status, paged) =
+        difftoml(&["--paginate", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert_eq!(status, paged_status);
+    assert_eq!(expected, paged);
+
+    let (_, unpaged) = difftoml(&[
+        "--paginate",
+        "--no-paginate",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert_eq!(expected, unpaged);
+}
+
+#[test]
+fn test_threads() {
+    let root = std::env::temp_dir().join(format!("difftoml_threads_{}", std::process::id()));
+    let (first, second) = (root.join("first"), root.join("second"));
+    std::fs::create_dir_all(&first).expect("Could not create the directory");
+    std::fs::create_dir_all(&second).expect("Could not create the directory");
+    for index in 0..8 {
+        let name = format!("file{}.toml", index);
+        let first_content = format!("index = {}\nname = \"first\"\n", index);
+        std::fs::write(first.join(&name), first_content).expect("Could not write the file");
+        let second_content = format!("index = {}\nname = \"second\"\n", index);
+        std::fs::write(second.join(&name), second_content).expect("Could not write the file");
+    }
+    // One of the files can not be parsed, which does not stop the others
+    std::fs::write(second.join("file3.toml"), "[broken\n").expect("Could not write the file");
+    let (first_str, second_str) = (
+        first.to_str().expect("Temporary path is not UTF-8"),
+        second.to_str().expect("Temporary path is not UTF-8"),
+    );
+
+    let (status, sequential) = difftoml(&["--threads", "1", first_str, second_str]);
+    let (parallel_status, parallel) = difftoml(&["--threads", "4", first_str, second_str]);
+    let stderr = difftoml_stderr(&["--threads", "4", first_str, second_str]);
+    std::fs::remove_dir_all(&root).expect("Could not remove the directory");
+
+    assert_eq!(Some(2), status);
+    assert_eq!(Some(2), parallel_status);
+    // The diffs are in the order of the files, whatever order they are made in
+    assert_eq!(sequential, parallel);
+    let headers: Vec<&str> = parallel
+        .lines()
+        .filter(|line| line.starts_with("==="))
+        .collect();
+    let expected: Vec<String> = (0..8)
+        .map(|index| format!("=== file{}.toml ===", index))
+        .collect();
+    assert_eq!(expected, headers);
+    assert_eq!(7, parallel.matches("Unequal value for key 'name'").count());
+    assert!(stderr.contains("file3.toml"));
+    assert!(stderr.contains("1 of 8 files could not be diffed"));
+}
+
+#[test]
+fn test_output_file_directories() {
+    let path = std::env::temp_dir().join(format!("difftoml_dirs_{}.txt", std::process::id()));
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+
+    let (_, expected) = difftoml(&["assets/dirs/first", "assets/dirs/second"]);
+    let (status, stdout) = difftoml(&[
+        "--output",
+        path_str,
+        "assets/dirs/first",
+        "assets/dirs/second",
+    ]);
+    let written = std::fs::read_to_string(&path).expect("Could not read the output file");
+    std::fs::remove_file(&path).expect("Could not remove the output file");
+
+    assert_eq!(Some(1), status);
+    assert_eq!("", stdout);
+    assert_eq!(expected, written);
+    // The diffs of all the files are kept, not only the last one
+    assert!(written.contains("Files only found in"));
+    assert!(written.contains("=== logging.toml ==="));
+}
+
+#[test]
+fn test_output_file_modes() {
+    let path = std::env::temp_dir().join(format!("difftoml_modes_{}.txt", std::process::id()));
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+    let files = ["assets/test_1.toml", "assets/test_2.toml"];
+    let modes: [&[&str]; 4] = [
+        &["--audit-excluded", "-x", "field3", files[0], files[1]],
+        &["--explain-exclusions", "field3", files[0], files[1]],
+        &["--validate-roundtrip", files[0], files[1]],
+        &[
+            "--baselines",
+            "assets/baselines/b1.toml,assets/baselines/b2.toml",
+            "assets/baselines/target.toml",
+        ],
+    ];
+    for mode in modes.iter() {
+        let mut args = vec!["--output", path_str];
+        args.extend(mode.iter());
+        let (_, stdout) = difftoml(&args);
+        let written = std::fs::read_to_string(&path).expect("Could not read the output file");
+        std::fs::remove_file(&path).expect("Could not remove the output file");
+
+        // Everything is written to the output file, in every mode
+        assert_eq!("", stdout, "{:?}", mode);
+        assert!(!written.is_empty(), "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_structured_format_reports() {
+    let args = [
+        "--format",
+        "json",
+        "--audit-excluded",
+        "-x",
+        "field1",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ];
+    let (status, stdout) = difftoml(&args);
+    let stderr = difftoml_stderr(&args);
+
+    // The audit is written to stderr, so the output is only the JSON document
+    assert_eq!(Some(1), status);
+    assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_ok());
+    assert!(stderr.contains("2 excluded keys differ"));
+    assert!(!stdout.contains("excluded keys differ"));
+}
+
+#[test]
+#[cfg(feature = "hashes")]
+fn test_show_hashes() {
+    let files = ["assets/test_1.toml", "assets/test_2.toml"];
+    let (_, text) = difftoml(&["--show-hashes", files[0], files[1]]);
+    let (_, json) = difftoml(&["--show-hashes", "--format", "json", files[0], files[1]]);
+    let document: serde_json::Value =
+        serde_json::from_str(&json).expect("The output is not a JSON document");
+
+    let first_hash = document["first_hash"]
+        .as_str()
+        .expect("No hash of the first file");
+    let second_hash = document["second_hash"]
+        .as_str()
+        .expect("No hash of the second file");
+    assert_eq!(64, first_hash.len());
+    assert!(text.starts_with(&format!("first: {} second: {}\n", first_hash, second_hash)));
+}
+
+#[test]
+fn test_max_entries_section_order() {
+    let (_, stdout) = difftoml(&[
+        "--max-entries",
+        "4",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+
+    // The entries only in the first file are shown in full before the later sections
+    assert!(stdout.contains("int_value: 123\nfield1.name: \"b\"\nfield1.values"));
+    assert!(stdout.contains("only found in assets/test_2.toml\ninteger_value: 123\n\n"));
+    assert!(!stdout.contains("Unequal value"));
+    assert!(stdout.ends_with(
+        "4 entries omitted by --max-entries\n\
+         2 only found in assets/test_2.toml\n\
+         2 with unequal values\n"
+    ));
+}
+
+#[test]
+fn test_unused_exclude_warning() {
+    let args = [
+        "-x",
+        "field3,feild0",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ];
+    let stderr = difftoml_stderr(&args);
+    assert!(stderr.contains("Warning: exclude key 'feild0' matches no key in either file"));
+    assert!(!stderr.contains("'field3'"));
+
+    let quiet: Vec<&str> = std::iter::once("-q").chain(args.iter().cloned()).collect();
+    assert_eq!("", difftoml_stderr(&quiet));
+}
+
+#[test]
+fn test_ignore_parse_errors() {
+    let path = std::env::temp_dir().join(format!("difftoml_malformed_{}.toml", std::process::id()));
+    std::fs::write(&path, "name = \"first\"\n[broken\n").expect("Could not write the file");
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+
+    let (status, _) = difftoml(&["assets/test_1.toml", path_str]);
+    let (graceful_status, stdout) =
+        difftoml(&["--ignore-parse-errors", "assets/test_1.toml", path_str]);
+    let json_args = [
+        "--format",
+        "json",
+        "--ignore-parse-errors",
+        "assets/test_1.toml",
+        path_str,
+    ];
+    let (json_status, json_stdout) = difftoml(&json_args);
+    let json_stderr = difftoml_stderr(&json_args);
+    std::fs::remove_file(&path).expect("Could not remove the file");
+
+    assert_eq!(Some(2), status);
+    assert_eq!(Some(3), graceful_status);
+    assert!(stdout.contains(&format!("{} failed to parse: ", path_str)));
+    assert!(!stdout.contains("assets/test_1.toml failed to parse"));
+
+    // With a structured format the report is written to stderr, leaving no text on stdout
+    assert_eq!(Some(3), json_status);
+    assert!(
+        json_stdout.is_empty() || serde_json::from_str::<serde_json::Value>(&json_stdout).is_ok()
+    );
+    assert!(!json_stdout.contains("failed to parse"));
+    assert!(json_stderr.contains(&format!("{} failed to parse: ", path_str)));
+}
+
+#[test]
+fn test_key_separator() {
+    let (_, stdout) = difftoml(&["assets/test_1.toml", "assets/test_2.toml"]);
+    assert!(stdout.contains("field1.values: [1.23, 4.56, 7.89]"));
+
+    let (_, stdout) = difftoml(&[
+        "--key-separator",
+        "/",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert!(stdout.contains("field1/values: [1.23, 4.56, 7.89]"));
+    assert!(stdout.contains("Unequal value for key 'field0/values[0]'"));
+    assert!(!stdout.contains("field0.values"));
+
+    // The audit of the excluded keys uses the separator too
+    let (_, stdout) = difftoml(&[
+        "--key-separator",
+        "/",
+        "--audit-excluded",
+        "-x",
+        "field1",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert!(stdout.contains("field1/values: [1.23, 4.56, 7.89] -> (missing)"));
+}
+
+#[test]
+fn test_summary_line() {
+    let args = [
+        "--summary-line",
+        "--format",
+        "json",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ];
+    let (_, stdout) = difftoml(&args);
+    assert!(!stdout.contains("difftoml:"));
+    assert_eq!(
+        "difftoml: added=3 removed=3 changed=2 equal=5\n",
+        difftoml_stderr(&args)
+    );
+
+    let (_, stdout) = difftoml(&["assets/test_1.toml", "assets/test_2.toml"]);
+    assert!(!stdout.contains("difftoml:"));
+    assert_eq!(
+        "",
+        difftoml_stderr(&["assets/test_1.toml", "assets/test_2.toml"])
+    );
+}
+
+#[test]
+fn test_check() {
+    let (status, stdout) = difftoml(&["--check", "assets/test_1.toml", "assets/test_2.toml"]);
+    assert_eq!(Some(0), status);
+    assert_eq!("", stdout);
+
+    let path = std::env::temp_dir().join(format!("difftoml_check_{}.toml", std::process::id()));
+    std::fs::write(&path, "name = \"first\"\n[broken\n").expect("Could not write the file");
+    let path_str = path.to_str().expect("Temporary path is not UTF-8");
+    let (status, _) = difftoml(&["--check", "assets/test_1.toml", path_str]);
+    let stderr = difftoml_stderr(&["--check", "assets/test_1.toml", path_str]);
+    std::fs::remove_file(&path).expect("Could not remove the file");
+
+    assert_eq!(Some(2), status);
+    assert!(stderr.starts_with("Error: "));
+}
+
+#[test]
+fn test_ignore_key_case_merge() {
+    let dir = std::env::temp_dir();
+    let name = |file: &str| dir.join(format!("difftoml_case_{}_{}", std::process::id(), file));
+    let (first, second, merged) = (name("first.toml"), name("second.toml"), name("merged.toml"));
+    std::fs::write(&first, "[Server]\nPort = 80\n").expect("Could not write the file");
+    std::fs::write(&second, "[server]\nport = 80\n").expect("Could not write the file");
+    let path = |path: &std::path::PathBuf| {
+        path.to_str()
+            .expect("Temporary path is not UTF-8")
+            .to_string()
+    };
+
+    let (status, _) = difftoml(&[
+        "--ignore-key-case",
+        "--merge",
+        &path(&merged),
+        &path(&first),
+        &path(&second),
+    ]);
+    let content = std::fs::read_to_string(&merged).expect("Could not read the merge");
+    for file in [&first, &second, &merged] {
+        std::fs::remove_file(file).expect("Could not remove the file");
+    }
+
+    // The keys that only differ in case are merged into the keys of the first file
+    assert_eq!(Some(0), status);
+    let value: toml::Value = content.parse().expect("The merge is not toml");
+    assert_eq!(Some(80), value["Server"]["Port"].as_integer());
+    assert!(value.get("server").is_none());
+}
+
+#[test]
+fn test_defaults_after_transforms() {
+    let dir = std::env::temp_dir();
+    let name = |file: &str| dir.join(format!("difftoml_defaults_{}_{}", std::process::id(), file));
+    let files = [
+        name("first.toml"),
+        name("second.toml"),
+        name("defaults.toml"),
+    ];
+    let contents = [
+        "[Server]\nport = 80\nmode = \"FAST\"\n",
+        "[server]\nport = 80\n",
+        "[server]\nmode = \"fast\"\n",
+    ];
+    for (file, content) in files.iter().zip(contents.iter()) {
+        std::fs::write(file, content).expect("Could not write the file");
+    }
+    let paths: Vec<&str> = files
+        .iter()
+        .map(|file| file.to_str().expect("Temporary path is not UTF-8"))
+        .collect();
+
+    let (status, stdout) = difftoml(&[
+        "--ignore-key-case",
+        "--transform",
+        "lowercase=Server.mode",
+        "--defaults-file",
+        paths[2],
+        paths[0],
+        paths[1],
+    ]);
+    for file in files.iter() {
+        std::fs::remove_file(file).expect("Could not remove the file");
+    }
+
+    // The default matches the key of the first file and its value after the transform
+    assert_eq!(Some(0), status, "{}", stdout);
+}
+
+#[test]
+fn test_colored_one_sided_keys() {
+    let output = Command::new(env!("CARGO_BIN_EXE_difftoml"))
+        .args([
+            "--color",
+            "always",
+            "-i",
+            "int_value,integer_value",
+            "assets/test_1.toml",
+            "assets/test_2.toml",
+        ])
+        .env_remove("NO_COLOR")
+        .output()
+        .expect("Could not run difftoml");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "\u{1b}[34m\nassets/test_1.toml\u{1b}[0m\n\
+         \u{1b}[34mint_value\u{1b}[0m: 123\n\
+         \u{1b}[33m\nassets/test_2.toml\u{1b}[0m\n\
+         \u{1b}[33minteger_value\u{1b}[0m: 123\n",
+        stdout
+    );
+}
+
+/// Run difftoml on the test assets in the named temporary directory with the given config file,
+/// if any, and return the exit status and the output
+fn difftoml_with_config(name: &str, config: Option<&str>, args: &[&str]) -> (Option<i32>, String) {
+    let dir = std::env::temp_dir().join(format!("difftoml_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Could not create the directory");
+    if let Some(config) = config {
+        std::fs::write(dir.join(".difftoml.toml"), config).expect("Could not write the config");
+    }
+    let assets = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let output = Command::new(env!("CARGO_BIN_EXE_difftoml"))
+        .args(args)
+        .arg(assets.join("test_1.toml"))
+        .arg(assets.join("test_2.toml"))
+        .current_dir(&dir)
+        .env_remove("NO_COLOR")
+        .env_remove("DIFFTOML_EXCLUDE")
+        .output()
+        .expect("Could not run difftoml");
+    std::fs::remove_dir_all(&dir).expect("Could not remove the directory");
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+    )
+}
+
+#[test]
+fn test_config_file() {
+    let config = "exclude = [\"field0\"]\ncolor = true\ndisplay_equal = true\n";
+    let (status, stdout) = difftoml_with_config("config", Some(config), &[]);
+    assert_eq!(Some(1), status);
+    assert!(!stdout.contains("field0"));
+    assert!(stdout.contains('\u{1b}'));
+    // Equal values are only displayed with -e, or display_equal in the config
+    assert!(stdout.contains("field2"));
+}
+
+#[test]
+fn test_config_file_cli_only() {
+    let (status, stdout) = difftoml_with_config("config_cli_only", None, &["-x", "field0"]);
+    assert_eq!(Some(1), status);
+    assert!(!stdout.contains("field0"));
+    assert!(stdout.contains("Unequal value for key 'name'"));
+    assert!(!stdout.contains("field2"));
+    assert!(!stdout.contains('\u{1b}'));
+}
+
+#[test]
+fn test_config_file_overridden() {
+    let config = "exclude = [\"field0\"]\ncolor = true\n";
+    let (status, stdout) = difftoml_with_config(
+        "config_overridden",
+        Some(config),
+        &["-x", "name", "--color", "never"],
+    );
+    assert_eq!(Some(1), status);
+    assert!(stdout.contains("field0"));
+    assert!(!stdout.contains("Unequal value for key 'name'"));
+    assert!(!stdout.contains('\u{1b}'));
+}
+
+#[test]
+fn test_max_value_width() {
+    let dir = std::env::temp_dir().join(format!("difftoml_value_width_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Could not create the directory");
+    let first = dir.join("first.toml");
+    let second = dir.join("second.toml");
+    std::fs::write(&first, format!("blob = \"{}\"\n", "å".repeat(3000)))
+        .expect("Could not write the file");
+    std::fs::write(&second, format!("blob = \"{}\"\n", "b".repeat(3000)))
+        .expect("Could not write the file");
+
+    let (status, stdout) = difftoml(&[
+        "--max-value-width",
+        "20",
+        first.to_str().expect("Temporary path is not UTF-8"),
+        second.to_str().expect("Temporary path is not UTF-8"),
+    ]);
+    std::fs::remove_dir_all(&dir).expect("Could not remove the directory");
+
+    assert_eq!(Some(1), status);
+    assert_eq!(
+        format!(
+            "\nUnequal value for key 'blob'\n< \"{}…(truncated)\n> \"{}…(truncated)\n",
+            "å".repeat(19),
+            "b".repeat(19)
+        ),
+        stdout
+    );
+}
+
+#[test]
+fn test_labels() {
+    let (status, stdout) = difftoml(&[
+        "--first-label",
+        "old",
+        "--second-label",
+        "new",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert_eq!(Some(1), status);
+    assert!(stdout.contains("Entries only found in old\n"));
+    assert!(stdout.contains("Entries only found in new\n"));
+    assert!(!stdout.contains("assets/test_"));
+
+    let (_, stdout) = difftoml(&[
+        "--first-label",
+        "old",
+        "--stat",
+        "assets/test_1.toml",
+        "assets/test_2.toml",
+    ]);
+    assert!(stdout.contains("only in old"));
+    assert!(stdout.contains("only in assets/test_2.toml"));
+}
+
+#[test]
+fn test_array_algorithm_elements() {
+    let dir = std::env::temp_dir();
+    let first = dir.join(format!("difftoml_array_1_{}.toml", std::process::id()));
+    let second = dir.join(format!("difftoml_array_2_{}.toml", std::process::id()));
+    std::fs::write(&first, "a = [1, \"x\", 3.0]\n").expect("Could not write the file");
+    std::fs::write(&second, "a = [1, 2, 3.05]\n").expect("Could not write the file");
+    let first_str = first.to_str().expect("Temporary path is not UTF-8");
+    let second_str = second.to_str().expect("Temporary path is not UTF-8");
+
+    let (status, stdout) = difftoml(&[
+        "--show-lines",
+        "--float-tolerance",
+        "0.1",
+        "--array-algorithm",
+        "lcs",
+        first_str,
+        second_str,
+    ]);
+    std::fs::remove_file(&first).expect("Could not remove the file");
+    std::fs::remove_file(&second).expect("Could not remove the file");
+
+    // The floats are equal within the tolerance, so only the other element is reported
+    assert_eq!(Some(1), status);
+    assert!(stdout.contains("Removed array entry 'a[1]' (line 1)"));
+    assert!(stdout.contains("Added array entry 'a[1]' (line 1)"));
+    assert!(!stdout.contains("a[2]"));
+}
+
+#[test]
+fn test_exclude_env_var() {
+    let dir = std::env::temp_dir();
+    let first = dir.join(format!("difftoml_env_1_{}.toml", std::process::id()));
+    let second = dir.join(format!("difftoml_env_2_{}.toml", std::process::id()));
+    std::fs::write(&first, "name = \"first\"\n[db]\nhost = \"a\"\n").expect("Could not write");
+    std::fs::write(&second, "name = \"second\"\n[db]\nhost = \"b\"\n").expect("Could not write");
+    let run = |exclude: Option<&str>| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_difftoml"));
+        command
+            .args([&first, &second])
+            .env_remove("DIFFTOML_EXCLUDE");
+        if let Some(exclude) = exclude {
+            command.env("DIFFTOML_EXCLUDE", exclude);
+        }
+        let output = command.output().expect("Could not run difftoml");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = run(None);
+    let excluded_stdout = run(Some("db"));
+    std::fs::remove_file(&first).expect("Could not remove the file");
+    std::fs::remove_file(&second).expect("Could not remove the file");
+
+    assert!(stdout.contains("db.host"));
+    assert!(!excluded_stdout.contains("db.host"));
+    assert!(excluded_stdout.contains("Unequal value for key 'name'"));
+}
+
+#[test]
+fn test_edit() {
+    let edit = |editor: &str, files: [&str; 2]| {
+        Command::new(env!("CARGO_BIN_EXE_difftoml"))
+            .arg("--edit")
+            .args(files)
+            .env_remove("VISUAL")
+            .env("EDITOR", editor)
+            .output()
+            .expect("Could not run difftoml")
+            .status
+            .code()
+    };
+    let differing = ["assets/test_1.toml", "assets/test_2.toml"];
+    let identical = ["assets/test_1.toml", "assets/test_1.toml"];
+
+    // A failing editor makes the run fail, so it is only started when the files differ
+    assert_eq!(Some(1), edit("true", differing));
+    assert_eq!(Some(2), edit("false", differing));
+    assert_eq!(Some(0), edit("false", identical));
+}